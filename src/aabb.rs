@@ -0,0 +1,167 @@
+use crate::{interval::Interval, ray::Ray, vec3::Point3};
+
+#[derive(Clone, Copy)]
+pub struct Aabb {
+    pub x: Interval,
+    pub y: Interval,
+    pub z: Interval,
+}
+
+impl Aabb {
+    pub fn new() -> Aabb {
+        Aabb::EMPTY
+    }
+
+    pub fn with_values(x: Interval, y: Interval, z: Interval) -> Aabb {
+        let mut bbox = Aabb { x, y, z };
+        bbox.pad_to_minimums();
+        bbox
+    }
+
+    // Treat the two points a and b as extrema for the bounding box, so we don't require a
+    // particular minimum/maximum coordinate order.
+    pub fn from_points(a: Point3, b: Point3) -> Aabb {
+        let mut bbox = Aabb {
+            x: Interval::with_values(a[0].min(b[0]), a[0].max(b[0])),
+            y: Interval::with_values(a[1].min(b[1]), a[1].max(b[1])),
+            z: Interval::with_values(a[2].min(b[2]), a[2].max(b[2])),
+        };
+        bbox.pad_to_minimums();
+        bbox
+    }
+
+    pub fn surrounding(box0: &Aabb, box1: &Aabb) -> Aabb {
+        Aabb {
+            x: Interval::enclosing(&box0.x, &box1.x),
+            y: Interval::enclosing(&box0.y, &box1.y),
+            z: Interval::enclosing(&box0.z, &box1.z),
+        }
+    }
+
+    // Box shared by both boxes, empty when they don't overlap
+    pub fn overlap(box0: &Aabb, box1: &Aabb) -> Aabb {
+        let bbox = Aabb {
+            x: Interval::intersection(&box0.x, &box1.x),
+            y: Interval::intersection(&box0.y, &box1.y),
+            z: Interval::intersection(&box0.z, &box1.z),
+        };
+
+        if bbox.is_empty() {
+            return Aabb::EMPTY;
+        }
+
+        bbox
+    }
+
+    pub fn axis_interval(&self, n: usize) -> &Interval {
+        match n {
+            1 => &self.y,
+            2 => &self.z,
+            _ => &self.x,
+        }
+    }
+
+    // Adjust the box so that no side is narrower than some delta, padding if necessary. Flat
+    // primitives such as axis-aligned triangles would otherwise never pass the slab test.
+    fn pad_to_minimums(&mut self) {
+        let delta = 0.0001;
+        if self.x.size() < delta {
+            self.x = self.x.expand(delta);
+        }
+        if self.y.size() < delta {
+            self.y = self.y.expand(delta);
+        }
+        if self.z.size() < delta {
+            self.z = self.z.expand(delta);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.x.size() < 0.0 || self.y.size() < 0.0 || self.z.size() < 0.0
+    }
+
+    pub fn longest_axis(&self) -> usize {
+        if self.x.size() > self.y.size() {
+            if self.x.size() > self.z.size() {
+                0
+            } else {
+                2
+            }
+        } else if self.y.size() > self.z.size() {
+            1
+        } else {
+            2
+        }
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        if self.is_empty() {
+            return 0.0;
+        }
+
+        let dx = self.x.size();
+        let dy = self.y.size();
+        let dz = self.z.size();
+        2.0 * (dx * dy + dy * dz + dz * dx)
+    }
+
+    pub fn centroid(&self) -> Point3 {
+        Point3::with_values(
+            0.5 * (self.x.min + self.x.max),
+            0.5 * (self.y.min + self.y.max),
+            0.5 * (self.z.min + self.z.max),
+        )
+    }
+
+    pub fn hit(&self, r: &Ray, ray_t: Interval) -> bool {
+        self.clip(r, ray_t).is_some()
+    }
+
+    // Part of `ray_t` during which the ray is inside the box
+    pub fn clip(&self, r: &Ray, mut ray_t: Interval) -> Option<Interval> {
+        let ray_orig = r.origin();
+        let ray_dir = r.direction();
+
+        for axis in 0..3 {
+            let ax = self.axis_interval(axis);
+            let adinv = 1.0 / ray_dir[axis];
+
+            let t0 = (ax.min - ray_orig[axis]) * adinv;
+            let t1 = (ax.max - ray_orig[axis]) * adinv;
+
+            if t0 < t1 {
+                if t0 > ray_t.min {
+                    ray_t.min = t0;
+                }
+                if t1 < ray_t.max {
+                    ray_t.max = t1;
+                }
+            } else {
+                if t1 > ray_t.min {
+                    ray_t.min = t1;
+                }
+                if t0 < ray_t.max {
+                    ray_t.max = t0;
+                }
+            }
+
+            if ray_t.max <= ray_t.min {
+                return None;
+            }
+        }
+
+        Some(ray_t)
+    }
+
+    pub const EMPTY: Aabb = Aabb {
+        x: Interval::EMPTY,
+        y: Interval::EMPTY,
+        z: Interval::EMPTY,
+    };
+
+    pub const UNIVERSE: Aabb = Aabb {
+        x: Interval::UNIVERSE,
+        y: Interval::UNIVERSE,
+        z: Interval::UNIVERSE,
+    };
+}
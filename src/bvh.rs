@@ -0,0 +1,216 @@
+use std::cmp::Ordering;
+
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable},
+    hittable_list::HittableList,
+    interval::Interval,
+    ray::Ray,
+};
+
+// Relative cost of visiting a node versus intersecting a primitive, used by the surface area
+// heuristic to decide between splitting a node and keeping its objects in a leaf.
+const TRAVERSAL_COST: f64 = 0.125;
+const MAX_LEAF_SIZE: usize = 4;
+
+pub struct BvhNode {
+    left: Box<dyn Hittable>,
+    right: Box<dyn Hittable>,
+    bbox: Aabb,
+}
+
+impl BvhNode {
+    pub fn new(list: HittableList) -> BvhNode {
+        let mut objects: Vec<(Aabb, Box<dyn Hittable>)> = list
+            .list
+            .into_iter()
+            .map(|object| (object.bounding_box(), object))
+            .collect();
+
+        match objects.len() {
+            0 => {
+                BvhNode::with_children(Box::new(HittableList::new()), Box::new(HittableList::new()))
+            }
+            1 => BvhNode::with_children(objects.remove(0).1, Box::new(HittableList::new())),
+            _ => BvhNode::split(objects),
+        }
+    }
+
+    fn with_children(left: Box<dyn Hittable>, right: Box<dyn Hittable>) -> BvhNode {
+        let bbox = Aabb::surrounding(&left.bounding_box(), &right.bounding_box());
+        BvhNode { left, right, bbox }
+    }
+
+    // Builds the subtree for a set of objects, collapsing small sets into a plain list when the
+    // surface area heuristic says that is cheaper than splitting them further.
+    fn build(mut objects: Vec<(Aabb, Box<dyn Hittable>)>) -> Box<dyn Hittable> {
+        if objects.len() == 1 {
+            return objects.remove(0).1;
+        }
+
+        if objects.len() <= MAX_LEAF_SIZE {
+            let bbox = objects.iter().fold(Aabb::EMPTY, |bbox, (object_box, _)| {
+                Aabb::surrounding(&bbox, object_box)
+            });
+            let (axis, index, cost) = BvhNode::best_split(&mut objects, &bbox);
+            let leaf_cost = objects.len() as f64;
+
+            if cost >= leaf_cost {
+                let list = objects.into_iter().map(|(_, object)| object).collect();
+                return Box::new(HittableList::with_values(list));
+            }
+
+            return Box::new(BvhNode::split_at(objects, axis, index));
+        }
+
+        Box::new(BvhNode::split(objects))
+    }
+
+    fn split(mut objects: Vec<(Aabb, Box<dyn Hittable>)>) -> BvhNode {
+        let bbox = objects.iter().fold(Aabb::EMPTY, |bbox, (object_box, _)| {
+            Aabb::surrounding(&bbox, object_box)
+        });
+        let (axis, index, _) = BvhNode::best_split(&mut objects, &bbox);
+
+        BvhNode::split_at(objects, axis, index)
+    }
+
+    fn split_at(mut objects: Vec<(Aabb, Box<dyn Hittable>)>, axis: usize, index: usize) -> BvhNode {
+        BvhNode::sort_by_centroid(&mut objects, axis);
+        let right_objects = objects.split_off(index);
+
+        BvhNode::with_children(BvhNode::build(objects), BvhNode::build(right_objects))
+    }
+
+    // Sweeps every axis for the partition of the centroid-sorted objects with the lowest
+    // surface area heuristic cost. Returns the axis, the index of the first object in the right
+    // half, and the cost relative to intersecting a single primitive.
+    fn best_split(objects: &mut [(Aabb, Box<dyn Hittable>)], bbox: &Aabb) -> (usize, usize, f64) {
+        let n = objects.len();
+        let parent_area = bbox.surface_area();
+
+        let mut best = (bbox.longest_axis(), n / 2, f64::INFINITY);
+
+        if parent_area <= 0.0 || !parent_area.is_finite() {
+            return best;
+        }
+
+        let mut right_areas = vec![0.0; n];
+
+        for axis in 0..3 {
+            BvhNode::sort_by_centroid(objects, axis);
+
+            let mut right_box = Aabb::EMPTY;
+            for i in (1..n).rev() {
+                right_box = Aabb::surrounding(&right_box, &objects[i].0);
+                right_areas[i] = right_box.surface_area();
+            }
+
+            let mut left_box = Aabb::EMPTY;
+            for i in 1..n {
+                left_box = Aabb::surrounding(&left_box, &objects[i - 1].0);
+                let cost = TRAVERSAL_COST
+                    + (left_box.surface_area() * i as f64 + right_areas[i] * (n - i) as f64)
+                        / parent_area;
+
+                if cost < best.2 {
+                    best = (axis, i, cost);
+                }
+            }
+        }
+
+        best
+    }
+
+    fn sort_by_centroid(objects: &mut [(Aabb, Box<dyn Hittable>)], axis: usize) {
+        objects.sort_by(|a, b| {
+            a.0.centroid()[axis]
+                .partial_cmp(&b.0.centroid()[axis])
+                .unwrap_or(Ordering::Equal)
+        });
+    }
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        if !self.bbox.hit(r, ray_t) {
+            return false;
+        }
+
+        let hit_left = self.left.hit(r, ray_t, rec);
+        let right_t = Interval::with_values(ray_t.min, if hit_left { rec.t } else { ray_t.max });
+        let hit_right = self.right.hit(r, right_t, rec);
+
+        return hit_left || hit_right;
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{color::Color, material::Lambertian, sampler::Sampler, sphere::Sphere, vec3::Vec3};
+
+    // The same field of overlapping spheres, some of them moving, for every call with a seed
+    fn sphere_field(seed: u64) -> HittableList {
+        let mut sampler = Sampler::new(seed);
+        let mat = Arc::new(Lambertian::new(Color::with_values(0.5, 0.5, 0.5)));
+        let mut list = HittableList::new();
+
+        for i in 0..500 {
+            let center = Vec3::random_min_max(-10.0, 10.0, &mut sampler);
+            let radius = sampler.random_between(0.1, 1.0);
+            if i % 5 == 0 {
+                let center2 = center + Vec3::random_min_max(-1.0, 1.0, &mut sampler);
+                list.add(Box::new(Sphere::moving(
+                    center,
+                    center2,
+                    radius,
+                    mat.clone(),
+                )));
+            } else {
+                list.add(Box::new(Sphere::new(center, radius, mat.clone())));
+            }
+        }
+
+        list
+    }
+
+    #[test]
+    fn bvh_finds_the_same_hits_as_a_list() {
+        let list = sphere_field(11);
+        let bvh = BvhNode::new(sphere_field(11));
+        let mut sampler = Sampler::new(12);
+        let mut hits = 0;
+
+        for _ in 0..20_000 {
+            let origin = Vec3::random_min_max(-15.0, 15.0, &mut sampler);
+            let direction = Vec3::random_unit_vector(&mut sampler);
+            let r = Ray::with_time(origin, direction, sampler.random_f64());
+            let ray_t = Interval::with_values(0.001, f64::INFINITY);
+
+            let mut expected = HitRecord::new();
+            let mut found = HitRecord::new();
+            let list_hit = list.hit(&r, ray_t, &mut expected);
+            let bvh_hit = bvh.hit(&r, ray_t, &mut found);
+
+            assert_eq!(list_hit, bvh_hit);
+            if !list_hit {
+                continue;
+            }
+
+            hits += 1;
+            assert!((expected.t - found.t).abs() < 1e-12);
+            assert!((expected.normal - found.normal).near_zero());
+            assert_eq!(expected.front_face, found.front_face);
+        }
+
+        // Both hits and misses have to be covered
+        assert!(hits > 2_000 && hits < 18_000, "{} hits", hits);
+    }
+}
@@ -1,18 +1,43 @@
 use std::{
-    fs::File,
     io::{self, Write},
-    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread,
 };
 
 use crate::{
-    color::{write_color, Color},
+    color::Color,
     hittable::{HitRecord, Hittable},
+    hittable_list::HittableList,
+    image::Image,
     interval::Interval,
+    material::Material,
     ray::Ray,
-    utils::{degrees_to_radians, random_f64},
+    sampler::Sampler,
+    spectrum::{clip_to_gamut, in_spectrum, Wavelengths},
+    utils::degrees_to_radians,
     vec3::{Point3, Vec3},
 };
 
+// Largest number of pixels rendered, which keeps the buffer of summed samples within a few
+// gigabytes
+const MAX_PIXELS: usize = 100_000_000;
+
+struct Tile {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+}
+
+pub enum Background {
+    // Vertical white to blue gradient
+    Sky,
+    Solid(Color),
+}
+
 pub struct Camera {
     pub aspect_ratio: f64,
     pub image_width: i32,
@@ -24,6 +49,24 @@ pub struct Camera {
     pub vup: Vec3,
     pub defocus_angle: f64,
     pub focus_dist: f64,
+    pub background: Background,
+    // Times at which the shutter opens and closes, every ray gets a random time in between
+    pub shutter_open: f64,
+    pub shutter_close: f64,
+    // Sample the lights passed to `render` directly at every bounce and combine them with the
+    // scattered rays through multiple importance sampling
+    pub light_sampling: bool,
+    // Number of render threads, 0 uses every available core
+    pub threads: usize,
+    // Width and height in pixels of the square tiles handed out to render threads
+    pub tile_size: i32,
+    // Seed the per-pixel samplers are derived from, a fresh one is drawn for every render when
+    // missing
+    pub seed: Option<u64>,
+    // Report the remaining tiles on stderr while rendering
+    pub show_progress: bool,
+    // Trace sampled wavelengths instead of RGB, so that dispersive materials split white light
+    pub spectral: bool,
 
     image_height: i32,
     center: Point3,
@@ -62,6 +105,15 @@ impl Camera {
             w: Vec3::new(),
             defocus_angle: 10.0,
             focus_dist: 10.0,
+            background: Background::Sky,
+            shutter_open: 0.0,
+            shutter_close: 1.0,
+            light_sampling: true,
+            threads: 0,
+            tile_size: 32,
+            seed: None,
+            show_progress: true,
+            spectral: false,
             defocus_disk_u: Vec3::new(),
             defocus_disk_v: Vec3::new(),
         }
@@ -100,88 +152,460 @@ impl Camera {
         self.defocus_disk_v = defocus_radius * self.v;
     }
 
-    pub fn render(&mut self, world: &dyn Hittable) {
+    // Renders the world into an image of linear, high dynamic range colors
+    pub fn render(&mut self, world: &dyn Hittable, lights: &HittableList) -> Image {
         self.initialize();
 
-        let path = Path::new("output.ppm");
-        let display = path.display();
-        let mut file = match File::create(&path) {
-            Ok(file) => file,
-            Err(why) => panic!("Couldn't create file {} {}", display, why),
-        };
-
-        let image_width = self.image_width;
-        let image_height = self.image_height;
-        let header = format!("P3 \n{image_width} {image_height} \n255\n");
-        file.write_all(header.as_bytes())
-            .expect("Unable to write to file");
+        let pixels = self
+            .render_tiles(world, lights, self.seed.unwrap_or_else(rand::random))
+            .into_iter()
+            .map(|pixel_color| {
+                let pixel_color = self.pixel_samples_scale * pixel_color;
+                if self.spectral {
+                    clip_to_gamut(&pixel_color)
+                } else {
+                    pixel_color
+                }
+            })
+            .collect();
 
-        for j in 0..self.image_height {
-            eprint!("\rScanlines remaining: {}", self.image_height - j);
+        if self.show_progress {
+            eprint!("\r{}", " ".repeat(30));
+            eprint!("\rDone.\n");
             io::stderr().flush().unwrap();
-            for i in 0..self.image_width {
+        }
+
+        Image::from_pixels(
+            self.image_width as usize,
+            self.image_height as usize,
+            pixels,
+        )
+    }
+
+    // Checks the settings that would otherwise make `render` panic or produce garbage
+    pub fn validate(&self) -> Result<(), String> {
+        if self.image_width < 1 {
+            return Err(format!(
+                "image width must be at least 1, got {}",
+                self.image_width
+            ));
+        }
+        if !(self.aspect_ratio > 0.0 && self.aspect_ratio.is_finite()) {
+            return Err(format!(
+                "aspect ratio must be positive, got {}",
+                self.aspect_ratio
+            ));
+        }
+        // The height follows from the width and aspect ratio, and can be far larger than either
+        let image_height = (self.image_width as f64 / self.aspect_ratio)
+            .max(1.0)
+            .floor();
+        if image_height * self.image_width as f64 > MAX_PIXELS as f64 {
+            return Err(format!(
+                "image of {} by {} pixels is larger than the limit of {} pixels",
+                self.image_width, image_height, MAX_PIXELS
+            ));
+        }
+        if self.smaples_per_pixel < 1 {
+            return Err(format!(
+                "samples per pixel must be at least 1, got {}",
+                self.smaples_per_pixel
+            ));
+        }
+        if self.max_depth < 0 {
+            return Err(format!(
+                "max depth must not be negative, got {}",
+                self.max_depth
+            ));
+        }
+        if !(self.shutter_open.is_finite() && self.shutter_close.is_finite())
+            || self.shutter_close < self.shutter_open
+        {
+            return Err(format!(
+                "shutter must close after it opens, got {} to {}",
+                self.shutter_open, self.shutter_close
+            ));
+        }
+        if (self.lookfrom - self.lookat).near_zero() {
+            return Err("lookfrom and lookat must be different points".to_string());
+        }
+
+        Ok(())
+    }
+
+    // Splits the image into tiles that worker threads pick up one at a time. Finished tiles are
+    // sent back to this thread, which assembles them into a row-major buffer of summed samples
+    // and reports progress.
+    fn render_tiles(&self, world: &dyn Hittable, lights: &HittableList, seed: u64) -> Vec<Color> {
+        let tile_size = self.tile_size.max(1);
+        let tiles_x = (self.image_width + tile_size - 1) / tile_size;
+        let tiles_y = (self.image_height + tile_size - 1) / tile_size;
+        let tile_count = (tiles_x * tiles_y) as usize;
+
+        let threads = if self.threads > 0 {
+            self.threads
+        } else {
+            thread::available_parallelism().map_or(1, |n| n.get())
+        }
+        .min(tile_count);
+
+        let pixel_count = (self.image_width as usize)
+            .checked_mul(self.image_height as usize)
+            .filter(|count| *count <= MAX_PIXELS)
+            .expect("image size must be validated before rendering");
+        let mut pixels = vec![Color::new(); pixel_count];
+        let next_tile = AtomicUsize::new(0);
+        let (sender, receiver) = mpsc::channel::<(Tile, Vec<Color>)>();
+
+        thread::scope(|scope| {
+            for _ in 0..threads {
+                let sender = sender.clone();
+                let next_tile = &next_tile;
+                scope.spawn(move || loop {
+                    let index = next_tile.fetch_add(1, Ordering::Relaxed);
+                    if index >= tile_count {
+                        break;
+                    }
+
+                    let tile_x = (index as i32 % tiles_x) * tile_size;
+                    let tile_y = (index as i32 / tiles_x) * tile_size;
+                    let tile = Tile {
+                        x: tile_x,
+                        y: tile_y,
+                        width: tile_size.min(self.image_width - tile_x),
+                        height: tile_size.min(self.image_height - tile_y),
+                    };
+
+                    let tile_pixels = self.render_tile(&tile, world, lights, seed);
+                    if sender.send((tile, tile_pixels)).is_err() {
+                        break;
+                    }
+                });
+            }
+            drop(sender);
+
+            let mut tiles_done = 0;
+            for (tile, tile_pixels) in receiver {
+                for row in 0..tile.height {
+                    let start = ((tile.y + row) * self.image_width + tile.x) as usize;
+                    let tile_start = (row * tile.width) as usize;
+                    pixels[start..start + tile.width as usize].copy_from_slice(
+                        &tile_pixels[tile_start..tile_start + tile.width as usize],
+                    );
+                }
+
+                tiles_done += 1;
+                if self.show_progress {
+                    eprint!("\rTiles remaining: {}    ", tile_count - tiles_done);
+                    io::stderr().flush().unwrap();
+                }
+            }
+        });
+
+        pixels
+    }
+
+    fn render_tile(
+        &self,
+        tile: &Tile,
+        world: &dyn Hittable,
+        lights: &HittableList,
+        seed: u64,
+    ) -> Vec<Color> {
+        let mut tile_pixels = Vec::with_capacity((tile.width * tile.height) as usize);
+
+        for j in tile.y..tile.y + tile.height {
+            for i in tile.x..tile.x + tile.width {
+                let mut sampler = Sampler::for_pixel(seed, i, j);
                 let mut pixel_color = Color::new();
                 for _ in 0..self.smaples_per_pixel {
-                    let ray = self.get_ray(i, j);
-                    pixel_color += self.ray_color(ray, self.max_depth, world);
+                    let mut ray = self.get_ray(i, j, &mut sampler);
+                    if !self.spectral {
+                        pixel_color +=
+                            self.ray_color(&ray, self.max_depth, world, lights, None, &mut sampler);
+                        continue;
+                    }
+
+                    let wavelengths = Wavelengths::sample(sampler.random_f64());
+                    ray.set_wavelengths(Some(wavelengths));
+                    let samples =
+                        self.ray_color(&ray, self.max_depth, world, lights, None, &mut sampler);
+                    pixel_color += wavelengths.to_rgb(&samples);
                 }
-                write_color(&mut file, self.pixel_samples_scale * pixel_color)
-                    .expect("Unable to write color to file")
+                tile_pixels.push(pixel_color);
             }
         }
-        eprint!("\r{}", " ".repeat(30));
-        eprint!("\rDone.\n");
-        io::stderr().flush().unwrap();
+
+        tile_pixels
     }
 
-    fn get_ray(&self, i: i32, j: i32) -> Ray {
-        let offset = self.sample_square();
+    fn get_ray(&self, i: i32, j: i32, sampler: &mut Sampler) -> Ray {
+        let offset = self.sample_square(sampler);
         let pixel_sample = self.pixel00_loc
             + ((i as f64 + offset.x()) * self.pixel_delta_u)
             + ((j as f64 + offset.y()) * self.pixel_delta_v);
 
-        let ray_origin = if self.focus_dist > 0.0 {
-            self.defocus_disk_sample()
-        } else {
+        let ray_origin = if self.defocus_angle <= 0.0 {
             self.center
+        } else {
+            self.defocus_disk_sample(sampler)
         };
 
         let ray_direction = pixel_sample - ray_origin;
 
-        return Ray::with_values(ray_origin, ray_direction);
+        let ray_time =
+            self.shutter_open + sampler.random_f64() * (self.shutter_close - self.shutter_open);
+
+        return Ray::with_time(ray_origin, ray_direction, ray_time);
     }
 
-    fn defocus_disk_sample(&self) -> Vec3 {
-        let p = Vec3::random_in_unit_disk();
-        return self.center + (p * self.defocus_disk_u) + (p[1] * self.defocus_disk_v);
+    fn defocus_disk_sample(&self, sampler: &mut Sampler) -> Vec3 {
+        let p = Vec3::random_in_unit_disk(sampler);
+        return self.center + (p[0] * self.defocus_disk_u) + (p[1] * self.defocus_disk_v);
     }
 
-    fn sample_square(&self) -> Vec3 {
-        return Vec3::with_values(random_f64() - 0.5, random_f64() - 0.5, 0.0);
+    fn sample_square(&self, sampler: &mut Sampler) -> Vec3 {
+        return Vec3::with_values(sampler.random_f64() - 0.5, sampler.random_f64() - 0.5, 0.0);
     }
 
-    fn ray_color(&self, r: Ray, depth: i32, world: &dyn Hittable) -> Color {
+    // `scatter_pdf` is the density with which the previous bounce picked `r`, or `None` when it
+    // came from the camera or a specular bounce that light sampling could not have produced.
+    // For rays carrying wavelengths the result is the spectrum at those wavelengths.
+    fn ray_color(
+        &self,
+        r: &Ray,
+        depth: i32,
+        world: &dyn Hittable,
+        lights: &HittableList,
+        scatter_pdf: Option<f64>,
+        sampler: &mut Sampler,
+    ) -> Color {
         if depth < 0 {
             return Color::new();
         }
 
         let mut rec = HitRecord::new();
 
-        if world.hit(&r, Interval::with_values(0.001, f64::INFINITY), &mut rec) {
-            let mut scattered = Ray::new();
-            let mut attenuation = Color::new();
-            let mat = rec.mat.clone();
+        if !world.hit(r, Interval::with_values(0.001, f64::INFINITY), &mut rec) {
+            return in_spectrum(r, self.background_color(r));
+        }
+
+        let sample_lights = self.light_sampling && !lights.is_empty();
+        let mat = rec.mat.clone();
+
+        let emitted = in_spectrum(r, mat.emitted(r, &rec));
+        let mut color = match scatter_pdf {
+            Some(pdf) if sample_lights => {
+                let light_pdf = lights.pdf_value(&r.origin(), &r.direction());
+                power_heuristic(pdf, light_pdf) * emitted
+            }
+            _ => emitted,
+        };
+
+        if sample_lights {
+            color += self.sample_lights(r, &rec, world, lights, sampler);
+        }
+
+        let mut scattered = Ray::new();
+        let mut attenuation = Color::new();
 
-            if mat.scatter(&r, &mut rec, &mut attenuation, &mut scattered) {
-                return attenuation * self.ray_color(scattered, depth - 1, world);
+        if !mat.scatter_in_world(r, &rec, world, &mut attenuation, &mut scattered, sampler) {
+            return color;
+        }
+
+        let mut attenuation = material_spectrum(mat.as_ref(), r, attenuation);
+        match (r.wavelengths(), scattered.wavelengths()) {
+            // Only the hero wavelength went the scattered way, and it now stands for all three
+            (Some(wavelengths), Some(scattered_wavelengths))
+                if !wavelengths.secondary_terminated()
+                    && scattered_wavelengths.secondary_terminated() =>
+            {
+                attenuation = attenuation * Color::with_values(3.0, 0.0, 0.0);
             }
+            (wavelengths, None) => scattered.set_wavelengths(wavelengths),
+            _ => {}
+        }
+
+        let pdf = mat.scattering_pdf(r, &rec, &scattered.direction());
+        let next_pdf = if pdf > 0.0 { Some(pdf) } else { None };
 
+        return color
+            + attenuation
+                * self.ray_color(&scattered, depth - 1, world, lights, next_pdf, sampler);
+    }
+
+    // Next event estimation: pick a direction towards one of the lights and add the light it
+    // sees, weighted against the chance of the material having scattered that way itself.
+    fn sample_lights(
+        &self,
+        r: &Ray,
+        rec: &HitRecord,
+        world: &dyn Hittable,
+        lights: &HittableList,
+        sampler: &mut Sampler,
+    ) -> Color {
+        let direction = lights.random(&rec.p, sampler);
+        let light_pdf = lights.pdf_value(&rec.p, &direction);
+        if light_pdf <= 0.0 || !rec.is_consistent(&direction) {
+            return Color::new();
+        }
+
+        let f = material_spectrum(rec.mat.as_ref(), r, rec.mat.eval(r, rec, &direction));
+        if f.x() <= 0.0 && f.y() <= 0.0 && f.z() <= 0.0 {
             return Color::new();
         }
 
-        let unit_direction = r.direction().unit_vector();
-        let a = 0.5 * (unit_direction.y() + 1.0);
-        return (1.0 - a) * Color::with_values(1.0, 1.0, 1.0)
-            + a * Color::with_values(0.5, 0.7, 1.0);
+        let mut shadow_ray = Ray::with_time(rec.p, direction, r.time());
+        shadow_ray.set_wavelengths(r.wavelengths());
+        let mut light_rec = HitRecord::new();
+        if !world.hit(
+            &shadow_ray,
+            Interval::with_values(0.001, f64::INFINITY),
+            &mut light_rec,
+        ) {
+            return Color::new();
+        }
+
+        let emitted = in_spectrum(r, light_rec.mat.emitted(&shadow_ray, &light_rec));
+        let scatter_pdf = rec.mat.scattering_pdf(r, rec, &direction);
+
+        return power_heuristic(light_pdf, scatter_pdf) / light_pdf * f * emitted;
+    }
+
+    fn background_color(&self, r: &Ray) -> Color {
+        match self.background {
+            Background::Solid(color) => color,
+            Background::Sky => {
+                let unit_direction = r.direction().unit_vector();
+                let a = 0.5 * (unit_direction.y() + 1.0);
+                (1.0 - a) * Color::with_values(1.0, 1.0, 1.0)
+                    + a * Color::with_values(0.5, 0.7, 1.0)
+            }
+        }
+    }
+}
+
+// What `mat` scattered or evaluated for `r`, as a spectrum at the wavelengths of `r` when it
+// carries any
+fn material_spectrum(mat: &dyn Material, r: &Ray, color: Color) -> Color {
+    if mat.is_spectral(r) {
+        return color;
+    }
+
+    in_spectrum(r, color)
+}
+
+// Multiple importance sampling weight for a sample drawn with density `pdf` when the other
+// strategy would have produced it with density `other_pdf`.
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let a = pdf * pdf;
+    let b = other_pdf * other_pdf;
+    if a + b <= 0.0 {
+        return 0.0;
+    }
+
+    a / (a + b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_bounds_the_derived_image_height() {
+        let mut camera = Camera::new();
+        camera.image_width = 40_000;
+        camera.aspect_ratio = 0.0001;
+        assert!(camera.validate().is_err());
+
+        // Wide enough on its own
+        camera.image_width = 1_000_000_000;
+        camera.aspect_ratio = 1e12;
+        assert!(camera.validate().is_err());
+
+        camera.image_width = 10_000;
+        camera.aspect_ratio = 1.0;
+        assert!(camera.validate().is_ok());
+    }
+
+    // Bit patterns of every channel of a render of a small Cornell box
+    fn render_bits(seed: u64, threads: usize, tile_size: i32) -> Vec<u64> {
+        let mut scene = crate::scenes::cornell_box();
+        scene.camera.image_width = 8;
+        scene.camera.smaples_per_pixel = 4;
+        scene.camera.max_depth = 8;
+        scene.camera.seed = Some(seed);
+        scene.camera.threads = threads;
+        scene.camera.tile_size = tile_size;
+        scene.camera.show_progress = false;
+
+        let image = scene.camera.render(&scene.world, &scene.lights);
+        assert_eq!((image.width(), image.height()), (8, 8));
+        image
+            .pixels()
+            .iter()
+            .flat_map(|pixel| [pixel.x(), pixel.y(), pixel.z()])
+            .map(f64::to_bits)
+            .collect()
+    }
+
+    #[test]
+    fn seeded_renders_are_identical_across_thread_counts() {
+        let reference = render_bits(7, 1, 8);
+        assert_eq!(render_bits(7, 1, 8), reference);
+        assert_eq!(render_bits(7, 4, 3), reference);
+        assert_eq!(render_bits(7, 0, 1), reference);
+
+        assert_ne!(render_bits(8, 1, 8), reference);
+    }
+
+    // A sample or two per pixel leaves most spectral estimates outside the gamut
+    #[test]
+    fn spectral_renders_have_no_negative_channels() {
+        let mut scene = crate::scenes::cornell_box();
+        scene.camera.image_width = 8;
+        scene.camera.smaples_per_pixel = 2;
+        scene.camera.max_depth = 4;
+        scene.camera.seed = Some(3);
+        scene.camera.spectral = true;
+        scene.camera.show_progress = false;
+
+        let image = scene.camera.render(&scene.world, &scene.lights);
+        for pixel in image.pixels() {
+            assert!(
+                pixel.x() >= 0.0 && pixel.y() >= 0.0 && pixel.z() >= 0.0,
+                "{}",
+                pixel
+            );
+        }
+    }
+
+    #[test]
+    fn ray_times_fall_within_the_shutter_interval() {
+        let mut camera = Camera::new();
+        camera.image_width = 4;
+        camera.shutter_open = 0.25;
+        camera.shutter_close = 0.75;
+        camera.initialize();
+
+        let mut sampler = Sampler::new(4);
+        let (mut earliest, mut latest) = (f64::INFINITY, f64::NEG_INFINITY);
+        for _ in 0..10_000 {
+            let time = camera.get_ray(1, 2, &mut sampler).time();
+            assert!((0.25..=0.75).contains(&time), "{}", time);
+            earliest = earliest.min(time);
+            latest = latest.max(time);
+        }
+        // Spread over the whole interval
+        assert!(
+            earliest < 0.26 && latest > 0.74,
+            "{} to {}",
+            earliest,
+            latest
+        );
+
+        // A shutter that opens and closes at once freezes the motion
+        camera.shutter_close = 0.25;
+        assert_eq!(camera.get_ray(0, 0, &mut sampler).time(), 0.25);
     }
 }
@@ -0,0 +1,339 @@
+use std::path::{Path, PathBuf};
+
+use crate::{camera::Camera, image_writer::ImageFormat, scenes::SCENE_NAMES};
+
+pub const USAGE: &str = "\
+Usage: GraphicsProgramming [OPTIONS] [SCENE]
+
+Renders SCENE, either the name of a built-in scene or a TOML scene description file.
+
+Options:
+  -s, --scene <SCENE>      Scene to render, same as the positional argument [default: spheres]
+  -w, --width <PIXELS>     Image width in pixels
+  -a, --aspect <RATIO>     Aspect ratio as width:height or a decimal, e.g. 16:9 or 1.5
+  -n, --samples <N>        Samples per pixel
+  -d, --max-depth <N>      Maximum number of ray bounces
+  -o, --output <PATH>      Output image path [default: output.ppm]
+  -f, --format <FORMAT>    Output image format: ppm (ASCII), ppm-binary, pfm or png.
+                           Inferred from the output extension by default
+      --seed <N>           Seed for the random samples, for reproducible renders
+  -j, --threads <N>        Render threads, 0 uses every core [default: 0]
+      --spectral           Trace sampled wavelengths instead of RGB, for dispersion
+  -q, --quiet              Don't print progress
+  -v, --verbose            Print render settings and timing
+  -h, --help               Print this help
+
+Scene settings given on the command line override the ones of the scene.
+";
+
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+pub enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+pub struct Options {
+    pub scene: String,
+    pub width: Option<i32>,
+    pub aspect_ratio: Option<f64>,
+    pub samples_per_pixel: Option<i32>,
+    pub max_depth: Option<i32>,
+    pub output: PathBuf,
+    pub format: ImageFormat,
+    pub seed: Option<u64>,
+    pub threads: Option<usize>,
+    pub spectral: bool,
+    pub verbosity: Verbosity,
+}
+
+impl Options {
+    // Overrides the scene's camera settings with the ones given on the command line
+    pub fn apply_to(&self, camera: &mut Camera) {
+        if let Some(width) = self.width {
+            camera.image_width = width;
+        }
+        if let Some(aspect_ratio) = self.aspect_ratio {
+            camera.aspect_ratio = aspect_ratio;
+        }
+        if let Some(samples_per_pixel) = self.samples_per_pixel {
+            camera.smaples_per_pixel = samples_per_pixel;
+        }
+        if let Some(max_depth) = self.max_depth {
+            camera.max_depth = max_depth;
+        }
+        if let Some(threads) = self.threads {
+            camera.threads = threads;
+        }
+        if self.spectral {
+            camera.spectral = true;
+        }
+        camera.seed = self.seed;
+        camera.show_progress = self.verbosity > Verbosity::Quiet;
+    }
+}
+
+pub enum Command {
+    Render(Options),
+    Help,
+}
+
+fn parse_number<T: std::str::FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value '{}' for {}", value, option))
+}
+
+fn parse_aspect_ratio(value: &str) -> Result<f64, String> {
+    let ratio = match value.split_once(':') {
+        Some((width, height)) => {
+            let width: f64 = parse_number("--aspect", width)?;
+            let height: f64 = parse_number("--aspect", height)?;
+            width / height
+        }
+        None => parse_number("--aspect", value)?,
+    };
+
+    if !(ratio > 0.0 && ratio.is_finite()) {
+        return Err(format!("aspect ratio must be positive, got '{}'", value));
+    }
+
+    Ok(ratio)
+}
+
+fn format_from_extension(path: &Path) -> Result<ImageFormat, String> {
+    if path.extension().is_none() {
+        return Ok(ImageFormat::Ppm);
+    }
+
+    ImageFormat::from_extension(path).ok_or_else(|| {
+        format!(
+            "can't infer the image format of '{}', pass --format (one of {})",
+            path.display(),
+            ImageFormat::NAMES.join(", ")
+        )
+    })
+}
+
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut scene: Option<String> = None;
+    let mut width = None;
+    let mut aspect_ratio = None;
+    let mut samples_per_pixel = None;
+    let mut max_depth = None;
+    let mut output = PathBuf::from("output.ppm");
+    let mut format = None;
+    let mut seed = None;
+    let mut threads = None;
+    let mut spectral = false;
+    let mut verbosity = Verbosity::Normal;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        // Accept both `--option value` and `--option=value`
+        let (option, inline_value) = match arg.split_once('=') {
+            Some((option, value)) if option.starts_with("--") => {
+                (option.to_string(), Some(value.to_string()))
+            }
+            _ => (arg.clone(), None),
+        };
+
+        let mut value = || -> Result<String, String> {
+            match inline_value.clone().or_else(|| args.next()) {
+                Some(value) => Ok(value),
+                None => Err(format!("missing value for {}", option)),
+            }
+        };
+
+        match option.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-q" | "--quiet" => verbosity = Verbosity::Quiet,
+            "-v" | "--verbose" => verbosity = Verbosity::Verbose,
+            "-s" | "--scene" => scene = Some(value()?),
+            "-w" | "--width" => {
+                let pixels: i32 = parse_number(&option, &value()?)?;
+                if pixels < 1 {
+                    return Err(format!("width must be at least 1, got {}", pixels));
+                }
+                width = Some(pixels);
+            }
+            "-a" | "--aspect" => aspect_ratio = Some(parse_aspect_ratio(&value()?)?),
+            "-n" | "--samples" => {
+                let samples: i32 = parse_number(&option, &value()?)?;
+                if samples < 1 {
+                    return Err(format!("samples must be at least 1, got {}", samples));
+                }
+                samples_per_pixel = Some(samples);
+            }
+            "-d" | "--max-depth" => {
+                max_depth = Some(parse_number::<u16>(&option, &value()?)? as i32)
+            }
+            "-o" | "--output" => output = PathBuf::from(value()?),
+            "-f" | "--format" => {
+                let name = value()?;
+                format = Some(ImageFormat::from_name(&name).ok_or_else(|| {
+                    format!(
+                        "unknown format '{}', expected one of {}",
+                        name,
+                        ImageFormat::NAMES.join(", ")
+                    )
+                })?);
+            }
+            "--seed" => seed = Some(parse_number(&option, &value()?)?),
+            "-j" | "--threads" => threads = Some(parse_number(&option, &value()?)?),
+            "--spectral" => spectral = true,
+            _ if option.starts_with('-') && option.len() > 1 => {
+                return Err(format!("unknown option '{}'", option));
+            }
+            _ => {
+                if scene.is_some() {
+                    return Err(format!("unexpected argument '{}'", arg));
+                }
+                scene = Some(arg);
+            }
+        }
+    }
+
+    let format = match format {
+        Some(format) => format,
+        None => format_from_extension(&output)?,
+    };
+
+    Ok(Command::Render(Options {
+        scene: scene.unwrap_or_else(|| SCENE_NAMES[0].to_string()),
+        width,
+        aspect_ratio,
+        samples_per_pixel,
+        max_depth,
+        output,
+        format,
+        seed,
+        threads,
+        spectral,
+        verbosity,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Command, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn render_options(args: &[&str]) -> Options {
+        match parse(args) {
+            Ok(Command::Render(options)) => options,
+            Ok(Command::Help) => panic!("{:?} asks for help", args),
+            Err(error) => panic!("{:?} fails with {}", args, error),
+        }
+    }
+
+    fn error(args: &[&str]) -> String {
+        match parse(args) {
+            Err(error) => error,
+            Ok(_) => panic!("{:?} should be rejected", args),
+        }
+    }
+
+    #[test]
+    fn defaults_leave_the_scene_settings_alone() {
+        let options = render_options(&[]);
+        assert_eq!(options.scene, SCENE_NAMES[0]);
+        assert!(options.width.is_none() && options.samples_per_pixel.is_none());
+        assert!(options.threads.is_none() && options.seed.is_none());
+        assert_eq!(options.output, PathBuf::from("output.ppm"));
+        assert!(options.format == ImageFormat::Ppm);
+
+        let mut camera = Camera::new();
+        options.apply_to(&mut camera);
+        let defaults = Camera::new();
+        assert_eq!(camera.image_width, defaults.image_width);
+        assert_eq!(camera.smaples_per_pixel, defaults.smaples_per_pixel);
+        assert_eq!(camera.threads, defaults.threads);
+    }
+
+    #[test]
+    fn overrides_are_applied_to_the_camera() {
+        let options = render_options(&[
+            "cornell",
+            "-w",
+            "320",
+            "--aspect=16:9",
+            "--samples",
+            "64",
+            "-d",
+            "12",
+            "--seed=42",
+            "-j",
+            "3",
+            "--spectral",
+            "-q",
+            "-o",
+            "out.png",
+        ]);
+        assert_eq!(options.scene, "cornell");
+        assert!(options.format == ImageFormat::Png);
+
+        let mut camera = Camera::new();
+        options.apply_to(&mut camera);
+        assert_eq!(camera.image_width, 320);
+        assert_eq!(camera.aspect_ratio, 16.0 / 9.0);
+        assert_eq!(camera.smaples_per_pixel, 64);
+        assert_eq!(camera.max_depth, 12);
+        assert_eq!(camera.seed, Some(42));
+        assert_eq!(camera.threads, 3);
+        assert!(camera.spectral);
+        assert!(!camera.show_progress);
+
+        // Zero threads means every core
+        assert_eq!(render_options(&["--threads", "0"]).threads, Some(0));
+    }
+
+    #[test]
+    fn unknown_options_and_extra_arguments_are_rejected() {
+        assert_eq!(error(&["--fast"]), "unknown option '--fast'");
+        assert_eq!(error(&["-x", "cornell"]), "unknown option '-x'");
+        assert_eq!(
+            error(&["cornell", "spheres"]),
+            "unexpected argument 'spheres'"
+        );
+        assert!(error(&["--format", "gif"]).starts_with("unknown format 'gif'"));
+        assert!(error(&["-o", "out.gif"]).contains("pass --format"));
+        assert!(matches!(
+            parse(&["--width", "5", "--help"]),
+            Ok(Command::Help)
+        ));
+    }
+
+    #[test]
+    fn missing_values_are_rejected() {
+        assert_eq!(error(&["--width"]), "missing value for --width");
+        assert_eq!(error(&["cornell", "-n"]), "missing value for -n");
+        assert_eq!(error(&["--seed"]), "missing value for --seed");
+    }
+
+    #[test]
+    fn invalid_numbers_are_rejected() {
+        assert_eq!(
+            error(&["--width", "wide"]),
+            "invalid value 'wide' for --width"
+        );
+        assert_eq!(error(&["--width=0"]), "width must be at least 1, got 0");
+        assert_eq!(error(&["-w", "-5"]), "width must be at least 1, got -5");
+        assert_eq!(
+            error(&["--samples", "many"]),
+            "invalid value 'many' for --samples"
+        );
+        assert_eq!(error(&["-n", "0"]), "samples must be at least 1, got 0");
+        assert_eq!(
+            error(&["--threads", "two"]),
+            "invalid value 'two' for --threads"
+        );
+        assert_eq!(error(&["-j", "-1"]), "invalid value '-1' for -j");
+        assert_eq!(error(&["-d", "-1"]), "invalid value '-1' for -d");
+        assert!(error(&["--aspect", "16:0"]).starts_with("aspect ratio must be positive"));
+        assert!(error(&["--aspect", "wide"]).starts_with("invalid value 'wide'"));
+    }
+}
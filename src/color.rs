@@ -11,7 +11,14 @@ pub fn linear_to_gamma(linear_component: f64) -> f64 {
     return 0.0;
 }
 
-pub fn write_color<W: Write>(out: &mut W, pixel_color: Color) -> std::io::Result<()> {
+// Inverse of `linear_to_gamma`, for colors read from 8-bit images
+pub fn gamma_to_linear(gamma_component: f64) -> f64 {
+    gamma_component * gamma_component
+}
+
+// Gamma encodes a linear color and quantizes it to 8 bits per channel, clamping values outside
+// of the displayable range.
+pub fn color_to_bytes(pixel_color: Color) -> [u8; 3] {
     let mut r = pixel_color.x();
     let mut g = pixel_color.y();
     let mut b = pixel_color.z();
@@ -22,9 +29,15 @@ pub fn write_color<W: Write>(out: &mut W, pixel_color: Color) -> std::io::Result
 
     let intensity = Interval::with_values(0.000, 0.999);
 
-    let rbyte = (256.0 * intensity.clamp(r)) as i32;
-    let gbyte = (256.0 * intensity.clamp(g)) as i32;
-    let bbyte = (256.0 * intensity.clamp(b)) as i32;
+    let rbyte = (256.0 * intensity.clamp(r)) as u8;
+    let gbyte = (256.0 * intensity.clamp(g)) as u8;
+    let bbyte = (256.0 * intensity.clamp(b)) as u8;
+
+    [rbyte, gbyte, bbyte]
+}
+
+pub fn write_color<W: Write>(out: &mut W, pixel_color: Color) -> std::io::Result<()> {
+    let [rbyte, gbyte, bbyte] = color_to_bytes(pixel_color);
 
     writeln!(out, "{} {} {}", rbyte, gbyte, bbyte)
 }
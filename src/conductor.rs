@@ -0,0 +1,269 @@
+use crate::{
+    color::Color,
+    hittable::HitRecord,
+    material::Material,
+    microfacet::{fresnel_conductor, reflect, shading_frame, MicrofacetDistribution},
+    ray::Ray,
+    sampler::Sampler,
+    thin_film::ThinFilm,
+    vec3::Vec3,
+};
+
+// Complex indices of refraction (eta, k) at red, green and blue wavelengths
+const PRESETS: [(&str, [f64; 3], [f64; 3]); 4] = [
+    (
+        "gold",
+        [0.143119, 0.374957, 1.44248],
+        [3.98316, 2.38572, 1.60322],
+    ),
+    (
+        "silver",
+        [0.155265, 0.116723, 0.138342],
+        [4.82835, 3.12225, 2.14696],
+    ),
+    (
+        "copper",
+        [0.200438, 0.924033, 1.10221],
+        [3.91295, 2.45285, 2.14219],
+    ),
+    (
+        "aluminium",
+        [1.65746, 0.880369, 0.521229],
+        [9.22387, 6.26952, 4.837],
+    ),
+];
+
+// Rough metal with a microfacet distribution and the Fresnel reflectance of its complex index of
+// refraction
+pub struct Conductor {
+    eta: Color,
+    k: Color,
+    distribution: Box<dyn MicrofacetDistribution>,
+    // Coating that colors the reflection with interference
+    pub film: Option<ThinFilm>,
+}
+
+impl Conductor {
+    pub const PRESET_NAMES: [&str; 4] = ["gold", "silver", "copper", "aluminium"];
+
+    pub fn new(eta: Color, k: Color, distribution: Box<dyn MicrofacetDistribution>) -> Self {
+        Conductor {
+            eta,
+            k,
+            distribution,
+            film: None,
+        }
+    }
+
+    // Measured metal named in `PRESET_NAMES`
+    pub fn from_preset(name: &str, distribution: Box<dyn MicrofacetDistribution>) -> Option<Self> {
+        let (_, eta, k) = PRESETS.iter().find(|(preset, _, _)| *preset == name)?;
+        let color = |c: &[f64; 3]| Color::with_values(c[0], c[1], c[2]);
+
+        Some(Conductor::new(color(eta), color(k), distribution))
+    }
+
+    fn reflectance(&self, ray_in: &Ray, cos_theta: f64, rec: &HitRecord) -> Color {
+        match &self.film {
+            Some(film) => film.conductor_reflectance(ray_in, cos_theta, &self.eta, &self.k, rec),
+            None => fresnel_conductor(cos_theta, &self.eta, &self.k),
+        }
+    }
+
+    // Outgoing and incoming directions in the shading frame, when both are above the surface
+    fn local_directions(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        direction: &Vec3,
+    ) -> Option<(Vec3, Vec3)> {
+        let frame = shading_frame(rec);
+        let wo = frame.inverse_transform(&(-ray_in.direction().unit_vector()));
+        let wi = frame.inverse_transform(&direction.unit_vector());
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return None;
+        }
+
+        Some((wo, wi))
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+        sampler: &mut Sampler,
+    ) -> bool {
+        let frame = shading_frame(rec);
+        let wo = frame.inverse_transform(&(-ray_in.direction().unit_vector()));
+        if wo.z() <= 0.0 {
+            return false;
+        }
+
+        let wm = self
+            .distribution
+            .sample_visible(&wo, sampler.random_f64(), sampler.random_f64());
+        let wi = reflect(&wo, &wm);
+        if wi.z() <= 0.0 {
+            return false;
+        }
+
+        // BSDF times cosine over the density of visible normals
+        let shadowing = self.distribution.g(&wo, &wi) / self.distribution.g1(&wo);
+        *attenuation = shadowing * self.reflectance(ray_in, wo.dot(&wm), rec);
+        *scattered = Ray::with_time(rec.p, frame.transform(&wi), ray_in.time());
+
+        return true;
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        let (wo, wi) = match self.local_directions(ray_in, rec, direction) {
+            Some(directions) => directions,
+            None => return Color::new(),
+        };
+        let wm = (wo + wi).unit_vector();
+
+        self.distribution.d(&wm) * self.distribution.g(&wo, &wi) / (4.0 * wo.z())
+            * self.reflectance(ray_in, wo.dot(&wm), rec)
+    }
+
+    // The film's reflectance is computed at the ray's wavelengths
+    fn is_spectral(&self, ray_in: &Ray) -> bool {
+        self.film.is_some() && ray_in.wavelengths().is_some()
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        let (wo, wi) = match self.local_directions(ray_in, rec, direction) {
+            Some(directions) => directions,
+            None => return 0.0,
+        };
+        let wm = (wo + wi).unit_vector();
+
+        self.distribution.visible_d(&wo, &wm) / (4.0 * wo.dot(&wm))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        material::testing::directional_albedo,
+        microfacet::{Beckmann, Ggx},
+    };
+
+    // Ray arriving at `cos_theta` to a surface at the origin facing +z, whose u direction is
+    // `tangent`
+    fn incoming(cos_theta: f64, tangent: Vec3) -> (Ray, HitRecord) {
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let ray_in = Ray::with_values(
+            Vec3::with_values(sin_theta, 0.3 * sin_theta, cos_theta),
+            Vec3::with_values(-sin_theta, -0.3 * sin_theta, -cos_theta),
+        );
+        let mut rec = HitRecord::new();
+        rec.normal = Vec3::with_values(0.0, 0.0, 1.0);
+        rec.geometric_normal = rec.normal;
+        rec.front_face = true;
+        rec.dpdu = tangent;
+
+        (ray_in, rec)
+    }
+
+    #[test]
+    fn gold_reflects_like_gold_at_normal_incidence() {
+        let gold = Conductor::from_preset("gold", Box::new(Ggx::isotropic(0.1))).unwrap();
+        let reflectance = fresnel_conductor(1.0, &gold.eta, &gold.k);
+
+        // Measured reflectance of gold at about 650, 550 and 450 nm
+        let expected = [0.97, 0.80, 0.33];
+        for (channel, expected) in expected.into_iter().enumerate() {
+            assert!(
+                (reflectance[channel] - expected).abs() < 0.02,
+                "{}",
+                reflectance
+            );
+        }
+
+        assert!(Conductor::from_preset("brass", Box::new(Ggx::isotropic(0.1))).is_none());
+    }
+
+    // A conductor that absorbs almost nothing only loses light to single scattering
+    #[test]
+    fn white_furnace_never_gains_energy() {
+        let mut sampler = Sampler::new(11);
+
+        for alpha in [0.01, 0.3, 1.0] {
+            let mirror = Conductor::new(
+                Color::with_values(1.0, 1.0, 1.0),
+                Color::with_values(1e3, 1e3, 1e3),
+                Box::new(Ggx::isotropic(alpha)),
+            );
+
+            for cos_theta in [1.0, 0.5, 0.1, 0.01] {
+                let albedo = directional_albedo(&mirror, cos_theta, &mut sampler);
+                assert!(
+                    albedo.x() <= 1.0 && albedo.x() > 0.0,
+                    "alpha {} at cos {} reflects {}",
+                    alpha,
+                    cos_theta,
+                    albedo
+                );
+
+                // Smooth metal keeps almost all of it, short of grazing angles
+                if alpha < 0.05 && cos_theta >= 0.1 {
+                    assert!(albedo.x() > 0.99, "{}", albedo);
+                }
+                // With a unit roughness, seen head on, the integral works out to 1 - ln 2
+                if alpha == 1.0 && cos_theta == 1.0 {
+                    let expected = 1.0 - 2.0_f64.ln();
+                    assert!((albedo.x() - expected).abs() < 0.01, "{}", albedo);
+                }
+            }
+        }
+    }
+
+    // `scatter` weights each sample by the BSDF and cosine over the pdf, so it must equal what
+    // `eval` and `scattering_pdf` give for the same direction
+    #[test]
+    fn sample_weights_match_eval_over_pdf() {
+        let distributions: [Box<dyn MicrofacetDistribution>; 4] = [
+            Box::new(Ggx::isotropic(0.2)),
+            Box::new(Ggx::new(0.5, 0.05)),
+            Box::new(Beckmann::isotropic(0.2)),
+            Box::new(Beckmann::new(0.05, 0.4)),
+        ];
+        let mut sampler = Sampler::new(23);
+
+        for distribution in distributions {
+            let copper = Conductor::from_preset("copper", distribution).unwrap();
+            for cos_theta in [1.0, 0.6, 0.15] {
+                let (ray_in, rec) = incoming(cos_theta, Vec3::with_values(1.0, 1.0, 0.0));
+
+                for _ in 0..200 {
+                    let mut attenuation = Color::new();
+                    let mut scattered = Ray::new();
+                    if !copper.scatter(
+                        &ray_in,
+                        &rec,
+                        &mut attenuation,
+                        &mut scattered,
+                        &mut sampler,
+                    ) {
+                        continue;
+                    }
+
+                    let direction = scattered.direction();
+                    let pdf = copper.scattering_pdf(&ray_in, &rec, &direction);
+                    assert!(pdf > 0.0);
+                    let weight = copper.eval(&ray_in, &rec, &direction) / pdf;
+                    for channel in 0..3 {
+                        let relative = (weight[channel] / attenuation[channel] - 1.0).abs();
+                        assert!(relative < 1e-6, "{} != {}", weight, attenuation);
+                    }
+                }
+            }
+        }
+    }
+}
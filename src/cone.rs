@@ -0,0 +1,262 @@
+use std::{f64::consts::PI, sync::Arc};
+
+use crate::{
+    aabb::Aabb,
+    disk::disk_bounds,
+    hittable::{HitRecord, Hittable},
+    interval::Interval,
+    material::Material,
+    onb::Onb,
+    ray::Ray,
+    vec3::{Point3, Vec3},
+};
+
+// Closed cone with a circular base of the given radius around `base`, narrowing to `apex`.
+// Intersections happen in a local frame where the axis runs along +z from the base.
+pub struct Cone {
+    base: Point3,
+    height: f64,
+    radius: f64,
+    frame: Onb,
+    mat: Arc<dyn Material>,
+    bbox: Aabb,
+}
+
+impl Cone {
+    pub fn new(base: Point3, apex: Point3, radius: f64, mat: Arc<dyn Material>) -> Cone {
+        assert!(
+            !(apex - base).near_zero(),
+            "Cone base and apex must be different points"
+        );
+
+        let radius = f64::max(0.0, radius);
+        let frame = Onb::new(&(apex - base));
+        let bbox = Aabb::surrounding(
+            &disk_bounds(base, &frame.w(), radius),
+            &Aabb::from_points(apex, apex),
+        );
+
+        Cone {
+            base,
+            height: (apex - base).length(),
+            radius,
+            frame,
+            mat,
+            bbox,
+        }
+    }
+}
+
+impl Hittable for Cone {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let o = self.frame.inverse_transform(&(r.origin() - self.base));
+        let d = self.frame.inverse_transform(&r.direction());
+
+        let mut closest = ray_t.max;
+        let mut outward_normal = Vec3::new();
+
+        // Side: x^2 + y^2 = (k (height - z))^2, with k the radius shrinking per unit of height
+        let k = self.radius / self.height;
+        let k2 = k * k;
+        let oq = self.height - o.z();
+        let a = d.x() * d.x() + d.y() * d.y() - k2 * d.z() * d.z();
+        let h = o.x() * d.x() + o.y() * d.y() + k2 * oq * d.z();
+        let c = o.x() * o.x() + o.y() * o.y() - k2 * oq * oq;
+
+        let mut roots = [0.0; 2];
+        let root_count = if a.abs() < 1e-12 {
+            // Ray parallel to the slope, crossing the double cone once
+            if h == 0.0 {
+                0
+            } else {
+                roots[0] = -c / (2.0 * h);
+                1
+            }
+        } else {
+            let discriminant = h * h - a * c;
+            if discriminant < 0.0 {
+                0
+            } else {
+                let sqrtd = discriminant.sqrt();
+                let (t0, t1) = ((-h - sqrtd) / a, (-h + sqrtd) / a);
+                roots = [t0.min(t1), t0.max(t1)];
+                2
+            }
+        };
+
+        for &root in &roots[..root_count] {
+            let p = o + root * d;
+            if ray_t.min < root && root < closest && (0.0..=self.height).contains(&p.z()) {
+                closest = root;
+                let rho = (p.x() * p.x() + p.y() * p.y()).sqrt();
+                outward_normal = Vec3::with_values(p.x(), p.y(), k * rho);
+                outward_normal = if outward_normal.near_zero() {
+                    Vec3::with_values(0.0, 0.0, 1.0)
+                } else {
+                    outward_normal.unit_vector()
+                };
+                break;
+            }
+        }
+
+        // Base cap at z = 0, facing -z
+        if d.z() != 0.0 {
+            let root = -o.z() / d.z();
+            let p = o + root * d;
+            if ray_t.min < root
+                && root < closest
+                && p.x() * p.x() + p.y() * p.y() <= self.radius * self.radius
+            {
+                closest = root;
+                outward_normal = Vec3::with_values(0.0, 0.0, -1.0);
+            }
+        }
+
+        if closest >= ray_t.max {
+            return false;
+        }
+
+        // u: angle around the axis, v: height along the side or distance from the center of the
+        // base
+        let p = o + closest * d;
+        let phi = p.y().atan2(p.x());
+        rec.u = if phi < 0.0 { phi + 2.0 * PI } else { phi } / (2.0 * PI);
+        rec.v = if outward_normal.z() == -1.0 {
+            (p.x() * p.x() + p.y() * p.y()).sqrt() / self.radius
+        } else {
+            p.z() / self.height
+        };
+        let dpdv = if outward_normal.z() == -1.0 {
+            self.radius * Vec3::with_values(phi.cos(), phi.sin(), 0.0)
+        } else {
+            Vec3::with_values(
+                -self.radius * phi.cos(),
+                -self.radius * phi.sin(),
+                self.height,
+            )
+        };
+        rec.dpdu = self
+            .frame
+            .transform(&(2.0 * PI * Vec3::with_values(-p.y(), p.x(), 0.0)));
+        rec.dpdv = self.frame.transform(&dpdv);
+
+        rec.t = closest;
+        rec.p = r.at(closest);
+        rec.set_face_normal(r, &self.frame.transform(&outward_normal));
+        rec.mat = self.mat.clone();
+
+        return true;
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        color::Color,
+        hittable::testing::{assert_near, hit},
+        material::Lambertian,
+    };
+
+    // Cone with a base of radius 1 on the origin and its apex at z = 2
+    fn cone() -> Cone {
+        Cone::new(
+            Point3::with_values(0.0, 0.0, 0.0),
+            Point3::with_values(0.0, 0.0, 2.0),
+            1.0,
+            Arc::new(Lambertian::new(Color::with_values(0.5, 0.5, 0.5))),
+        )
+    }
+
+    #[test]
+    fn axis_aligned_ray_hits_the_side() {
+        let rec = hit(
+            &cone(),
+            Point3::with_values(5.0, 0.0, 1.0),
+            Vec3::with_values(-1.0, 0.0, 0.0),
+        )
+        .unwrap();
+
+        // Halfway up the radius has shrunk to 0.5, and the side slopes 1 in 2
+        assert!((rec.t - 4.5).abs() < 1e-9);
+        assert_near(rec.p, Point3::with_values(0.5, 0.0, 1.0));
+        assert_near(rec.normal, Vec3::with_values(2.0, 0.0, 1.0).unit_vector());
+        assert!(rec.front_face);
+        assert!((rec.u - 0.5).abs() < 1e-9);
+        assert!((rec.v - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn axis_aligned_ray_hits_the_base() {
+        let rec = hit(
+            &cone(),
+            Point3::with_values(0.0, -0.5, -5.0),
+            Vec3::with_values(0.0, 0.0, 1.0),
+        )
+        .unwrap();
+
+        assert!((rec.t - 5.0).abs() < 1e-9);
+        assert_near(rec.normal, Vec3::with_values(0.0, 0.0, -1.0));
+        assert!(rec.front_face);
+        assert!((rec.u - 0.75).abs() < 1e-9);
+        assert!((rec.v - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn ray_down_the_axis_hits_the_apex() {
+        let rec = hit(
+            &cone(),
+            Point3::with_values(0.0, 0.0, 5.0),
+            Vec3::with_values(0.0, 0.0, -1.0),
+        )
+        .unwrap();
+
+        // The side normal vanishes at the tip, which then faces along the axis
+        assert!((rec.t - 3.0).abs() < 1e-9);
+        assert_near(rec.normal, Vec3::with_values(0.0, 0.0, 1.0));
+        assert!(rec.front_face);
+        assert!((rec.v - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn mirrored_cone_above_the_apex_is_not_hit() {
+        // The side's equation also holds on the upside-down cone beyond the apex, which has
+        // radius 0.5 at z = 3
+        let above = hit(
+            &cone(),
+            Point3::with_values(5.0, 0.0, 3.0),
+            Vec3::with_values(-1.0, 0.0, 0.0),
+        );
+        assert!(above.is_none());
+
+        // Straight down from inside the mirrored cone, the apex is the first thing hit
+        let through = hit(
+            &cone(),
+            Point3::with_values(0.0, 0.0, 3.0),
+            Vec3::with_values(0.0, 0.0, -1.0),
+        )
+        .unwrap();
+        assert!((through.t - 1.0).abs() < 1e-9);
+        assert!(through.front_face);
+    }
+
+    #[test]
+    fn ray_parallel_to_the_slope_crosses_the_side_once() {
+        // Parallel to the side on -x, so only the side on +x is crossed
+        let rec = hit(
+            &cone(),
+            Point3::with_values(-0.2, 0.0, 0.5),
+            Vec3::with_values(1.0, 0.0, 2.0),
+        )
+        .unwrap();
+
+        assert!((rec.t - 0.475).abs() < 1e-9);
+        assert_near(rec.p, Point3::with_values(0.275, 0.0, 1.45));
+        assert_near(rec.normal, -Vec3::with_values(2.0, 0.0, 1.0).unit_vector());
+        assert!(!rec.front_face);
+    }
+}
@@ -0,0 +1,246 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable},
+    interval::Interval,
+    material::Material,
+    ray::Ray,
+    sampler::Sampler,
+    vec3::Vec3,
+};
+
+// Volume of constant density filling a convex boundary, such as smoke or fog. Rays passing
+// through it travel an exponentially distributed distance before scattering off a particle,
+// with the phase function given as the material.
+pub struct ConstantMedium {
+    boundary: Box<dyn Hittable>,
+    neg_inv_density: f64,
+    phase_function: Arc<dyn Material>,
+}
+
+impl ConstantMedium {
+    pub fn new(
+        boundary: Box<dyn Hittable>,
+        density: f64,
+        phase_function: Arc<dyn Material>,
+    ) -> ConstantMedium {
+        ConstantMedium {
+            boundary,
+            neg_inv_density: -1.0 / density,
+            phase_function,
+        }
+    }
+
+    // `hit` gets no sampler, so the free-flight distance is drawn from a stream seeded by the
+    // ray itself. This keeps seeded renders reproducible and gives the same answer every time
+    // the same ray is tested.
+    fn ray_sampler(r: &Ray) -> Sampler {
+        let origin = r.origin();
+        let direction = r.direction();
+        let bits = [
+            origin.x(),
+            origin.y(),
+            origin.z(),
+            direction.x(),
+            direction.y(),
+            direction.z(),
+            r.time(),
+        ];
+
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for value in bits {
+            hash = (hash ^ value.to_bits()).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+            hash ^= hash >> 29;
+        }
+
+        Sampler::new(hash)
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let mut rec1 = HitRecord::new();
+        let mut rec2 = HitRecord::new();
+
+        if !self.boundary.hit(r, Interval::UNIVERSE, &mut rec1) {
+            return false;
+        }
+
+        if !self.boundary.hit(
+            r,
+            Interval::with_values(rec1.t + 0.0001, f64::INFINITY),
+            &mut rec2,
+        ) {
+            return false;
+        }
+
+        rec1.t = rec1.t.max(ray_t.min);
+        rec2.t = rec2.t.min(ray_t.max);
+
+        if rec1.t >= rec2.t {
+            return false;
+        }
+
+        rec1.t = rec1.t.max(0.0);
+
+        let ray_length = r.direction().length();
+        let distance_inside_boundary = (rec2.t - rec1.t) * ray_length;
+        let mut sampler = ConstantMedium::ray_sampler(r);
+        let hit_distance = self.neg_inv_density * (1.0 - sampler.random_f64()).ln();
+
+        if hit_distance > distance_inside_boundary {
+            return false;
+        }
+
+        rec.t = rec1.t + hit_distance / ray_length;
+        rec.p = r.at(rec.t);
+
+        // Arbitrary, the phase function doesn't depend on a surface
+        rec.normal = Vec3::with_values(1.0, 0.0, 0.0);
+        rec.geometric_normal = rec.normal;
+        rec.front_face = true;
+        rec.u = 0.0;
+        rec.v = 0.0;
+        rec.dpdu = Vec3::new();
+        rec.dpdv = Vec3::new();
+        rec.mat = self.phase_function.clone();
+
+        return true;
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.boundary.bounding_box()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{color::Color, material::Isotropic, quad::make_box, vec3::Point3};
+
+    const DENSITY: f64 = 0.7;
+    const THICKNESS: f64 = 1.0;
+
+    // Slab of fog between z = 0 and z = THICKNESS, wide enough for every test ray to cross it
+    fn slab() -> ConstantMedium {
+        let boundary = make_box(
+            Point3::with_values(-100.0, -100.0, 0.0),
+            Point3::with_values(100.0, 100.0, THICKNESS),
+            Arc::new(Isotropic::new(Color::with_values(1.0, 1.0, 1.0))),
+        );
+        ConstantMedium::new(
+            Box::new(boundary),
+            DENSITY,
+            Arc::new(Isotropic::new(Color::with_values(1.0, 1.0, 1.0))),
+        )
+    }
+
+    // Fraction of `rays` that cross the slab without scattering
+    fn transmitted_fraction(medium: &ConstantMedium, rays: &[Ray]) -> f64 {
+        let transmitted = rays
+            .iter()
+            .filter(|r| {
+                let mut rec = HitRecord::new();
+                !medium.hit(r, Interval::with_values(0.001, f64::INFINITY), &mut rec)
+            })
+            .count();
+
+        transmitted as f64 / rays.len() as f64
+    }
+
+    #[test]
+    fn slab_transmittance_matches_beer_lambert() {
+        let medium = slab();
+        let mut sampler = Sampler::new(7);
+        let rays: Vec<Ray> = (0..100_000)
+            .map(|_| {
+                let origin = Point3::with_values(
+                    sampler.random_between(-10.0, 10.0),
+                    sampler.random_between(-10.0, 10.0),
+                    -1.0,
+                );
+                Ray::with_values(origin, Vec3::with_values(0.0, 0.0, 1.0))
+            })
+            .collect();
+
+        let expected = (-DENSITY * THICKNESS).exp();
+        let fraction = transmitted_fraction(&medium, &rays);
+        assert!(
+            (fraction - expected).abs() < 0.01,
+            "transmitted {}, expected {}",
+            fraction,
+            expected
+        );
+    }
+
+    // Rays that differ only in the last bits of their origin or direction, as shadow rays from
+    // neighboring points or directions do, must still get independent free-flight distances
+    #[test]
+    fn hashed_distances_are_exponential_for_nearly_identical_rays() {
+        let medium = slab();
+        let base_origin = Point3::with_values(0.25, -0.5, -1.0);
+        let n = 100_000;
+
+        let by_origin: Vec<Ray> = (0..n)
+            .map(|i| {
+                let origin = base_origin + Vec3::with_values(i as f64 * 1e-12, 0.0, 0.0);
+                Ray::with_values(origin, Vec3::with_values(0.0, 0.0, 1.0))
+            })
+            .collect();
+        let by_direction: Vec<Ray> = (0..n)
+            .map(|i| {
+                let direction = Vec3::with_values(i as f64 * 1e-12, 0.0, 1.0);
+                Ray::with_values(base_origin, direction)
+            })
+            .collect();
+
+        for rays in [by_origin, by_direction] {
+            // Distances into the medium, with the ones that got through cut off at the far side
+            let distances: Vec<f64> = rays
+                .iter()
+                .map(|r| {
+                    let mut rec = HitRecord::new();
+                    if medium.hit(r, Interval::with_values(0.001, f64::INFINITY), &mut rec) {
+                        rec.p.z()
+                    } else {
+                        THICKNESS
+                    }
+                })
+                .collect();
+
+            // Compare the empirical distribution with 1 - exp(-density z) at several depths
+            for depth in [0.1, 0.25, 0.5, 0.75, 0.99] {
+                let below = distances.iter().filter(|z| **z < depth).count() as f64 / n as f64;
+                let expected = 1.0 - (-DENSITY * depth).exp();
+                assert!(
+                    (below - expected).abs() < 0.01,
+                    "fraction scattered before {}: {}, expected {}",
+                    depth,
+                    below,
+                    expected
+                );
+            }
+        }
+    }
+
+    // The same ray gets the same answer every time, which keeps seeded renders reproducible
+    #[test]
+    fn identical_rays_get_identical_distances() {
+        let medium = slab();
+        let r = Ray::with_values(
+            Point3::with_values(0.1, 0.2, -1.0),
+            Vec3::with_values(0.3, 0.1, 1.0),
+        );
+
+        let mut first = HitRecord::new();
+        let mut second = HitRecord::new();
+        let hit_first = medium.hit(&r, Interval::with_values(0.001, f64::INFINITY), &mut first);
+        let hit_second = medium.hit(&r, Interval::with_values(0.001, f64::INFINITY), &mut second);
+
+        assert_eq!(hit_first, hit_second);
+        if hit_first {
+            assert_eq!(first.t, second.t);
+        }
+    }
+}
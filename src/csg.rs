@@ -0,0 +1,363 @@
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable},
+    interval::Interval,
+    ray::Ray,
+};
+
+// Distance past a crossing, relative to its ray parameter, at which the search for the next one
+// resumes, so that rounding doesn't find the same surface twice
+const CROSSING_EPSILON: f64 = 1e-9;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum CsgOperation {
+    Union,
+    Intersection,
+    // The left solid with the right one carved out of it
+    Difference,
+}
+
+impl CsgOperation {
+    fn inside(&self, in_left: bool, in_right: bool) -> bool {
+        match self {
+            CsgOperation::Union => in_left || in_right,
+            CsgOperation::Intersection => in_left && in_right,
+            CsgOperation::Difference => in_left && !in_right,
+        }
+    }
+}
+
+// Boolean combination of two closed solids. Every surface keeps the material of the solid it
+// comes from, so the inside of a bowl cut out by a difference shows the right solid's material.
+pub struct Csg {
+    left: Box<dyn Hittable>,
+    right: Box<dyn Hittable>,
+    operation: CsgOperation,
+    bbox: Aabb,
+}
+
+impl Csg {
+    pub fn new(left: Box<dyn Hittable>, right: Box<dyn Hittable>, operation: CsgOperation) -> Csg {
+        let bbox = match operation {
+            CsgOperation::Union => Aabb::surrounding(&left.bounding_box(), &right.bounding_box()),
+            CsgOperation::Intersection => {
+                Aabb::overlap(&left.bounding_box(), &right.bounding_box())
+            }
+            CsgOperation::Difference => left.bounding_box(),
+        };
+
+        Csg {
+            left,
+            right,
+            operation,
+            bbox,
+        }
+    }
+
+    pub fn union(left: Box<dyn Hittable>, right: Box<dyn Hittable>) -> Csg {
+        Csg::new(left, right, CsgOperation::Union)
+    }
+
+    pub fn intersection(left: Box<dyn Hittable>, right: Box<dyn Hittable>) -> Csg {
+        Csg::new(left, right, CsgOperation::Intersection)
+    }
+
+    pub fn difference(left: Box<dyn Hittable>, right: Box<dyn Hittable>) -> Csg {
+        Csg::new(left, right, CsgOperation::Difference)
+    }
+
+    // Surface crossings of `object` along the ray within `ray_t`, in order, followed by the first
+    // one past it, if any, which tells whether the ray ends up inside. `hit` only reports the
+    // closest one, so this keeps asking for the next crossing just past the previous one. Of
+    // several crossings at the same t, such as where a ray grazes the edge of a box, only one is
+    // found.
+    fn crossings(object: &dyn Hittable, r: &Ray, ray_t: Interval) -> Vec<HitRecord> {
+        let mut crossings = Vec::new();
+        let mut t = ray_t.min;
+        let mut rec = HitRecord::new();
+
+        while object.hit(r, Interval::with_values(t, f64::INFINITY), &mut rec) {
+            t = rec.t + CROSSING_EPSILON * rec.t.abs().max(1.0);
+            crossings.push(rec.clone());
+            if rec.t >= ray_t.max {
+                break;
+            }
+        }
+
+        crossings
+    }
+
+    // Whether the ray is inside a closed solid right after its crossing `i`. A solid is left
+    // through a back face, so the next crossing tells, which stays right when the search skipped
+    // a crossing at the same t as this one.
+    fn inside_after(crossings: &[HitRecord], i: usize) -> bool {
+        crossings.get(i + 1).is_some_and(|next| !next.front_face)
+    }
+}
+
+impl Hittable for Csg {
+    // Walks the crossings of both solids in order, tracking whether the ray is inside each of
+    // them, and reports the first crossing where it enters or leaves the combined solid.
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        if !self.bbox.hit(r, ray_t) {
+            return false;
+        }
+
+        let left = Csg::crossings(self.left.as_ref(), r, ray_t);
+        let right = Csg::crossings(self.right.as_ref(), r, ray_t);
+
+        // A closed solid is left through a back face, so the first crossing tells whether the
+        // ray starts inside
+        let mut in_left = left.first().is_some_and(|rec| !rec.front_face);
+        let mut in_right = right.first().is_some_and(|rec| !rec.front_face);
+        let mut inside = self.operation.inside(in_left, in_right);
+
+        let (mut i, mut j) = (0, 0);
+        while i < left.len() || j < right.len() {
+            let take_left = j == right.len() || (i < left.len() && left[i].t <= right[j].t);
+            let crossing = if take_left {
+                in_left = Csg::inside_after(&left, i);
+                i += 1;
+                &left[i - 1]
+            } else {
+                in_right = Csg::inside_after(&right, j);
+                j += 1;
+                &right[j - 1]
+            };
+
+            if crossing.t >= ray_t.max {
+                return false;
+            }
+
+            let was_inside = inside;
+            inside = self.operation.inside(in_left, in_right);
+            if inside == was_inside {
+                continue;
+            }
+
+            // The normal already faces the incoming ray, only the side of the combined solid
+            // the ray was on changes
+            *rec = crossing.clone();
+            rec.front_face = inside;
+
+            return true;
+        }
+
+        return false;
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::*;
+    use crate::{
+        color::Color,
+        hittable_list::HittableList,
+        material::Lambertian,
+        quad::make_box,
+        sphere::Sphere,
+        vec3::{Point3, Vec3},
+    };
+
+    fn sphere(center: Point3, radius: f64) -> Box<dyn Hittable> {
+        let mat = Arc::new(Lambertian::new(Color::with_values(0.5, 0.5, 0.5)));
+        Box::new(Sphere::new(center, radius, mat))
+    }
+
+    // Unit spheres around the origin and around x = 1, overlapping between x = 0 and x = 1
+    fn overlapping_spheres() -> (Box<dyn Hittable>, Box<dyn Hittable>) {
+        (
+            sphere(Point3::with_values(0.0, 0.0, 0.0), 1.0),
+            sphere(Point3::with_values(1.0, 0.0, 0.0), 1.0),
+        )
+    }
+
+    // Counts the queries made of the object it wraps
+    struct Counted {
+        object: Box<dyn Hittable>,
+        queries: &'static AtomicUsize,
+    }
+
+    impl Hittable for Counted {
+        fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+            self.queries.fetch_add(1, Ordering::Relaxed);
+            self.object.hit(r, ray_t, rec)
+        }
+
+        fn bounding_box(&self) -> Aabb {
+            self.object.bounding_box()
+        }
+    }
+
+    // First crossing of the combined solid along the ray after `t_min`
+    fn hit(object: &Csg, origin: Point3, direction: Vec3, t_min: f64) -> Option<HitRecord> {
+        let mut rec = HitRecord::new();
+        let ray = Ray::with_values(origin, direction);
+        if !object.hit(&ray, Interval::with_values(t_min, f64::INFINITY), &mut rec) {
+            return None;
+        }
+
+        Some(rec)
+    }
+
+    // Checks a crossing at `t` whose normal faces the ray, entering the solid or leaving it
+    fn assert_crossing(rec: Option<HitRecord>, t: f64, normal: Vec3, entering: bool) {
+        let rec = rec.expect("expected a crossing");
+        assert!((rec.t - t).abs() < 1e-9, "t {} instead of {}", rec.t, t);
+        assert!((rec.normal - normal).near_zero(), "normal {}", rec.normal);
+        assert_eq!(rec.front_face, entering);
+    }
+
+    #[test]
+    fn union_spans_both_solids() {
+        let (a, b) = overlapping_spheres();
+        let union = Csg::union(a, b);
+        let origin = Point3::with_values(-5.0, 0.0, 0.0);
+        let direction = Vec3::with_values(1.0, 0.0, 0.0);
+        let facing = Vec3::with_values(-1.0, 0.0, 0.0);
+
+        assert_crossing(hit(&union, origin, direction, 0.001), 4.0, facing, true);
+        assert_crossing(hit(&union, origin, direction, 4.5), 7.0, facing, false);
+        assert!(hit(&union, origin, direction, 7.5).is_none());
+    }
+
+    #[test]
+    fn intersection_keeps_the_overlap() {
+        let (a, b) = overlapping_spheres();
+        let intersection = Csg::intersection(a, b);
+        let origin = Point3::with_values(-5.0, 0.0, 0.0);
+        let direction = Vec3::with_values(1.0, 0.0, 0.0);
+        let facing = Vec3::with_values(-1.0, 0.0, 0.0);
+
+        assert_crossing(
+            hit(&intersection, origin, direction, 0.001),
+            5.0,
+            facing,
+            true,
+        );
+        assert_crossing(
+            hit(&intersection, origin, direction, 5.5),
+            6.0,
+            facing,
+            false,
+        );
+        assert!(hit(&intersection, origin, direction, 6.5).is_none());
+
+        // From inside the overlap
+        let inside = Point3::with_values(0.5, 0.0, 0.0);
+        assert_crossing(
+            hit(&intersection, inside, direction, 0.001),
+            0.5,
+            facing,
+            false,
+        );
+    }
+
+    #[test]
+    fn difference_cuts_the_right_solid_away() {
+        let (a, b) = overlapping_spheres();
+        let difference = Csg::difference(a, b);
+        let origin = Point3::with_values(-5.0, 0.0, 0.0);
+        let direction = Vec3::with_values(1.0, 0.0, 0.0);
+        let facing = Vec3::with_values(-1.0, 0.0, 0.0);
+
+        assert_crossing(
+            hit(&difference, origin, direction, 0.001),
+            4.0,
+            facing,
+            true,
+        );
+        // Leaves where the right sphere starts
+        assert_crossing(hit(&difference, origin, direction, 4.5), 5.0, facing, false);
+        assert!(hit(&difference, origin, direction, 5.5).is_none());
+    }
+
+    #[test]
+    fn bowl_shows_its_inner_surface() {
+        // Unit sphere with the upper half of another one carved out of it
+        let bowl = Csg::difference(
+            sphere(Point3::with_values(0.0, 0.0, 0.0), 1.0),
+            sphere(Point3::with_values(0.0, 1.0, 0.0), 1.0),
+        );
+        let origin = Point3::with_values(0.0, 5.0, 0.0);
+        let direction = Vec3::with_values(0.0, -1.0, 0.0);
+        let up = Vec3::with_values(0.0, 1.0, 0.0);
+
+        // Looking down into the bowl, the first surface is its inside at the bottom, facing up
+        assert_crossing(hit(&bowl, origin, direction, 0.001), 5.0, up, true);
+        assert_crossing(hit(&bowl, origin, direction, 5.5), 6.0, up, false);
+
+        // Past the rim, a ray from the side sees the outside of the bowl
+        let side = Point3::with_values(-5.0, -0.5, 0.0);
+        let rec = hit(&bowl, side, Vec3::with_values(1.0, 0.0, 0.0), 0.001);
+        let x = (1.0 - 0.25_f64).sqrt();
+        let outward = Vec3::with_values(-x, -0.5, 0.0);
+        assert_crossing(rec, 5.0 - x, outward, true);
+    }
+
+    #[test]
+    fn grazing_an_edge_leaves_the_solid_again() {
+        // The ray touches the edge of the box at x = 0, y = 1 where it crosses the left and top
+        // faces at the same t, then passes through a sphere outside the box
+        let mat = Arc::new(Lambertian::new(Color::with_values(0.5, 0.5, 0.5)));
+        let cube = make_box(
+            Point3::with_values(0.0, 0.0, 0.0),
+            Point3::with_values(1.0, 1.0, 1.0),
+            mat,
+        );
+        let origin = Point3::with_values(-1.0, 0.0, 0.5);
+        let direction = Vec3::with_values(1.0, 1.0, 0.0);
+        let intersection = Csg::intersection(
+            Box::new(cube),
+            sphere(Point3::with_values(2.0, 3.0, 0.5), 0.5),
+        );
+
+        assert!(hit(&intersection, origin, direction, 0.001).is_none());
+    }
+
+    #[test]
+    fn stops_looking_past_the_end_of_the_ray() {
+        // A row of ten spheres along x, every 3 units from x = 0
+        static QUERIES: AtomicUsize = AtomicUsize::new(0);
+        let mut row = HittableList::new();
+        for k in 0..10 {
+            row.add(sphere(Point3::with_values(3.0 * k as f64, 0.0, 0.0), 1.0));
+        }
+        let counted = Box::new(Counted {
+            object: Box::new(row),
+            queries: &QUERIES,
+        });
+        let union = Csg::union(counted, sphere(Point3::with_values(1.5, 0.0, 0.0), 1.0));
+
+        let ray = Ray::with_values(
+            Point3::with_values(-5.0, 0.0, 0.0),
+            Vec3::with_values(1.0, 0.0, 0.0),
+        );
+        let mut rec = HitRecord::new();
+
+        // Ending inside the first sphere, once the crossing out of it shows the ray ends inside
+        assert!(union.hit(&ray, Interval::with_values(0.001, 4.5), &mut rec));
+        assert!((rec.t - 4.0).abs() < 1e-9);
+        assert_eq!(QUERIES.swap(0, Ordering::Relaxed), 2);
+
+        // Through the first two spheres, joined up by the right one, up to the crossing into the
+        // third past the end
+        assert!(union.hit(&ray, Interval::with_values(4.5, 9.5), &mut rec));
+        assert!((rec.t - 9.0).abs() < 1e-9);
+        assert_eq!(QUERIES.swap(0, Ordering::Relaxed), 4);
+
+        // Without an end every crossing of the row is needed
+        assert!(union.hit(&ray, Interval::with_values(4.5, f64::INFINITY), &mut rec));
+        assert!((rec.t - 9.0).abs() < 1e-9);
+        assert_eq!(QUERIES.swap(0, Ordering::Relaxed), 20);
+    }
+}
@@ -0,0 +1,225 @@
+use std::{f64::consts::PI, sync::Arc};
+
+use crate::{
+    aabb::Aabb,
+    disk::disk_bounds,
+    hittable::{HitRecord, Hittable},
+    interval::Interval,
+    material::Material,
+    onb::Onb,
+    ray::Ray,
+    vec3::{Point3, Vec3},
+};
+
+// Closed cylinder running from the center of one cap `p0` to the center of the other `p1`.
+// Intersections happen in a local frame where the axis runs along +z from the origin.
+pub struct Cylinder {
+    p0: Point3,
+    height: f64,
+    radius: f64,
+    frame: Onb,
+    mat: Arc<dyn Material>,
+    bbox: Aabb,
+}
+
+impl Cylinder {
+    pub fn new(p0: Point3, p1: Point3, radius: f64, mat: Arc<dyn Material>) -> Cylinder {
+        assert!(
+            !(p1 - p0).near_zero(),
+            "Cylinder cap centers must be different points"
+        );
+
+        let radius = f64::max(0.0, radius);
+        let frame = Onb::new(&(p1 - p0));
+        let bbox = Aabb::surrounding(
+            &disk_bounds(p0, &frame.w(), radius),
+            &disk_bounds(p1, &frame.w(), radius),
+        );
+
+        Cylinder {
+            p0,
+            height: (p1 - p0).length(),
+            radius,
+            frame,
+            mat,
+            bbox,
+        }
+    }
+}
+
+impl Hittable for Cylinder {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let o = self.frame.inverse_transform(&(r.origin() - self.p0));
+        let d = self.frame.inverse_transform(&r.direction());
+
+        let mut closest = ray_t.max;
+        let mut outward_normal = Vec3::new();
+
+        // Side: x^2 + y^2 = radius^2 between the caps
+        let a = d.x() * d.x() + d.y() * d.y();
+        if a > 0.0 {
+            let h = o.x() * d.x() + o.y() * d.y();
+            let c = o.x() * o.x() + o.y() * o.y() - self.radius * self.radius;
+            let discriminant = h * h - a * c;
+
+            if discriminant >= 0.0 {
+                let sqrtd = discriminant.sqrt();
+                for root in [(-h - sqrtd) / a, (-h + sqrtd) / a] {
+                    let z = o.z() + root * d.z();
+                    if ray_t.min < root && root < closest && (0.0..=self.height).contains(&z) {
+                        closest = root;
+                        let p = o + root * d;
+                        outward_normal = Vec3::with_values(p.x(), p.y(), 0.0) / self.radius;
+                        break;
+                    }
+                }
+            }
+        }
+
+        // Caps: z = 0 facing -z and z = height facing +z
+        if d.z() != 0.0 {
+            for (z, normal_z) in [(0.0, -1.0), (self.height, 1.0)] {
+                let root = (z - o.z()) / d.z();
+                if !(ray_t.min < root && root < closest) {
+                    continue;
+                }
+
+                let p = o + root * d;
+                if p.x() * p.x() + p.y() * p.y() <= self.radius * self.radius {
+                    closest = root;
+                    outward_normal = Vec3::with_values(0.0, 0.0, normal_z);
+                }
+            }
+        }
+
+        if closest >= ray_t.max {
+            return false;
+        }
+
+        // u: angle around the axis, v: height along the side or distance from the center of a cap
+        let p = o + closest * d;
+        let phi = p.y().atan2(p.x());
+        rec.u = if phi < 0.0 { phi + 2.0 * PI } else { phi } / (2.0 * PI);
+        rec.v = if outward_normal.z() == 0.0 {
+            p.z() / self.height
+        } else {
+            (p.x() * p.x() + p.y() * p.y()).sqrt() / self.radius
+        };
+        let dpdv = if outward_normal.z() == 0.0 {
+            Vec3::with_values(0.0, 0.0, self.height)
+        } else {
+            self.radius * Vec3::with_values(phi.cos(), phi.sin(), 0.0)
+        };
+        rec.dpdu = self
+            .frame
+            .transform(&(2.0 * PI * Vec3::with_values(-p.y(), p.x(), 0.0)));
+        rec.dpdv = self.frame.transform(&dpdv);
+
+        rec.t = closest;
+        rec.p = r.at(closest);
+        rec.set_face_normal(r, &self.frame.transform(&outward_normal));
+        rec.mat = self.mat.clone();
+
+        return true;
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        color::Color,
+        hittable::testing::{assert_near, hit},
+        material::Lambertian,
+    };
+
+    // Cylinder of radius 1 standing on the origin with its axis along +z up to z = 2
+    fn cylinder() -> Cylinder {
+        Cylinder::new(
+            Point3::with_values(0.0, 0.0, 0.0),
+            Point3::with_values(0.0, 0.0, 2.0),
+            1.0,
+            Arc::new(Lambertian::new(Color::with_values(0.5, 0.5, 0.5))),
+        )
+    }
+
+    #[test]
+    fn axis_aligned_ray_hits_the_side() {
+        let rec = hit(
+            &cylinder(),
+            Point3::with_values(5.0, 0.0, 1.0),
+            Vec3::with_values(-1.0, 0.0, 0.0),
+        )
+        .unwrap();
+
+        assert!((rec.t - 4.0).abs() < 1e-9);
+        assert_near(rec.p, Point3::with_values(1.0, 0.0, 1.0));
+        assert_near(rec.normal, Vec3::with_values(1.0, 0.0, 0.0));
+        assert!(rec.front_face);
+        assert!((rec.u - 0.5).abs() < 1e-9);
+        assert!((rec.v - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn axis_aligned_ray_hits_the_top_cap() {
+        let rec = hit(
+            &cylinder(),
+            Point3::with_values(0.0, 0.5, 5.0),
+            Vec3::with_values(0.0, 0.0, -1.0),
+        )
+        .unwrap();
+
+        assert!((rec.t - 3.0).abs() < 1e-9);
+        assert_near(rec.normal, Vec3::with_values(0.0, 0.0, 1.0));
+        assert!(rec.front_face);
+        assert!((rec.u - 0.25).abs() < 1e-9);
+        assert!((rec.v - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn rays_parallel_to_the_axis_only_meet_the_caps() {
+        // No side equation to solve, so a ray inside the tube goes from cap to cap
+        let inside = hit(
+            &cylinder(),
+            Point3::with_values(0.5, 0.5, 1.0),
+            Vec3::with_values(0.0, 0.0, 1.0),
+        )
+        .unwrap();
+        assert!((inside.t - 1.0).abs() < 1e-9);
+        assert_near(inside.normal, Vec3::with_values(0.0, 0.0, -1.0));
+        assert!(!inside.front_face);
+
+        let outside = hit(
+            &cylinder(),
+            Point3::with_values(1.001, 0.0, -5.0),
+            Vec3::with_values(0.0, 0.0, 1.0),
+        );
+        assert!(outside.is_none());
+    }
+
+    #[test]
+    fn diagonal_rays_pick_the_side_or_cap_around_the_rim() {
+        // Aimed at the rim of the top cap from just below and just above it
+        let below = hit(
+            &cylinder(),
+            Point3::with_values(3.0, 0.0, 3.999),
+            Vec3::with_values(-1.0, 0.0, -1.0),
+        )
+        .unwrap();
+        assert!(below.normal.x() > 0.99);
+        assert!((below.v - 0.9995).abs() < 1e-9);
+
+        let above = hit(
+            &cylinder(),
+            Point3::with_values(3.0, 0.0, 4.001),
+            Vec3::with_values(-1.0, 0.0, -1.0),
+        )
+        .unwrap();
+        assert_near(above.normal, Vec3::with_values(0.0, 0.0, 1.0));
+        assert!((above.v - 0.999).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,226 @@
+use std::{f64::consts::PI, sync::Arc};
+
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable},
+    interval::Interval,
+    material::Material,
+    onb::Onb,
+    ray::Ray,
+    sampler::Sampler,
+    vec3::{Point3, Vec3},
+};
+
+// Flat circular disk facing along `normal`
+pub struct Disk {
+    center: Point3,
+    radius: f64,
+    // In-plane axes u and v, with w along the normal
+    frame: Onb,
+    d: f64,
+    mat: Arc<dyn Material>,
+    bbox: Aabb,
+}
+
+impl Disk {
+    pub fn new(center: Point3, normal: Vec3, radius: f64, mat: Arc<dyn Material>) -> Disk {
+        let radius = f64::max(0.0, radius);
+        let frame = Onb::new(&normal);
+
+        Disk {
+            center,
+            radius,
+            d: frame.w().dot(&center),
+            bbox: disk_bounds(center, &frame.w(), radius),
+            frame,
+            mat,
+        }
+    }
+}
+
+// Bounding box of a circle of the given radius around `center`, facing along the unit vector
+// `normal`. The extent along each axis shrinks the more the circle faces that axis.
+pub fn disk_bounds(center: Point3, normal: &Vec3, radius: f64) -> Aabb {
+    let extent = Vec3::with_values(
+        radius * (1.0 - normal.x() * normal.x()).max(0.0).sqrt(),
+        radius * (1.0 - normal.y() * normal.y()).max(0.0).sqrt(),
+        radius * (1.0 - normal.z() * normal.z()).max(0.0).sqrt(),
+    );
+
+    Aabb::from_points(center - extent, center + extent)
+}
+
+impl Hittable for Disk {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let normal = self.frame.w();
+        let denom = normal.dot(&r.direction());
+
+        if denom.abs() < 1e-8 {
+            return false;
+        }
+
+        let t = (self.d - normal.dot(&r.origin())) / denom;
+        if !ray_t.surrounds(t) {
+            return false;
+        }
+
+        let intersection = r.at(t);
+        let local = intersection - self.center;
+        let distance_squared = local.length_squared();
+        if distance_squared > self.radius * self.radius {
+            return false;
+        }
+
+        // u: angle around the normal from the frame's u axis, v: distance from the center
+        let phi = local.dot(&self.frame.v()).atan2(local.dot(&self.frame.u()));
+        rec.t = t;
+        rec.p = intersection;
+        rec.u = if phi < 0.0 { phi + 2.0 * PI } else { phi } / (2.0 * PI);
+        rec.v = distance_squared.sqrt() / self.radius;
+        rec.dpdu = 2.0
+            * PI
+            * (local.dot(&self.frame.u()) * self.frame.v()
+                - local.dot(&self.frame.v()) * self.frame.u());
+        rec.dpdv = self.radius * (phi.cos() * self.frame.u() + phi.sin() * self.frame.v());
+        rec.set_face_normal(r, &normal);
+        rec.mat = self.mat.clone();
+
+        return true;
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let mut rec = HitRecord::new();
+        let ray = Ray::with_values(*origin, *direction);
+        if !self.hit(&ray, Interval::with_values(0.001, f64::INFINITY), &mut rec) {
+            return 0.0;
+        }
+
+        let distance_squared = rec.t * rec.t * direction.length_squared();
+        let cosine = (direction.dot(&rec.normal) / direction.length()).abs();
+        let area = PI * self.radius * self.radius;
+
+        distance_squared / (cosine * area)
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut Sampler) -> Vec3 {
+        let r = self.radius * sampler.random_f64().sqrt();
+        let phi = 2.0 * PI * sampler.random_f64();
+        let p = self.center
+            + self
+                .frame
+                .transform(&Vec3::with_values(r * phi.cos(), r * phi.sin(), 0.0));
+
+        p - *origin
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        color::Color,
+        hittable::testing::{assert_near, assert_pdf_matches_solid_angle, hit},
+        material::Lambertian,
+    };
+
+    // Radius 2 around (0, 1, 0), facing +y
+    fn disk() -> Disk {
+        let mat = Arc::new(Lambertian::new(Color::with_values(0.5, 0.5, 0.5)));
+        Disk::new(
+            Point3::with_values(0.0, 1.0, 0.0),
+            Vec3::with_values(0.0, 1.0, 0.0),
+            2.0,
+            mat,
+        )
+    }
+
+    fn down_onto(disk: &Disk, x: f64, z: f64) -> Option<HitRecord> {
+        hit(
+            disk,
+            Point3::with_values(x, 3.0, z),
+            Vec3::with_values(0.0, -1.0, 0.0),
+        )
+    }
+
+    #[test]
+    fn hits_stop_at_the_radius() {
+        let disk = disk();
+        let e = 1e-6;
+        let diagonal = 2.0 / 2.0_f64.sqrt();
+
+        for (x, z) in [
+            (2.0 - e, 0.0),
+            (0.0, -2.0 + e),
+            (diagonal - e, diagonal - e),
+        ] {
+            assert!(down_onto(&disk, x, z).is_some(), "({}, {})", x, z);
+        }
+        for (x, z) in [
+            (2.0 + e, 0.0),
+            (0.0, -2.0 - e),
+            (diagonal + e, diagonal + e),
+        ] {
+            assert!(down_onto(&disk, x, z).is_none(), "({}, {})", x, z);
+        }
+    }
+
+    #[test]
+    fn texture_coordinates_go_around_and_out() {
+        let disk = disk();
+        let u_axis = disk.frame.u();
+        let v_axis = disk.frame.v();
+
+        let rec = down_onto(&disk, 0.0, 0.0).unwrap();
+        assert!(rec.v.abs() < 1e-12);
+
+        // A quarter of the way around at each of the frame's axes, and out at the rim
+        for (direction, u) in [
+            (u_axis, 0.0),
+            (v_axis, 0.25),
+            (-u_axis, 0.5),
+            (-v_axis, 0.75),
+        ] {
+            let p = 2.0 * direction * (1.0 - 1e-9);
+            let rec = down_onto(&disk, p.x(), p.z()).unwrap();
+            assert!((rec.u - u).abs() < 1e-6, "u {} instead of {}", rec.u, u);
+            assert!((rec.v - 1.0).abs() < 1e-6, "v {}", rec.v);
+        }
+    }
+
+    #[test]
+    fn front_face_is_the_side_the_normal_points_out_of() {
+        let disk = disk();
+        let up = Vec3::with_values(0.0, 1.0, 0.0);
+
+        let rec = down_onto(&disk, 0.5, 0.5).unwrap();
+        assert!(rec.front_face);
+        assert_near(rec.normal, up);
+
+        let rec = hit(&disk, Point3::with_values(0.5, -1.0, 0.5), up).unwrap();
+        assert!(!rec.front_face);
+        assert_near(rec.normal, -up);
+    }
+
+    #[test]
+    fn pdf_matches_the_sampled_directions() {
+        let disk = disk();
+        assert_pdf_matches_solid_angle(&disk, Point3::with_values(0.5, 2.5, -1.0));
+
+        // Towards its center from far away, the disk covers its area times the cosine over the
+        // distance squared
+        let origin = Point3::with_values(0.0, 41.0, 30.0);
+        let direction = Point3::with_values(0.0, 1.0, 0.0) - origin;
+        let solid_angle = 4.0 * PI * 0.8 / (50.0 * 50.0);
+        let pdf = disk.pdf_value(&origin, &direction);
+        assert!((pdf * solid_angle - 1.0).abs() < 1e-9, "{}", pdf);
+
+        assert_eq!(
+            disk.pdf_value(&origin, &Vec3::with_values(0.0, 1.0, 0.0)),
+            0.0
+        );
+    }
+}
@@ -0,0 +1,158 @@
+use std::{f64::consts::PI, sync::Arc};
+
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable},
+    interval::Interval,
+    material::Material,
+    ray::Ray,
+    sphere::sphere_derivatives,
+    vec3::{Point3, Vec3},
+};
+
+// Axis-aligned ellipsoid with semi-axes `radii`; wrap it in a `Transform` to rotate it. Rays are
+// intersected in the space where it becomes a unit sphere, which keeps the ray parameter.
+pub struct Ellipsoid {
+    center: Point3,
+    radii: Vec3,
+    mat: Arc<dyn Material>,
+    bbox: Aabb,
+}
+
+impl Ellipsoid {
+    pub fn new(center: Point3, radii: Vec3, mat: Arc<dyn Material>) -> Ellipsoid {
+        assert!(
+            (0..3).all(|i| radii[i] != 0.0 && radii[i].is_finite()),
+            "Ellipsoid radii must be non-zero and finite"
+        );
+
+        let radii = Vec3::with_values(radii.x().abs(), radii.y().abs(), radii.z().abs());
+
+        Ellipsoid {
+            center,
+            radii,
+            mat,
+            bbox: Aabb::from_points(center - radii, center + radii),
+        }
+    }
+}
+
+impl Hittable for Ellipsoid {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let oc = (self.center - r.origin()) / self.radii;
+        let d = r.direction() / self.radii;
+
+        let a = d.length_squared();
+        let h = d.dot(&oc);
+        let c = oc.length_squared() - 1.0;
+        let discriminant = h * h - a * c;
+
+        if discriminant < 0.0 {
+            return false;
+        }
+
+        let sqrtd = discriminant.sqrt();
+
+        let mut root = (h - sqrtd) / a;
+        if !ray_t.surrounds(root) {
+            root = (h + sqrtd) / a;
+            if !ray_t.surrounds(root) {
+                return false;
+            }
+        }
+
+        rec.t = root;
+        rec.p = r.at(root);
+
+        // Point on the unit sphere, whose normal goes back through the inverse transpose
+        let unit_p = (rec.p - self.center) / self.radii;
+        let outward_normal = (unit_p / self.radii).unit_vector();
+        rec.set_face_normal(r, &outward_normal);
+
+        // Same mapping as a sphere, applied before stretching
+        let theta = (-unit_p.y()).clamp(-1.0, 1.0).acos();
+        let phi = (-unit_p.z()).atan2(unit_p.x()) + PI;
+        rec.u = phi / (2.0 * PI);
+        rec.v = theta / PI;
+        let (dpdu, dpdv) = sphere_derivatives(&unit_p, 1.0);
+        rec.dpdu = dpdu * self.radii;
+        rec.dpdv = dpdv * self.radii;
+        rec.mat = self.mat.clone();
+
+        return true;
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        color::Color,
+        hittable::testing::{assert_near, hit},
+        material::Lambertian,
+    };
+
+    fn ellipsoid() -> Ellipsoid {
+        Ellipsoid::new(
+            Point3::with_values(1.0, 2.0, 3.0),
+            Vec3::with_values(2.0, 1.0, 0.5),
+            Arc::new(Lambertian::new(Color::with_values(0.5, 0.5, 0.5))),
+        )
+    }
+
+    #[test]
+    fn axis_aligned_ray_hits_the_pole() {
+        let rec = hit(
+            &ellipsoid(),
+            Point3::with_values(1.0, 2.0, 10.0),
+            Vec3::with_values(0.0, 0.0, -1.0),
+        )
+        .unwrap();
+
+        assert!((rec.t - 6.5).abs() < 1e-9);
+        assert_near(rec.p, Point3::with_values(1.0, 2.0, 3.5));
+        assert_near(rec.normal, Vec3::with_values(0.0, 0.0, 1.0));
+        assert!(rec.front_face);
+        assert!((rec.u - 0.25).abs() < 1e-9);
+        assert!((rec.v - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn rays_from_the_center_reach_each_radius() {
+        let axes = [
+            (Vec3::with_values(1.0, 0.0, 0.0), 2.0),
+            (Vec3::with_values(0.0, -1.0, 0.0), 1.0),
+            (Vec3::with_values(0.0, 0.0, 1.0), 0.5),
+        ];
+        for (direction, radius) in axes {
+            let rec = hit(&ellipsoid(), Point3::with_values(1.0, 2.0, 3.0), direction).unwrap();
+            assert!((rec.t - radius).abs() < 1e-9);
+            assert_near(rec.normal, -direction);
+            assert!(!rec.front_face);
+        }
+    }
+
+    #[test]
+    fn normals_follow_the_stretched_surface() {
+        // Point at 45 degrees on the unit sphere, which the radii squash toward the pole
+        let s = 0.5_f64.sqrt();
+        let rec = hit(
+            &ellipsoid(),
+            Point3::with_values(1.0, 2.0 + s, 10.0),
+            Vec3::with_values(0.0, 0.0, -1.0),
+        )
+        .unwrap();
+
+        assert!((rec.t - (7.0 - 0.5 * s)).abs() < 1e-9);
+        assert_near(rec.normal, Vec3::with_values(0.0, 1.0, 2.0).unit_vector());
+        assert!(rec.front_face);
+
+        // The derivatives stay tangent to the stretched surface
+        assert!(rec.dpdu.dot(&rec.normal).abs() < 1e-9);
+        assert!(rec.dpdv.dot(&rec.normal).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,419 @@
+use std::{path::Path, sync::Arc};
+
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable},
+    image::{Image, ImageError},
+    interval::Interval,
+    material::Material,
+    ray::Ray,
+    triangle::intersect_triangle,
+    vec3::{Point3, Vec3},
+};
+
+// Terrain over a grid of height samples, one per image pixel. The grid spans `size.x` along x and
+// `size.z` along z from `corner`, with the top row of the image at the smallest z. Heights of 0
+// to 1 are scaled to `corner.y` up to `corner.y + size.y`.
+//
+// Each grid cell is split into two triangles that are built on the fly, so the memory used is a
+// single height per sample. Rays walk the cells they cross in order with a 2D DDA, skipping
+// cells whose heights the ray passes above or below.
+pub struct Heightfield {
+    heights: Vec<f64>,
+    // Samples along x and along z
+    columns: usize,
+    rows: usize,
+    corner: Point3,
+    size: Vec3,
+    cell_x: f64,
+    cell_z: f64,
+    mat: Arc<dyn Material>,
+    bbox: Aabb,
+}
+
+impl Heightfield {
+    // Heights are the mean of the color channels of each pixel
+    pub fn new(image: &Image, corner: Point3, size: Vec3, mat: Arc<dyn Material>) -> Self {
+        assert!(
+            image.width() >= 2 && image.height() >= 2,
+            "Heightfield images need at least 2x2 pixels"
+        );
+        assert!(
+            size.x() > 0.0 && size.z() > 0.0,
+            "Heightfield sizes along x and z must be positive"
+        );
+
+        let heights: Vec<f64> = image
+            .pixels()
+            .iter()
+            .map(|pixel| (pixel.x() + pixel.y() + pixel.z()) / 3.0)
+            .collect();
+
+        let columns = image.width();
+        let rows = image.height();
+        let (min_height, max_height) = heights
+            .iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &h| {
+                (lo.min(h), hi.max(h))
+            });
+
+        let bbox = Aabb::from_points(
+            Point3::with_values(corner.x(), corner.y() + min_height * size.y(), corner.z()),
+            Point3::with_values(
+                corner.x() + size.x(),
+                corner.y() + max_height * size.y(),
+                corner.z() + size.z(),
+            ),
+        );
+
+        Heightfield {
+            heights,
+            columns,
+            rows,
+            corner,
+            size,
+            cell_x: size.x() / (columns - 1) as f64,
+            cell_z: size.z() / (rows - 1) as f64,
+            mat,
+            bbox,
+        }
+    }
+
+    // Reads the heights from a grayscale PNG or PGM file, without gamma decoding
+    pub fn load(
+        path: &Path,
+        corner: Point3,
+        size: Vec3,
+        mat: Arc<dyn Material>,
+    ) -> Result<Self, ImageError> {
+        Ok(Heightfield::new(&Image::load_raw(path)?, corner, size, mat))
+    }
+
+    fn height(&self, i: usize, j: usize) -> f64 {
+        self.corner.y() + self.heights[j * self.columns + i] * self.size.y()
+    }
+
+    fn vertex(&self, i: usize, j: usize) -> Point3 {
+        Point3::with_values(
+            self.corner.x() + i as f64 * self.cell_x,
+            self.height(i, j),
+            self.corner.z() + j as f64 * self.cell_z,
+        )
+    }
+
+    // Normal of the smooth surface through the samples, by central differences (one-sided at
+    // the borders)
+    fn vertex_normal(&self, i: usize, j: usize) -> Vec3 {
+        let (i0, i1) = (i.saturating_sub(1), (i + 1).min(self.columns - 1));
+        let (j0, j1) = (j.saturating_sub(1), (j + 1).min(self.rows - 1));
+
+        let slope_x = (self.height(i1, j) - self.height(i0, j)) / ((i1 - i0) as f64 * self.cell_x);
+        let slope_z = (self.height(i, j1) - self.height(i, j0)) / ((j1 - j0) as f64 * self.cell_z);
+
+        Vec3::with_values(-slope_x, 1.0, -slope_z).unit_vector()
+    }
+
+    // Intersects the two triangles of cell (i, j), returning the closer hit
+    fn hit_cell(&self, r: &Ray, i: usize, j: usize, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let corners = [(i, j), (i, j + 1), (i + 1, j), (i + 1, j + 1)];
+        // Both wound so that their geometric normals face +y
+        let triangles = [[0, 1, 2], [3, 2, 1]];
+
+        let mut closest = ray_t.max;
+        let mut hit_anything = false;
+
+        for triangle in triangles {
+            let [a, b, c] = triangle.map(|k| corners[k]);
+            let (p0, p1, p2) = (
+                self.vertex(a.0, a.1),
+                self.vertex(b.0, b.1),
+                self.vertex(c.0, c.1),
+            );
+
+            let hit = match intersect_triangle(
+                r,
+                Interval::with_values(ray_t.min, closest),
+                &p0,
+                &p1,
+                &p2,
+            ) {
+                Some(hit) => hit,
+                None => continue,
+            };
+            closest = hit.t;
+            hit_anything = true;
+
+            rec.t = hit.t;
+            rec.p = r.at(hit.t);
+
+            let geometric_normal = (p1 - p0).cross(&(p2 - p0)).unit_vector();
+            rec.set_face_normal(r, &geometric_normal);
+
+            let shading_normal = hit.b0 * self.vertex_normal(a.0, a.1)
+                + hit.b1 * self.vertex_normal(b.0, b.1)
+                + hit.b2 * self.vertex_normal(c.0, c.1);
+            if !shading_normal.near_zero() {
+                rec.set_shading_normal(r, &shading_normal.unit_vector());
+            }
+
+            // (0, 0) at the bottom left of the image, as for image textures
+            rec.u = ((rec.p.x() - self.corner.x()) / self.size.x()).clamp(0.0, 1.0);
+            rec.v = 1.0 - ((rec.p.z() - self.corner.z()) / self.size.z()).clamp(0.0, 1.0);
+            // Steps along the triangle's plane that move u and v at the rate above
+            if geometric_normal.y().abs() > 1e-8 {
+                let slope_x = -geometric_normal.x() / geometric_normal.y();
+                let slope_z = -geometric_normal.z() / geometric_normal.y();
+                rec.dpdu = self.size.x() * Vec3::with_values(1.0, slope_x, 0.0);
+                rec.dpdv = -self.size.z() * Vec3::with_values(0.0, slope_z, 1.0);
+            } else {
+                rec.dpdu = Vec3::new();
+                rec.dpdv = Vec3::new();
+            }
+            rec.mat = self.mat.clone();
+        }
+
+        hit_anything
+    }
+}
+
+impl Hittable for Heightfield {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let span = match self.bbox.clip(r, ray_t) {
+            Some(span) => span,
+            None => return false,
+        };
+
+        let origin = r.origin();
+        let dir = r.direction();
+
+        // Cell containing the point where the ray enters the box
+        let start = r.at(span.min);
+        let cell = |offset: f64, cell_size: f64, cells: usize| {
+            ((offset / cell_size).floor().max(0.0) as usize).min(cells - 1)
+        };
+        let mut i = cell(start.x() - self.corner.x(), self.cell_x, self.columns - 1);
+        let mut j = cell(start.z() - self.corner.z(), self.cell_z, self.rows - 1);
+
+        // Ray distance to the next cell boundary along an axis, and between boundaries
+        let boundary = |index: usize, d: f64, o: f64, corner: f64, cell_size: f64| {
+            if d > 0.0 {
+                (
+                    (corner + (index + 1) as f64 * cell_size - o) / d,
+                    cell_size / d,
+                )
+            } else if d < 0.0 {
+                ((corner + index as f64 * cell_size - o) / d, -cell_size / d)
+            } else {
+                (f64::INFINITY, f64::INFINITY)
+            }
+        };
+        let (mut t_max_x, t_delta_x) =
+            boundary(i, dir.x(), origin.x(), self.corner.x(), self.cell_x);
+        let (mut t_max_z, t_delta_z) =
+            boundary(j, dir.z(), origin.z(), self.corner.z(), self.cell_z);
+
+        // Tolerance on the cell height ranges, so rays grazing a cell's top aren't culled
+        let epsilon = 1e-9 * (self.size.y().abs() + 1.0);
+        let mut t = span.min;
+
+        loop {
+            let t_exit = t_max_x.min(t_max_z).min(span.max);
+
+            let y_enter = origin.y() + t * dir.y();
+            let y_exit = origin.y() + t_exit * dir.y();
+            let heights = [
+                self.height(i, j),
+                self.height(i + 1, j),
+                self.height(i, j + 1),
+                self.height(i + 1, j + 1),
+            ];
+            let cell_min = heights.iter().cloned().fold(f64::INFINITY, f64::min);
+            let cell_max = heights.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+
+            if y_enter.max(y_exit) >= cell_min - epsilon
+                && y_enter.min(y_exit) <= cell_max + epsilon
+                && self.hit_cell(r, i, j, ray_t, rec)
+            {
+                return true;
+            }
+
+            if t_exit >= span.max {
+                return false;
+            }
+
+            if t_max_x < t_max_z {
+                if dir.x() > 0.0 {
+                    i += 1;
+                    if i >= self.columns - 1 {
+                        return false;
+                    }
+                } else {
+                    if i == 0 {
+                        return false;
+                    }
+                    i -= 1;
+                }
+                t = t_max_x;
+                t_max_x += t_delta_x;
+            } else {
+                if dir.z() > 0.0 {
+                    j += 1;
+                    if j >= self.rows - 1 {
+                        return false;
+                    }
+                } else {
+                    if j == 0 {
+                        return false;
+                    }
+                    j -= 1;
+                }
+                t = t_max_z;
+                t_max_z += t_delta_z;
+            }
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{color::Color, hittable::testing::hit, material::Lambertian};
+
+    // Field over [0, 5] x [0, 4] with heights of 0 to 1 given per sample
+    fn field(columns: usize, rows: usize, height: impl Fn(usize, usize) -> f64) -> Heightfield {
+        let mut image = Image::new(columns, rows);
+        for j in 0..rows {
+            for i in 0..columns {
+                let h = height(i, j);
+                image.set_pixel(i, j, Color::with_values(h, h, h));
+            }
+        }
+
+        let mat = Arc::new(Lambertian::new(Color::with_values(0.5, 0.5, 0.5)));
+        Heightfield::new(&image, Point3::new(), Vec3::with_values(5.0, 1.0, 4.0), mat)
+    }
+
+    // Closest hit over every cell, without the DDA
+    fn brute_force(field: &Heightfield, origin: Point3, direction: Vec3) -> Option<f64> {
+        let r = Ray::with_values(origin, direction);
+        let mut ray_t = Interval::with_values(0.001, f64::INFINITY);
+        let mut rec = HitRecord::new();
+        let mut closest = None;
+
+        for j in 0..field.rows - 1 {
+            for i in 0..field.columns - 1 {
+                if field.hit_cell(&r, i, j, ray_t, &mut rec) {
+                    ray_t.max = rec.t;
+                    closest = Some(rec.t);
+                }
+            }
+        }
+
+        closest
+    }
+
+    #[test]
+    fn walks_the_cells_a_ray_crosses() {
+        let bumpy = field(6, 5, |i, j| ((i * 7 + j * 3) % 5) as f64 / 4.0);
+
+        // Rays in every direction across the field, some axis aligned, some starting over it
+        let mut hits = 0;
+        for k in 0..64 {
+            let angle = k as f64 * 0.41;
+            let origin = Point3::with_values(
+                2.5 - 4.0 * angle.cos(),
+                1.5 + (k % 3) as f64 * 0.2,
+                2.0 - 3.0 * angle.sin() * (k % 2) as f64,
+            );
+            let direction = match k % 8 {
+                0 => Vec3::with_values(1.0, -0.2, 0.0),
+                1 => Vec3::with_values(0.0, -0.3, -1.0),
+                _ => Vec3::with_values(angle.cos(), -0.1 * (k % 5) as f64, angle.sin()),
+            };
+
+            let expected = brute_force(&bumpy, origin, direction);
+            let actual = hit(&bumpy, origin, direction).map(|rec| rec.t);
+            match (expected, actual) {
+                (Some(expected), Some(actual)) => {
+                    assert!((expected - actual).abs() < 1e-9, "ray {}", k);
+                    hits += 1;
+                }
+                (None, None) => {}
+                _ => panic!("ray {}: expected {:?}, got {:?}", k, expected, actual),
+            }
+        }
+        // Both hits and misses are checked
+        assert!(hits > 16 && hits < 48, "{} hits", hits);
+    }
+
+    #[test]
+    fn hits_at_the_edges_of_the_grid() {
+        let ramp = field(6, 5, |i, _| i as f64 / 5.0);
+
+        // Onto the corner of the last row of the grid, walking back across the cells, and
+        // straight down onto a cell boundary inside it
+        let corner = Point3::with_values(0.0, 0.0, 4.0);
+        for origin in [
+            Point3::with_values(4.5, 2.0, 0.5),
+            Point3::with_values(3.0, 1.5, 2.5),
+            Point3::with_values(0.5, 1.5, 3.5),
+        ] {
+            let rec = hit(&ramp, origin, corner - origin).unwrap();
+            assert!((rec.p - corner).length() < 1e-9, "{}", rec.p);
+        }
+        let rec = hit(
+            &ramp,
+            Point3::with_values(2.0, 3.0, 1.0),
+            Vec3::with_values(0.0, -1.0, 0.0),
+        );
+        assert!((rec.unwrap().t - 2.6).abs() < 1e-9);
+
+        // Level, in from past the high end, crossing cells until it meets the slope halfway up
+        let rec = hit(
+            &ramp,
+            Point3::with_values(6.0, 0.5, 2.0),
+            Vec3::with_values(-1.0, 0.0, 0.0),
+        );
+        assert!((rec.unwrap().t - 3.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn misses_a_flat_field() {
+        let flat = field(4, 4, |_, _| 0.0);
+
+        // Parallel to the field above it
+        assert!(hit(
+            &flat,
+            Point3::with_values(-1.0, 0.5, 2.0),
+            Vec3::with_values(1.0, 0.0, 0.2)
+        )
+        .is_none());
+        // Coming down past its far side
+        assert!(hit(
+            &flat,
+            Point3::with_values(1.0, 1.0, 2.0),
+            Vec3::with_values(5.0, -1.0, 0.0)
+        )
+        .is_none());
+        // Heading up, away from it
+        assert!(hit(
+            &flat,
+            Point3::with_values(2.0, 0.1, 2.0),
+            Vec3::with_values(0.3, 1.0, 0.0)
+        )
+        .is_none());
+
+        let rec = hit(
+            &flat,
+            Point3::with_values(1.0, 1.0, 2.0),
+            Vec3::with_values(1.0, -1.0, 0.5),
+        );
+        let rec = rec.unwrap();
+        assert!((rec.p.x() - 2.0).abs() < 1e-9 && rec.p.y().abs() < 1e-9);
+        assert!(rec.front_face);
+    }
+}
@@ -1,18 +1,33 @@
 use std::sync::Arc;
 
 use crate::{
+    aabb::Aabb,
     color::Color,
     interval::Interval,
     material::{Lambertian, Material},
     ray::Ray,
+    sampler::Sampler,
     vec3::{Point3, Vec3},
 };
 
+// Smallest cosine between a shading normal and the direction towards the viewer
+const MIN_VIEW_COSINE: f64 = 0.01;
+
 #[derive(Clone)]
 pub struct HitRecord {
     pub p: Point3,
+    // Shading normal, facing the incoming ray
     pub normal: Vec3,
+    // Normal of the surface itself, on the same side as `normal`, which shading normals bend away
+    // from
+    pub geometric_normal: Vec3,
     pub t: f64,
+    // Surface coordinates of the hit point
+    pub u: f64,
+    pub v: f64,
+    // Derivatives of the hit point along u and v, zero where the surface has no parameterization
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
     pub front_face: bool,
     pub mat: Arc<dyn Material>,
 }
@@ -22,22 +37,119 @@ impl HitRecord {
         HitRecord {
             p: Point3::new(),
             normal: Vec3::new(),
+            geometric_normal: Vec3::new(),
             t: 0.0,
+            u: 0.0,
+            v: 0.0,
+            dpdu: Vec3::new(),
+            dpdv: Vec3::new(),
             front_face: false,
             mat: Arc::new(Lambertian::new(Color::new())),
         }
     }
 
     pub fn set_face_normal(&mut self, r: &Ray, outward_normal: &Vec3) {
-        self.front_face = r.direction().dot(&outward_normal) < 0.0;
+        self.front_face = r.direction().dot(outward_normal) < 0.0;
         if self.front_face {
             self.normal = *outward_normal
         } else {
             self.normal = -(*outward_normal);
         }
+        self.geometric_normal = self.normal;
+    }
+
+    // Bends the shading normal to `shading_normal`, given facing out of the surface, on the side
+    // that was hit. Where `r` would arrive from below it, which shading models can't handle, it
+    // is tilted towards the viewer just enough to see it from above.
+    pub fn set_shading_normal(&mut self, r: &Ray, shading_normal: &Vec3) {
+        let normal = if self.front_face {
+            *shading_normal
+        } else {
+            -*shading_normal
+        };
+
+        let to_viewer = -r.direction().unit_vector();
+        let cos_theta = normal.dot(&to_viewer);
+        self.normal = if cos_theta < MIN_VIEW_COSINE {
+            (normal + (MIN_VIEW_COSINE - cos_theta) * to_viewer).unit_vector()
+        } else {
+            normal
+        };
+    }
+
+    // Whether `direction` leaves on the same side of the surface for the shading and the
+    // geometric normal. Light sent one way by the shading normal and the other way by the
+    // geometry would leak through the surface.
+    pub fn is_consistent(&self, direction: &Vec3) -> bool {
+        (direction.dot(&self.normal) > 0.0) == (direction.dot(&self.geometric_normal) > 0.0)
     }
 }
 
-pub trait Hittable {
+pub trait Hittable: Send + Sync {
     fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool;
+
+    fn bounding_box(&self) -> Aabb;
+
+    // Solid angle density of `random` picking `direction` from `origin`, for objects that can be
+    // sampled as lights.
+    fn pdf_value(&self, _origin: &Point3, _direction: &Vec3) -> f64 {
+        0.0
+    }
+
+    // Random direction from `origin` towards this object
+    fn random(&self, _origin: &Point3, _sampler: &mut Sampler) -> Vec3 {
+        Vec3::with_values(1.0, 0.0, 0.0)
+    }
+}
+
+// Helpers shared by the tests of the individual primitives
+#[cfg(test)]
+pub mod testing {
+    use super::*;
+
+    pub fn hit(object: &dyn Hittable, origin: Point3, direction: Vec3) -> Option<HitRecord> {
+        let mut rec = HitRecord::new();
+        let ray = Ray::with_values(origin, direction);
+        if !object.hit(&ray, Interval::with_values(0.001, f64::INFINITY), &mut rec) {
+            return None;
+        }
+
+        Some(rec)
+    }
+
+    pub fn assert_near(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-9, "{} != {}", a, b);
+    }
+
+    // Checks that `pdf_value` is the density `random` picks directions from `origin` with. Its
+    // mean reciprocal over those directions is the solid angle the object covers, which is
+    // estimated independently by the fraction of uniform directions that hit it.
+    pub fn assert_pdf_matches_solid_angle(object: &dyn Hittable, origin: Point3) {
+        const SAMPLES: usize = 200_000;
+        let mut sampler = Sampler::new(31);
+
+        let mut from_pdf = 0.0;
+        for _ in 0..SAMPLES {
+            let direction = object.random(&origin, &mut sampler);
+            let pdf = object.pdf_value(&origin, &direction);
+            assert!(pdf > 0.0, "no density towards {}", direction);
+            from_pdf += 1.0 / pdf / SAMPLES as f64;
+        }
+
+        let mut hits = 0;
+        for _ in 0..SAMPLES {
+            let direction = Vec3::random_unit_vector(&mut sampler);
+            if hit(object, origin, direction).is_some() {
+                hits += 1;
+            }
+        }
+        let from_hits = 4.0 * std::f64::consts::PI * hits as f64 / SAMPLES as f64;
+
+        assert!(
+            (from_pdf / from_hits - 1.0).abs() < 0.03,
+            "solid angle {} from the pdf but {} from hits",
+            from_pdf,
+            from_hits
+        );
+    }
 }
@@ -1,26 +1,46 @@
 use crate::{
+    aabb::Aabb,
     hittable::{HitRecord, Hittable},
     interval::Interval,
+    sampler::Sampler,
+    vec3::{Point3, Vec3},
 };
 
 pub struct HittableList {
     pub list: Vec<Box<dyn Hittable>>,
+    bbox: Aabb,
 }
 
 impl HittableList {
     pub fn new() -> HittableList {
-        HittableList { list: Vec::new() }
+        HittableList {
+            list: Vec::new(),
+            bbox: Aabb::EMPTY,
+        }
     }
 
     pub fn with_values(list: Vec<Box<dyn Hittable>>) -> HittableList {
-        HittableList { list }
+        let bbox = list.iter().fold(Aabb::EMPTY, |bbox, object| {
+            Aabb::surrounding(&bbox, &object.bounding_box())
+        });
+        HittableList { list, bbox }
     }
 
     pub fn clear(&mut self) {
         self.list.clear();
+        self.bbox = Aabb::EMPTY;
+    }
+
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
     }
 
     pub fn add(&mut self, hittable: Box<dyn Hittable>) {
+        self.bbox = Aabb::surrounding(&self.bbox, &hittable.bounding_box());
         self.list.push(hittable);
     }
 }
@@ -50,4 +70,30 @@ impl Hittable for HittableList {
 
         return hit_anything;
     }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        if self.list.is_empty() {
+            return 0.0;
+        }
+
+        let weight = 1.0 / self.list.len() as f64;
+        self.list
+            .iter()
+            .map(|object| weight * object.pdf_value(origin, direction))
+            .sum()
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut Sampler) -> Vec3 {
+        if self.list.is_empty() {
+            return Vec3::with_values(1.0, 0.0, 0.0);
+        }
+
+        let index =
+            ((sampler.random_f64() * self.list.len() as f64) as usize).min(self.list.len() - 1);
+        self.list[index].random(origin, sampler)
+    }
 }
@@ -0,0 +1,396 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+use crate::color::{gamma_to_linear, Color};
+
+#[derive(Debug)]
+pub enum ImageError {
+    Io { path: PathBuf, error: io::Error },
+    Decode { path: PathBuf, message: String },
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImageError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            ImageError::Decode { path, message } => write!(f, "{}: {}", path.display(), message),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {}
+
+// Largest texture accepted from a file, so a corrupt header cannot ask for an absurd buffer
+const MAX_PIXELS: usize = 100_000_000;
+
+// Row-major grid of linear, unclamped RGB pixels, top row first
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Image {
+        Image {
+            width,
+            height,
+            pixels: vec![Color::new(); width * height],
+        }
+    }
+
+    pub fn from_pixels(width: usize, height: usize, pixels: Vec<Color>) -> Image {
+        assert_eq!(
+            pixels.len(),
+            width * height,
+            "Pixel count must match the image size"
+        );
+        Image {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: Color) {
+        self.pixels[y * self.width + x] = color;
+    }
+
+    // Loads a PNG or a binary/ASCII PPM or PGM file. Values are treated as gamma encoded and
+    // converted back to linear color.
+    pub fn load(path: &Path) -> Result<Image, ImageError> {
+        Image::read(path, true)
+    }
+
+    // Loads the stored values scaled to [0, 1] as they are, for data such as height maps
+    pub fn load_raw(path: &Path) -> Result<Image, ImageError> {
+        Image::read(path, false)
+    }
+
+    fn read(path: &Path, gamma_encoded: bool) -> Result<Image, ImageError> {
+        let io_error = |error| ImageError::Io {
+            path: path.to_path_buf(),
+            error,
+        };
+        let mut bytes = Vec::new();
+        File::open(path)
+            .and_then(|file| BufReader::new(file).read_to_end(&mut bytes))
+            .map_err(io_error)?;
+
+        let decoded = if bytes.starts_with(b"\x89PNG") {
+            Image::decode_png(&bytes, gamma_encoded)
+        } else if bytes.first() == Some(&b'P') {
+            Image::decode_pnm(&bytes, gamma_encoded)
+        } else {
+            Err("unrecognized image format".to_string())
+        };
+
+        decoded.map_err(|message| ImageError::Decode {
+            path: path.to_path_buf(),
+            message,
+        })
+    }
+
+    fn decode_png(bytes: &[u8], gamma_encoded: bool) -> Result<Image, String> {
+        let mut decoder = png::Decoder::new(bytes);
+        // Palettes and low bit depths are expanded to 8 bits, 16-bit samples are kept
+        decoder.set_transformations(png::Transformations::EXPAND);
+        let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+        let header = reader.info();
+        Image::check_size(header.width as usize, header.height as usize)?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).map_err(|e| e.to_string())?;
+
+        let channels = info.color_type.samples();
+        let (sample_size, max_value) = match info.bit_depth {
+            png::BitDepth::Sixteen => (2, 65535),
+            _ => (1, 255),
+        };
+        let mut image = Image::new(info.width as usize, info.height as usize);
+
+        for y in 0..image.height {
+            let row = &buffer[y * info.line_size..];
+            for x in 0..image.width {
+                let texel = &row[x * channels * sample_size..];
+                let sample = |channel: usize| {
+                    let i = channel * sample_size;
+                    if sample_size == 1 {
+                        texel[i] as u32
+                    } else {
+                        (texel[i] as u32) << 8 | texel[i + 1] as u32
+                    }
+                };
+                let (r, g, b) = if channels < 3 {
+                    (sample(0), sample(0), sample(0))
+                } else {
+                    (sample(0), sample(1), sample(2))
+                };
+                image.set_pixel(
+                    x,
+                    y,
+                    Image::color_from_samples(r, g, b, max_value, gamma_encoded),
+                );
+            }
+        }
+
+        Ok(image)
+    }
+
+    // Netpbm family: P2/P5 gray maps and P3/P6 pixel maps, ASCII or binary respectively
+    fn decode_pnm(bytes: &[u8], gamma_encoded: bool) -> Result<Image, String> {
+        let magic = bytes.get(..2).ok_or("truncated header")?;
+        let (channels, binary) = match magic {
+            b"P2" => (1, false),
+            b"P3" => (3, false),
+            b"P5" => (1, true),
+            b"P6" => (3, true),
+            _ => {
+                return Err(format!(
+                    "unsupported netpbm type {}",
+                    String::from_utf8_lossy(magic)
+                ))
+            }
+        };
+
+        let mut pos = 2;
+        let mut header = [0u32; 3];
+        for value in header.iter_mut() {
+            *value = Image::next_pnm_number(bytes, &mut pos)?;
+        }
+        let [width, height, max_value] = header;
+        if max_value == 0 || max_value > 65535 {
+            return Err(format!("invalid maximum value {}", max_value));
+        }
+
+        // The header is untrusted, so the raster size is checked against the file before allocating
+        let sample_count = Image::check_size(width as usize, height as usize)? * channels;
+
+        let samples = if binary {
+            // A single whitespace byte separates the header from the raster
+            pos += 1;
+            let sample_size = if max_value < 256 { 1 } else { 2 };
+            let raster = sample_count
+                .checked_mul(sample_size)
+                .and_then(|len| bytes.get(pos..)?.get(..len))
+                .ok_or("truncated pixel data")?;
+            raster
+                .chunks(sample_size)
+                .map(|sample| {
+                    if sample_size == 1 {
+                        sample[0] as u32
+                    } else {
+                        (sample[0] as u32) << 8 | sample[1] as u32
+                    }
+                })
+                .collect::<Vec<_>>()
+        } else {
+            // Every ASCII sample takes at least one byte
+            if sample_count > bytes.len().saturating_sub(pos) {
+                return Err("truncated pixel data".to_string());
+            }
+            let mut samples = Vec::with_capacity(sample_count);
+            for _ in 0..sample_count {
+                samples.push(Image::next_pnm_number(bytes, &mut pos)?);
+            }
+            samples
+        };
+
+        let mut image = Image::new(width as usize, height as usize);
+        for (i, texel) in samples.chunks(channels).enumerate() {
+            let (r, g, b) = if channels == 1 {
+                (texel[0], texel[0], texel[0])
+            } else {
+                (texel[0], texel[1], texel[2])
+            };
+            image.pixels[i] = Image::color_from_samples(r, g, b, max_value, gamma_encoded);
+        }
+
+        Ok(image)
+    }
+
+    // Returns the pixel count of an image of the given size, if it is within the limit
+    fn check_size(width: usize, height: usize) -> Result<usize, String> {
+        width
+            .checked_mul(height)
+            .filter(|&pixels| pixels <= MAX_PIXELS)
+            .ok_or_else(|| {
+                format!(
+                    "image of {} by {} pixels is larger than the limit of {} pixels",
+                    width, height, MAX_PIXELS
+                )
+            })
+    }
+
+    fn next_pnm_number(bytes: &[u8], pos: &mut usize) -> Result<u32, String> {
+        loop {
+            match bytes.get(*pos) {
+                Some(b'#') => {
+                    while bytes.get(*pos).is_some_and(|&c| c != b'\n') {
+                        *pos += 1;
+                    }
+                }
+                Some(c) if c.is_ascii_whitespace() => *pos += 1,
+                Some(_) => break,
+                None => return Err("unexpected end of file".to_string()),
+            }
+        }
+
+        let start = *pos;
+        while bytes.get(*pos).is_some_and(|c| c.is_ascii_digit()) {
+            *pos += 1;
+        }
+
+        std::str::from_utf8(&bytes[start..*pos])
+            .ok()
+            .and_then(|digits| digits.parse().ok())
+            .ok_or_else(|| format!("invalid number at byte {}", start))
+    }
+
+    fn color_from_samples(r: u32, g: u32, b: u32, max_value: u32, gamma_encoded: bool) -> Color {
+        let scale = 1.0 / max_value as f64;
+        let decode = |sample: u32| {
+            let value = sample as f64 * scale;
+            if gamma_encoded {
+                gamma_to_linear(value)
+            } else {
+                value
+            }
+        };
+
+        Color::with_values(decode(r), decode(g), decode(b))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_writer::write_png;
+
+    fn assert_color(actual: Color, expected: [f64; 3]) {
+        for channel in 0..3 {
+            assert!(
+                (actual[channel] - expected[channel]).abs() < 1e-9,
+                "{:?} != {:?}",
+                [actual[0], actual[1], actual[2]],
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn decodes_ascii_pixmaps() {
+        let bytes = b"P3\n# comment\n2 1\n255\n255 0 0  0 51 255\n";
+        let image = Image::decode_pnm(bytes, false).unwrap();
+        assert_eq!((image.width(), image.height()), (2, 1));
+        assert_color(image.pixel(0, 0), [1.0, 0.0, 0.0]);
+        assert_color(image.pixel(1, 0), [0.0, 0.2, 1.0]);
+    }
+
+    #[test]
+    fn decodes_binary_gray_maps() {
+        let mut bytes = b"P5 2 2 255\n".to_vec();
+        bytes.extend([0, 51, 255, 102]);
+        let image = Image::decode_pnm(&bytes, false).unwrap();
+        assert_color(image.pixel(1, 0), [0.2, 0.2, 0.2]);
+        assert_color(image.pixel(1, 1), [0.4, 0.4, 0.4]);
+
+        // Sixteen-bit samples are big endian
+        let mut bytes = b"P5 1 1 65535\n".to_vec();
+        bytes.extend([0xff, 0xff]);
+        let image = Image::decode_pnm(&bytes, false).unwrap();
+        assert_color(image.pixel(0, 0), [1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn decodes_binary_pixmaps() {
+        let mut bytes = b"P6\n1 2\n255\n".to_vec();
+        bytes.extend([255, 0, 0, 0, 0, 255]);
+        let image = Image::decode_pnm(&bytes, true).unwrap();
+        assert_color(image.pixel(0, 0), [1.0, 0.0, 0.0]);
+        assert_color(image.pixel(0, 1), [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn rejects_truncated_pixmaps() {
+        let mut bytes = b"P6 2 2 255\n".to_vec();
+        bytes.extend([0; 11]);
+        assert!(Image::decode_pnm(&bytes, false).is_err());
+        assert!(Image::decode_pnm(b"P3 2 1 255\n1 2 3 4 5", false).is_err());
+        assert!(Image::decode_pnm(b"P5 2", false).is_err());
+        assert!(Image::decode_pnm(b"P", false).is_err());
+    }
+
+    #[test]
+    fn rejects_oversized_headers_without_allocating() {
+        // Overflows a 64-bit sample count
+        let header = b"P6 4000000000 4000000000 255\n";
+        assert!(Image::decode_pnm(header, false).is_err());
+        // Within the pixel limit, but the raster would be far larger than the file
+        assert!(Image::decode_pnm(b"P6 10000 10000 65535\n", false).is_err());
+        assert!(Image::decode_pnm(b"P3 10000 10000 255\n0 0 0", false).is_err());
+        assert!(Image::decode_pnm(b"P6 1 1 70000\n", false).is_err());
+    }
+
+    #[test]
+    fn decodes_png_round_trip() {
+        let mut source = Image::new(2, 1);
+        source.set_pixel(0, 0, Color::with_values(1.0, 0.0, 0.0));
+        source.set_pixel(1, 0, Color::with_values(0.0, 0.0, 1.0));
+        let mut bytes = Vec::new();
+        write_png(&source, &mut bytes).unwrap();
+
+        let image = Image::decode_png(&bytes, true).unwrap();
+        assert_eq!((image.width(), image.height()), (2, 1));
+        assert_color(image.pixel(0, 0), [1.0, 0.0, 0.0]);
+        assert_color(image.pixel(1, 0), [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn rejects_broken_pngs() {
+        let mut bytes = Vec::new();
+        write_png(&Image::new(4, 4), &mut bytes).unwrap();
+        assert!(Image::decode_png(&bytes[..bytes.len() / 2], false).is_err());
+
+        // Valid header claiming a huge image, which the decoder's memory limit turns away
+        let mut oversized = bytes.clone();
+        oversized[16..24].copy_from_slice(&[0x00, 0x10, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00]);
+        let crc = crc32(&oversized[12..29]);
+        oversized[29..33].copy_from_slice(&crc.to_be_bytes());
+        assert!(Image::decode_png(&oversized, false).is_err());
+    }
+
+    fn crc32(bytes: &[u8]) -> u32 {
+        let mut crc = !0u32;
+        for &byte in bytes {
+            crc ^= byte as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 == 1 {
+                    crc >> 1 ^ 0xedb8_8320
+                } else {
+                    crc >> 1
+                };
+            }
+        }
+        !crc
+    }
+}
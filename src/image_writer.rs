@@ -0,0 +1,198 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use crate::{
+    color::{color_to_bytes, write_color},
+    image::Image,
+};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ImageFormat {
+    // ASCII P3 portable pixmap
+    Ppm,
+    // Binary P6 portable pixmap
+    PpmBinary,
+    // Portable float map, keeps the linear high dynamic range values
+    Pfm,
+    Png,
+}
+
+impl ImageFormat {
+    pub const NAMES: [&str; 4] = ["ppm", "ppm-binary", "pfm", "png"];
+
+    pub fn from_name(name: &str) -> Option<ImageFormat> {
+        match name.to_ascii_lowercase().as_str() {
+            "ppm" | "p3" => Some(ImageFormat::Ppm),
+            "ppm-binary" | "p6" => Some(ImageFormat::PpmBinary),
+            "pfm" => Some(ImageFormat::Pfm),
+            "png" => Some(ImageFormat::Png),
+            _ => None,
+        }
+    }
+
+    // Format implied by a file extension; `.ppm` keeps the ASCII variant
+    pub fn from_extension(path: &Path) -> Option<ImageFormat> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "ppm" => Some(ImageFormat::Ppm),
+            "pfm" => Some(ImageFormat::Pfm),
+            "png" => Some(ImageFormat::Png),
+            _ => None,
+        }
+    }
+}
+
+// Takes the output already opened, so a bad path fails before the render rather than after it
+pub fn write_image(image: &Image, file: File, format: ImageFormat) -> io::Result<()> {
+    let mut out = BufWriter::new(file);
+
+    match format {
+        ImageFormat::Ppm => write_ppm(image, &mut out)?,
+        ImageFormat::PpmBinary => write_ppm_binary(image, &mut out)?,
+        ImageFormat::Pfm => write_pfm(image, &mut out)?,
+        ImageFormat::Png => write_png(image, &mut out)?,
+    }
+
+    out.flush()
+}
+
+pub fn write_ppm<W: Write>(image: &Image, out: &mut W) -> io::Result<()> {
+    let image_width = image.width();
+    let image_height = image.height();
+    let header = format!("P3 \n{image_width} {image_height} \n255\n");
+    out.write_all(header.as_bytes())?;
+
+    for pixel_color in image.pixels() {
+        write_color(out, *pixel_color)?;
+    }
+
+    Ok(())
+}
+
+pub fn write_ppm_binary<W: Write>(image: &Image, out: &mut W) -> io::Result<()> {
+    write!(out, "P6\n{} {}\n255\n", image.width(), image.height())?;
+    out.write_all(&to_bytes(image))
+}
+
+// Colors are stored as little endian 32-bit floats, with the bottom row first
+pub fn write_pfm<W: Write>(image: &Image, out: &mut W) -> io::Result<()> {
+    write!(out, "PF\n{} {}\n-1.0\n", image.width(), image.height())?;
+
+    for y in (0..image.height()).rev() {
+        for x in 0..image.width() {
+            let pixel_color = image.pixel(x, y);
+            for channel in 0..3 {
+                out.write_all(&(pixel_color[channel] as f32).to_le_bytes())?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub fn write_png<W: Write>(image: &Image, out: &mut W) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(out, image.width() as u32, image.height() as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+    writer
+        .write_image_data(&to_bytes(image))
+        .map_err(io::Error::other)?;
+
+    writer.finish().map_err(io::Error::other)
+}
+
+fn to_bytes(image: &Image) -> Vec<u8> {
+    image
+        .pixels()
+        .iter()
+        .flat_map(|pixel_color| color_to_bytes(*pixel_color))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+
+    // Linear colors whose gamma encoded bytes are, row by row, (128, 0, 255) (255, 25, 0) and
+    // (204, 230, 10) (255, 255, 255), with values above 1 and below 0 clamped
+    fn image() -> Image {
+        let mut image = Image::new(2, 2);
+        image.set_pixel(0, 0, Color::with_values(0.25, 0.0, 1.0));
+        image.set_pixel(1, 0, Color::with_values(4.0, 0.01, -1.0));
+        image.set_pixel(0, 1, Color::with_values(0.64, 0.81, 0.0016));
+        image.set_pixel(1, 1, Color::with_values(1.0, 1.0, 1.0));
+        image
+    }
+
+    const BYTES: [u8; 12] = [128, 0, 255, 255, 25, 0, 204, 230, 10, 255, 255, 255];
+
+    #[test]
+    fn writes_ascii_pixmaps() {
+        let mut out = Vec::new();
+        write_ppm(&image(), &mut out).unwrap();
+
+        let expected = "P3 \n2 2 \n255\n128 0 255\n255 25 0\n204 230 10\n255 255 255\n";
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn writes_binary_pixmaps() {
+        let mut out = Vec::new();
+        write_ppm_binary(&image(), &mut out).unwrap();
+
+        let mut expected = b"P6\n2 2\n255\n".to_vec();
+        expected.extend(BYTES);
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn writes_float_maps_bottom_row_first() {
+        let image = image();
+        let mut out = Vec::new();
+        write_pfm(&image, &mut out).unwrap();
+
+        let header = b"PF\n2 2\n-1.0\n";
+        assert_eq!(&out[..header.len()], header);
+
+        // Linear values as they are, unclamped
+        let floats: Vec<f32> = out[header.len()..]
+            .chunks_exact(4)
+            .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect();
+        let mut expected = Vec::new();
+        for (x, y) in [(0, 1), (1, 1), (0, 0), (1, 0)] {
+            let pixel = image.pixel(x, y);
+            expected.extend([pixel.x() as f32, pixel.y() as f32, pixel.z() as f32]);
+        }
+        assert_eq!(floats, expected);
+    }
+
+    #[test]
+    fn png_round_trips_through_the_loader() {
+        let path = std::env::temp_dir().join(format!(
+            "image-writer-test-{}-{:?}.png",
+            std::process::id(),
+            std::thread::current().id()
+        ));
+        write_image(&image(), File::create(&path).unwrap(), ImageFormat::Png).unwrap();
+        let loaded = Image::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+
+        assert_eq!((loaded.width(), loaded.height()), (2, 2));
+        let bytes: Vec<u8> = loaded
+            .pixels()
+            .iter()
+            .flat_map(|pixel| {
+                // Back to the gamma encoded 8-bit values that were stored
+                [pixel.x(), pixel.y(), pixel.z()].map(|c| (c.sqrt() * 255.0).round() as u8)
+            })
+            .collect();
+        assert_eq!(bytes, BYTES);
+    }
+}
@@ -1,5 +1,6 @@
 use core::f64;
 
+#[derive(Clone, Copy)]
 pub struct Interval {
     pub min: f64,
     pub max: f64,
@@ -17,6 +18,22 @@ impl Interval {
         Interval { min, max }
     }
 
+    // Tightest interval enclosing both a and b
+    pub fn enclosing(a: &Interval, b: &Interval) -> Interval {
+        Interval {
+            min: a.min.min(b.min),
+            max: a.max.max(b.max),
+        }
+    }
+
+    // Overlap of a and b, empty when they are disjoint
+    pub fn intersection(a: &Interval, b: &Interval) -> Interval {
+        Interval {
+            min: a.min.max(b.min),
+            max: a.max.min(b.max),
+        }
+    }
+
     pub fn size(&self) -> f64 {
         self.max - self.min
     }
@@ -39,6 +56,11 @@ impl Interval {
         value
     }
 
+    pub fn expand(&self, delta: f64) -> Interval {
+        let padding = delta / 2.0;
+        Interval::with_values(self.min - padding, self.max + padding)
+    }
+
     pub const EMPTY: Interval = Interval {
         min: f64::INFINITY,
         max: f64::NEG_INFINITY,
@@ -0,0 +1,257 @@
+use std::sync::Arc;
+
+use crate::{
+    color::Color,
+    hittable::HitRecord,
+    material::Material,
+    microfacet::MicrofacetDistribution,
+    ray::Ray,
+    rough_dielectric::RoughDielectric,
+    sampler::Sampler,
+    spectrum::{in_spectrum, Wavelengths},
+    vec3::Vec3,
+};
+
+// Bounces between the coat and the base followed before the light is taken as absorbed
+const MAX_BOUNCES: usize = 32;
+
+// Dielectric coat over any base material, such as the clearcoat of car paint or the varnish on
+// wood. Light entering the coat bounces between its surface and the base until it leaves, which
+// is followed one scattering event at a time with the coat's rough interface and the base's own
+// `scatter`. Every step attenuates the light, so the layers never reflect more than arrives.
+//
+// The layers are stacked on the side of the surface the ray arrives from. Since the paths
+// through them are random, the material has no density for light sampling to weigh against and
+// is lit only by the rays it scatters.
+pub struct Layered {
+    base: Arc<dyn Material>,
+    interface: RoughDielectric,
+    // Color of the coat at unit thickness, light crossing it straight is tinted by
+    // `tint^thickness`
+    tint: Color,
+    thickness: f64,
+}
+
+impl Layered {
+    pub fn new(
+        base: Arc<dyn Material>,
+        ior: f64,
+        distribution: Box<dyn MicrofacetDistribution>,
+        tint: Color,
+        thickness: f64,
+    ) -> Self {
+        Layered {
+            base,
+            interface: RoughDielectric::new(ior, distribution),
+            tint,
+            thickness,
+        }
+    }
+
+    // Transmittance of the coat along `ray`, which crosses it at an angle to `normal`, at the
+    // ray's wavelengths when the layers are `spectral`
+    fn coat_transmittance(&self, ray: &Ray, normal: &Vec3, spectral: bool) -> Color {
+        let cos_theta = ray.direction().unit_vector().dot(normal).abs().max(1e-6);
+        let distance = self.thickness / cos_theta;
+
+        let transmittance = Color::with_values(
+            self.tint.x().max(0.0).powf(distance),
+            self.tint.y().max(0.0).powf(distance),
+            self.tint.z().max(0.0).powf(distance),
+        );
+        if spectral {
+            return in_spectrum(ray, transmittance);
+        }
+
+        transmittance
+    }
+}
+
+impl Material for Layered {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+        sampler: &mut Sampler,
+    ) -> bool {
+        let up = rec.normal;
+        let spectral = self.is_spectral(ray_in);
+        let mut wavelengths = ray_in.wavelengths();
+        let mut weight = Color::with_values(1.0, 1.0, 1.0);
+
+        // The same hit seen from above the coat, from inside it onto the base, and from inside
+        // it onto its surface
+        let mut top = rec.clone();
+        top.front_face = true;
+
+        let mut base = rec.clone();
+        base.t = 1.0;
+
+        let mut top_from_below = rec.clone();
+        top_from_below.normal = -up;
+        top_from_below.geometric_normal = -rec.geometric_normal;
+        top_from_below.front_face = false;
+        top_from_below.t = 1.0;
+
+        // Reflect off the coat or refract into it
+        let mut direction = match scatter_step(
+            &self.interface,
+            ray_in,
+            &top,
+            spectral,
+            &mut weight,
+            &mut wavelengths,
+            sampler,
+        ) {
+            Some(direction) => direction,
+            None => return false,
+        };
+
+        let mut bounces = 0;
+        while direction.dot(&up) <= 0.0 {
+            if bounces == MAX_BOUNCES {
+                return false;
+            }
+            bounces += 1;
+
+            // Down through the coat to the base, which has to send the light back up
+            let down = inner_ray(rec, direction, ray_in.time(), wavelengths);
+            weight = weight * self.coat_transmittance(&down, &up, spectral);
+            direction = match scatter_step(
+                self.base.as_ref(),
+                &down,
+                &base,
+                spectral,
+                &mut weight,
+                &mut wavelengths,
+                sampler,
+            ) {
+                Some(direction) if direction.dot(&up) > 0.0 => direction,
+                _ => return false,
+            };
+
+            // Up through the coat, out of it or back down
+            let rising = inner_ray(rec, direction, ray_in.time(), wavelengths);
+            weight = weight * self.coat_transmittance(&rising, &up, spectral);
+            direction = match scatter_step(
+                &self.interface,
+                &rising,
+                &top_from_below,
+                spectral,
+                &mut weight,
+                &mut wavelengths,
+                sampler,
+            ) {
+                Some(direction) => direction,
+                None => return false,
+            };
+        }
+
+        *attenuation = weight;
+        *scattered = Ray::with_time(rec.p, direction, ray_in.time());
+        scattered.set_wavelengths(wavelengths);
+
+        return true;
+    }
+
+    // A spectral base makes the whole stack spectral
+    fn is_spectral(&self, ray_in: &Ray) -> bool {
+        self.base.is_spectral(ray_in)
+    }
+}
+
+// Scatters `ray` off `material` at `surface`, multiplying `weight` by the attenuation, taken at
+// the ray's wavelengths when the layers are `spectral`, and keeping the wavelengths the material
+// leaves the ray with
+fn scatter_step(
+    material: &dyn Material,
+    ray: &Ray,
+    surface: &HitRecord,
+    spectral: bool,
+    weight: &mut Color,
+    wavelengths: &mut Option<Wavelengths>,
+    sampler: &mut Sampler,
+) -> Option<Vec3> {
+    let mut attenuation = Color::new();
+    let mut scattered = Ray::new();
+    if !material.scatter(ray, surface, &mut attenuation, &mut scattered, sampler) {
+        return None;
+    }
+
+    if spectral && !material.is_spectral(ray) {
+        attenuation = in_spectrum(ray, attenuation);
+    }
+    *weight = *weight * attenuation;
+    if scattered.wavelengths().is_some() {
+        *wavelengths = scattered.wavelengths();
+    }
+
+    Some(scattered.direction())
+}
+
+// Ray inside the coat that reaches the hit point of `rec` going along `direction`
+fn inner_ray(rec: &HitRecord, direction: Vec3, time: f64, wavelengths: Option<Wavelengths>) -> Ray {
+    let mut ray = Ray::with_time(rec.p - direction, direction, time);
+    ray.set_wavelengths(wavelengths);
+    ray
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        conductor::Conductor,
+        material::{testing::directional_albedo, Lambertian},
+        microfacet::Ggx,
+        thin_film::ThinFilm,
+    };
+
+    // Clear coats over white bases may lose light to single scattering, but must never reflect
+    // more than arrives. Single samples can weigh more than one, such as the reflections of the
+    // filmed glass base, whose color channels are sampled with their mean reflectance.
+    #[test]
+    fn white_furnace_never_gains_energy() {
+        let white = Color::with_values(1.0, 1.0, 1.0);
+        let mut filmed_glass = RoughDielectric::new(1.5, Box::new(Ggx::isotropic(0.2)));
+        filmed_glass.film = Some(ThinFilm::new(1.33, 0.4));
+        let bases: [(&str, Arc<dyn Material>); 3] = [
+            ("lambertian", Arc::new(Lambertian::new(white))),
+            (
+                "conductor",
+                Arc::new(Conductor::new(
+                    Color::new(),
+                    Color::with_values(1e3, 1e3, 1e3),
+                    Box::new(Ggx::isotropic(0.3)),
+                )),
+            ),
+            ("filmed glass", Arc::new(filmed_glass)),
+        ];
+        let mut sampler = Sampler::new(9);
+
+        for (name, base) in bases {
+            for alpha in [0.001, 0.3] {
+                let mat = Layered::new(
+                    base.clone(),
+                    1.5,
+                    Box::new(Ggx::isotropic(alpha)),
+                    white,
+                    0.1,
+                );
+
+                for cos_theta in [1.0, 0.7, 0.3, 0.1] {
+                    let albedo = directional_albedo(&mat, cos_theta, &mut sampler);
+                    assert!(
+                        albedo.x() <= 1.01 && albedo.y() <= 1.01 && albedo.z() <= 1.01,
+                        "{} base under a coat with alpha {} at cos {} reflects {}",
+                        name,
+                        alpha,
+                        cos_theta,
+                        albedo
+                    );
+                }
+            }
+        }
+    }
+}
@@ -1,72 +1,149 @@
-use std::sync::Arc;
+#![allow(clippy::needless_return, clippy::new_without_default)]
 
-use camera::Camera;
-use color::Color;
-use hittable_list::HittableList;
-use material::{Dielectric, Lambertian, Metal};
-use sphere::Sphere;
-use vec3::Point3;
+use std::{env, error::Error, fs::File, path::Path, process, time::Instant};
 
+use bvh::BvhNode;
+use cli::{Command, Options, Verbosity};
+
+pub mod aabb;
+pub mod bvh;
 pub mod camera;
+pub mod cli;
 pub mod color;
+pub mod conductor;
+pub mod cone;
+pub mod constant_medium;
+pub mod csg;
+pub mod cylinder;
+pub mod disk;
+pub mod ellipsoid;
+pub mod heightfield;
 pub mod hittable;
 pub mod hittable_list;
+pub mod image;
+pub mod image_writer;
 pub mod interval;
+pub mod layered;
+pub mod mat4;
 pub mod material;
+pub mod mesh;
+pub mod microfacet;
+pub mod normal_map;
+pub mod obj;
+pub mod onb;
+pub mod principled;
+pub mod quad;
 pub mod ray;
+pub mod rough_dielectric;
+pub mod sampler;
+pub mod scene_file;
+pub mod scenes;
+pub mod sdf;
+pub mod spectrum;
 pub mod sphere;
+pub mod subsurface;
+pub mod texture;
+pub mod thin_film;
+pub mod torus;
+pub mod transform;
+pub mod triangle;
 pub mod utils;
 pub mod vec3;
 
 fn main() {
-    let mut world = HittableList::new();
-    let material_ground = Arc::new(Lambertian::new(Color::with_values(0.8, 0.8, 0.0)));
-    let material_center = Arc::new(Lambertian::new(Color::with_values(0.1, 0.2, 0.5)));
-    let material_left = Arc::new(Dielectric::new(1.50));
-    let material_bubble = Arc::new(Dielectric::new(1.00 / 1.50));
-    let material_right = Arc::new(Metal::new(Color::with_values(0.8, 0.6, 0.2), 1.0));
-
-    world.add(Box::new(Sphere::new(
-        Point3::with_values(0.0, -100.5, -1.0),
-        100.0,
-        material_ground,
-    )));
-
-    world.add(Box::new(Sphere::new(
-        Point3::with_values(0.0, 0.0, -1.2),
-        0.5,
-        material_center,
-    )));
-
-    world.add(Box::new(Sphere::new(
-        Point3::with_values(-1.0, 0.0, -1.0),
-        0.5,
-        material_left,
-    )));
-    world.add(Box::new(Sphere::new(
-        Point3::with_values(-1.0, 0.0, -1.0),
-        0.4,
-        material_bubble,
-    )));
-    world.add(Box::new(Sphere::new(
-        Point3::with_values(1.0, 0.0, -1.0),
-        0.5,
-        material_right,
-    )));
-    let mut camera = Camera::new();
-
-    camera.aspect_ratio = 16.0 / 9.0;
-    camera.image_width = 800;
-    camera.smaples_per_pixel = 100;
-    camera.max_depth = 50;
-    camera.v_fov = 20.0;
-
-    camera.focus_dist = 3.4;
-    camera.defocus_angle = 10.0;
-
-    camera.lookfrom = Point3::with_values(-2.0, 2.0, 1.0);
-    camera.lookat = Point3::with_values(0.0, 0.0, -1.0);
-    camera.vup = Point3::with_values(0.0, 1.0, 0.0);
-
-    camera.render(&world);
+    let options = match cli::parse_args(env::args().skip(1)) {
+        Ok(Command::Render(options)) => options,
+        Ok(Command::Help) => {
+            print!("{}", cli::USAGE);
+            return;
+        }
+        Err(error) => {
+            eprintln!("error: {}\n\nRun with --help for usage.", error);
+            process::exit(2);
+        }
+    };
+
+    if let Err(error) = run(&options) {
+        eprintln!("error: {}", error);
+        process::exit(1);
+    }
+}
+
+fn run(options: &Options) -> Result<(), Box<dyn Error>> {
+    let mut scene = match scenes::by_name(&options.scene) {
+        Some(scene) => scene,
+        None if Path::new(&options.scene).exists() => {
+            scene_file::load_scene(Path::new(&options.scene))?
+        }
+        None => {
+            return Err(format!(
+                "unknown scene '{}', expected a scene file or one of {}",
+                options.scene,
+                scenes::SCENE_NAMES.join(", ")
+            )
+            .into())
+        }
+    };
+
+    let camera = &mut scene.camera;
+    options.apply_to(camera);
+    camera.validate()?;
+
+    let output = File::create(&options.output)
+        .map_err(|e| format!("{}: {}", options.output.display(), e))?;
+
+    if options.verbosity == Verbosity::Verbose {
+        eprintln!(
+            "Rendering '{}': {} objects, {} px wide at aspect {:.3}, {} samples, depth {}",
+            options.scene,
+            scene.world.len(),
+            camera.image_width,
+            camera.aspect_ratio,
+            camera.smaples_per_pixel,
+            camera.max_depth
+        );
+    }
+
+    let start = Instant::now();
+    let world = BvhNode::new(scene.world);
+
+    let image = scene.camera.render(&world, &scene.lights);
+
+    image_writer::write_image(&image, output, options.format)
+        .map_err(|e| format!("{}: {}", options.output.display(), e))?;
+
+    if options.verbosity == Verbosity::Verbose {
+        eprintln!(
+            "Wrote {} in {:.2}s",
+            options.output.display(),
+            start.elapsed().as_secs_f64()
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bad_output_paths_fail_before_rendering() {
+        let args = [
+            "-w",
+            "4000",
+            "-n",
+            "100000",
+            "-q",
+            "-o",
+            "/nonexistent/x.png",
+        ];
+        let Ok(Command::Render(options)) = cli::parse_args(args.map(String::from)) else {
+            panic!("arguments should parse");
+        };
+
+        // Rendering this would take hours, the error has to come first
+        let error = run(&options).unwrap_err().to_string();
+        assert!(error.starts_with("/nonexistent/x.png: "), "{}", error);
+    }
 }
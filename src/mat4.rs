@@ -0,0 +1,227 @@
+use std::ops::Mul;
+
+use crate::{
+    utils::degrees_to_radians,
+    vec3::{Point3, Vec3},
+};
+
+// Row-major 4x4 matrix of an affine transform. Points are treated as column vectors with an
+// implicit w of 1 and directions with a w of 0.
+#[derive(Clone, Copy)]
+pub struct Mat4 {
+    m: [[f64; 4]; 4],
+}
+
+impl Mat4 {
+    pub fn new() -> Mat4 {
+        Mat4::IDENTITY
+    }
+
+    pub fn with_values(m: [[f64; 4]; 4]) -> Mat4 {
+        Mat4 { m }
+    }
+
+    pub fn translation(offset: &Vec3) -> Mat4 {
+        Mat4::with_values([
+            [1.0, 0.0, 0.0, offset.x()],
+            [0.0, 1.0, 0.0, offset.y()],
+            [0.0, 0.0, 1.0, offset.z()],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn scaling(factors: &Vec3) -> Mat4 {
+        Mat4::with_values([
+            [factors.x(), 0.0, 0.0, 0.0],
+            [0.0, factors.y(), 0.0, 0.0],
+            [0.0, 0.0, factors.z(), 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    // Counter-clockwise rotation about `axis` when looking down the axis towards the origin
+    pub fn rotation(axis: &Vec3, degrees: f64) -> Mat4 {
+        let a = axis.unit_vector();
+        let theta = degrees_to_radians(degrees);
+        let (sin_theta, cos_theta) = theta.sin_cos();
+        let t = 1.0 - cos_theta;
+
+        Mat4::with_values([
+            [
+                t * a.x() * a.x() + cos_theta,
+                t * a.x() * a.y() - sin_theta * a.z(),
+                t * a.x() * a.z() + sin_theta * a.y(),
+                0.0,
+            ],
+            [
+                t * a.x() * a.y() + sin_theta * a.z(),
+                t * a.y() * a.y() + cos_theta,
+                t * a.y() * a.z() - sin_theta * a.x(),
+                0.0,
+            ],
+            [
+                t * a.x() * a.z() - sin_theta * a.y(),
+                t * a.y() * a.z() + sin_theta * a.x(),
+                t * a.z() * a.z() + cos_theta,
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn transpose(&self) -> Mat4 {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.m[j][i];
+            }
+        }
+
+        Mat4 { m }
+    }
+
+    // Gauss-Jordan elimination with partial pivoting, `None` for singular matrices
+    pub fn inverse(&self) -> Option<Mat4> {
+        let mut a = self.m;
+        let mut inv = Mat4::IDENTITY.m;
+
+        for col in 0..4 {
+            let pivot = (col..4)
+                .max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))
+                .unwrap();
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let scale = 1.0 / a[col][col];
+            for j in 0..4 {
+                a[col][j] *= scale;
+                inv[col][j] *= scale;
+            }
+
+            for row in 0..4 {
+                let factor = a[row][col];
+                if row == col || factor == 0.0 {
+                    continue;
+                }
+                for j in 0..4 {
+                    a[row][j] -= factor * a[col][j];
+                    inv[row][j] -= factor * inv[col][j];
+                }
+            }
+        }
+
+        Some(Mat4 { m: inv })
+    }
+
+    // Determinant of the upper 3x3 part, the volume scale of the linear part of the transform
+    pub fn determinant3(&self) -> f64 {
+        let m = &self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    pub fn transform_point(&self, p: &Point3) -> Point3 {
+        self.transform_vector(p) + Vec3::with_values(self.m[0][3], self.m[1][3], self.m[2][3])
+    }
+
+    pub fn transform_vector(&self, v: &Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::with_values(
+            m[0][0] * v[0] + m[0][1] * v[1] + m[0][2] * v[2],
+            m[1][0] * v[0] + m[1][1] * v[1] + m[1][2] * v[2],
+            m[2][0] * v[0] + m[2][1] * v[1] + m[2][2] * v[2],
+        )
+    }
+
+    pub const IDENTITY: Mat4 = Mat4 {
+        m: [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ],
+    };
+}
+
+impl Mul for Mat4 {
+    type Output = Mat4;
+
+    // `a * b` applies `b` first, then `a`
+    fn mul(self, rhs: Self) -> Self::Output {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[i][k] * rhs.m[k][j]).sum();
+            }
+        }
+
+        Mat4 { m }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_matrix_near(a: &Mat4, b: &Mat4) {
+        for i in 0..4 {
+            for j in 0..4 {
+                assert!(
+                    (a.m[i][j] - b.m[i][j]).abs() < 1e-9,
+                    "[{}][{}]: {} != {}",
+                    i,
+                    j,
+                    a.m[i][j],
+                    b.m[i][j]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn inverse_undoes_the_transform() {
+        let matrix = Mat4::translation(&Vec3::with_values(1.0, -2.0, 3.0))
+            * Mat4::rotation(&Vec3::with_values(1.0, 2.0, -0.5), 37.0)
+            * Mat4::scaling(&Vec3::with_values(2.0, 0.5, -3.0));
+        let inverse = matrix.inverse().unwrap();
+
+        assert_matrix_near(&(matrix * inverse), &Mat4::IDENTITY);
+        assert_matrix_near(&(inverse * matrix), &Mat4::IDENTITY);
+
+        let p = Point3::with_values(0.3, -1.7, 2.2);
+        let q = inverse.transform_point(&matrix.transform_point(&p));
+        assert!((q - p).length() < 1e-9, "{}", q);
+        // Directions ignore the translation
+        let v = Vec3::with_values(1.0, 0.0, 0.0);
+        let w = inverse.transform_vector(&matrix.transform_vector(&v));
+        assert!((w - v).length() < 1e-9, "{}", w);
+
+        // A zero diagonal needs the rows swapped to find a pivot
+        let permutation = Mat4::with_values([
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        assert_matrix_near(&permutation.inverse().unwrap(), &permutation.transpose());
+    }
+
+    #[test]
+    fn rotations_invert_to_their_transpose() {
+        let rotation = Mat4::rotation(&Vec3::with_values(0.0, 1.0, 1.0), 120.0);
+
+        assert_matrix_near(&rotation.inverse().unwrap(), &rotation.transpose());
+        assert!((rotation.determinant3() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn singular_matrices_have_no_inverse() {
+        let flattened = Mat4::scaling(&Vec3::with_values(1.0, 0.0, 1.0));
+        assert!(flattened.inverse().is_none());
+        assert_eq!(flattened.determinant3(), 0.0);
+    }
+}
@@ -1,53 +1,129 @@
-use crate::{color::Color, hittable::HitRecord, ray::Ray, utils::random_f64, vec3::Vec3};
+use std::{f64::consts::PI, sync::Arc};
 
-pub trait Material {
+use crate::{
+    color::Color,
+    hittable::{HitRecord, Hittable},
+    ray::Ray,
+    sampler::Sampler,
+    spectrum::{in_spectrum, LAMBDA_REFERENCE},
+    texture::{SolidColor, Texture},
+    thin_film::ThinFilm,
+    vec3::Vec3,
+};
+
+pub trait Material: Send + Sync {
     fn scatter(
         &self,
         ray_in: &Ray,
         rec: &HitRecord,
         attenuation: &mut Color,
         scattered: &mut Ray,
+        sampler: &mut Sampler,
     ) -> bool;
+
+    // `scatter` for light that travels through the scene before leaving the surface again, such
+    // as inside the object, which `world` is traced for. Directions a bent shading normal sends
+    // through the surface are dropped.
+    fn scatter_in_world(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        _world: &dyn Hittable,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+        sampler: &mut Sampler,
+    ) -> bool {
+        if !self.scatter(ray_in, rec, attenuation, scattered, sampler) {
+            return false;
+        }
+
+        return rec.is_consistent(&scattered.direction());
+    }
+
+    fn emitted(&self, _ray_in: &Ray, _rec: &HitRecord) -> Color {
+        Color::new()
+    }
+
+    // Whether `scatter` and `eval` give their colors at the wavelengths `ray_in` carries, for
+    // materials whose color changes over the spectrum faster than RGB can follow, rather than
+    // as RGB colors the camera converts
+    fn is_spectral(&self, _ray_in: &Ray) -> bool {
+        false
+    }
+
+    // BSDF times the cosine term for light arriving from `direction`, used when sampling lights
+    // explicitly. Materials that only scatter into discrete directions return black.
+    fn eval(&self, _ray_in: &Ray, _rec: &HitRecord, _direction: &Vec3) -> Color {
+        Color::new()
+    }
+
+    // Solid angle density with which `scatter` picks `direction`. Zero marks a discrete
+    // (specular) scattering direction that light sampling cannot reach.
+    fn scattering_pdf(&self, _ray_in: &Ray, _rec: &HitRecord, _direction: &Vec3) -> f64 {
+        0.0
+    }
 }
 
 pub struct Lambertian {
-    pub albedo: Color,
+    pub tex: Arc<dyn Texture>,
 }
 
 impl Lambertian {
     pub fn new(albedo: Color) -> Self {
-        Lambertian { albedo }
+        Lambertian::with_texture(Arc::new(SolidColor::new(albedo)))
+    }
+
+    pub fn with_texture(tex: Arc<dyn Texture>) -> Self {
+        Lambertian { tex }
     }
 }
 
 impl Material for Lambertian {
     fn scatter(
         &self,
-        _ray_in: &Ray,
+        ray_in: &Ray,
         rec: &HitRecord,
         attenuation: &mut Color,
         scattered: &mut Ray,
+        sampler: &mut Sampler,
     ) -> bool {
-        let mut scatered_dir = rec.normal + Vec3::random_unit_vector();
+        let mut scatered_dir = rec.normal + Vec3::random_unit_vector(sampler);
 
         if scatered_dir.near_zero() {
             scatered_dir = rec.normal;
         }
 
-        *scattered = Ray::with_values(rec.p, scatered_dir);
-        *attenuation = self.albedo;
+        *scattered = Ray::with_time(rec.p, scatered_dir, ray_in.time());
+        *attenuation = self.tex.value(rec.u, rec.v, &rec.p);
         return true;
     }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        self.scattering_pdf(ray_in, rec, direction) * self.tex.value(rec.u, rec.v, &rec.p)
+    }
+
+    fn scattering_pdf(&self, _ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        let cos_theta = rec.normal.dot(&direction.unit_vector());
+        if cos_theta < 0.0 {
+            0.0
+        } else {
+            cos_theta / PI
+        }
+    }
 }
 
 pub struct Metal {
-    albedo: Color,
+    tex: Arc<dyn Texture>,
     fuzz: f64,
 }
 
 impl Metal {
     pub fn new(albedo: Color, fuzz: f64) -> Self {
-        Metal { albedo, fuzz }
+        Metal::with_texture(Arc::new(SolidColor::new(albedo)), fuzz)
+    }
+
+    pub fn with_texture(tex: Arc<dyn Texture>, fuzz: f64) -> Self {
+        Metal { tex, fuzz }
     }
 }
 
@@ -58,12 +134,13 @@ impl Material for Metal {
         rec: &HitRecord,
         attenuation: &mut Color,
         scattered: &mut Ray,
+        sampler: &mut Sampler,
     ) -> bool {
         let mut reflected = Vec3::reflect(ray_in.direction(), &rec.normal);
 
-        reflected = reflected.unit_vector() + (self.fuzz * Vec3::random_unit_vector());
-        *scattered = Ray::with_values(rec.p, reflected);
-        *attenuation = self.albedo;
+        reflected = reflected.unit_vector() + (self.fuzz * Vec3::random_unit_vector(sampler));
+        *scattered = Ray::with_time(rec.p, reflected, ray_in.time());
+        *attenuation = self.tex.value(rec.u, rec.v, &rec.p);
 
         return scattered.direction().dot(&rec.normal) > 0.0;
     }
@@ -73,11 +150,23 @@ pub struct Dielectric {
     // Refractive index in vacuum or air, or the ratio of the material's refractive index over
     // the refractive index of the enclosing media
     refraction_index: f64,
+    // Absorption coefficients per unit distance inside the material
+    absorption: Color,
+    // Coating that colors the reflection with interference
+    pub film: Option<ThinFilm>,
 }
 
 impl Dielectric {
     pub fn new(refraction_index: f64) -> Self {
-        Dielectric { refraction_index }
+        Dielectric::with_absorption(refraction_index, Color::new())
+    }
+
+    pub fn with_absorption(refraction_index: f64, absorption: Color) -> Self {
+        Dielectric {
+            refraction_index,
+            absorption,
+            film: None,
+        }
     }
 
     pub fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
@@ -95,29 +184,394 @@ impl Material for Dielectric {
         rec: &HitRecord,
         attenuation: &mut Color,
         scattered: &mut Ray,
+        sampler: &mut Sampler,
     ) -> bool {
-        *attenuation = Color::with_values(1.0, 1.0, 1.0);
+        *attenuation = transmittance(&self.absorption, ray_in, rec);
 
-        let ri = if rec.front_face {
-            1.0 / self.refraction_index
+        let film = match &self.film {
+            Some(film) => film,
+            None => {
+                let direction = dielectric_direction(ray_in, rec, self.refraction_index, sampler);
+                *scattered = Ray::with_time(rec.p, direction, ray_in.time());
+                return true;
+            }
+        };
+        *attenuation = in_spectrum(ray_in, *attenuation);
+
+        // The film's reflectance depends on the color, so reflect with its mean and weight the
+        // colors by how much more or less likely they were to go that way
+        let (incident_ior, substrate_ior) = if rec.front_face {
+            (1.0, self.refraction_index)
         } else {
-            self.refraction_index
+            (self.refraction_index, 1.0)
         };
+        let ri = incident_ior / substrate_ior;
 
         let unit_direction = ray_in.direction().unit_vector();
         let cos_theta = ((-unit_direction).dot(&rec.normal)).min(1.0);
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
-        let cannot_refract = ri * sin_theta > 1.0;
+        let reflectance =
+            film.dielectric_reflectance(ray_in, cos_theta, incident_ior, substrate_ior, rec);
+        let reflect_chance = (reflectance.x() + reflectance.y() + reflectance.z()) / 3.0;
+        let white = Color::with_values(1.0, 1.0, 1.0);
 
-        let direction = if cannot_refract || Dielectric::reflectance(cos_theta, ri) > random_f64() {
+        let direction = if ri * sin_theta > 1.0 {
+            *attenuation = *attenuation * reflectance;
+            Vec3::reflect(unit_direction, &rec.normal)
+        } else if sampler.random_f64() < reflect_chance {
+            *attenuation = *attenuation * reflectance / reflect_chance;
             Vec3::reflect(unit_direction, &rec.normal)
         } else {
+            *attenuation = *attenuation * (white - reflectance) / (1.0 - reflect_chance);
             Vec3::refract(&unit_direction, &rec.normal, ri)
         };
+        *scattered = Ray::with_time(rec.p, direction, ray_in.time());
+
+        return true;
+    }
+
+    // The film's reflectance is computed at the ray's wavelengths
+    fn is_spectral(&self, ray_in: &Ray) -> bool {
+        self.film.is_some() && ray_in.wavelengths().is_some()
+    }
+}
+
+// Reflected or refracted direction of a smooth dielectric surface, chosen in proportion to its
+// reflectance
+pub fn dielectric_direction(
+    ray_in: &Ray,
+    rec: &HitRecord,
+    refraction_index: f64,
+    sampler: &mut Sampler,
+) -> Vec3 {
+    let ri = if rec.front_face {
+        1.0 / refraction_index
+    } else {
+        refraction_index
+    };
+
+    let unit_direction = ray_in.direction().unit_vector();
+    let cos_theta = ((-unit_direction).dot(&rec.normal)).min(1.0);
+    let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+
+    let cannot_refract = ri * sin_theta > 1.0;
+
+    if cannot_refract || Dielectric::reflectance(cos_theta, ri) > sampler.random_f64() {
+        Vec3::reflect(unit_direction, &rec.normal)
+    } else {
+        Vec3::refract(&unit_direction, &rec.normal, ri)
+    }
+}
+
+// Refractive index as a function of the wavelength, with wavelengths in micrometers
+#[derive(Clone, Copy)]
+pub enum Dispersion {
+    // n = a + b / lambda^2
+    Cauchy { a: f64, b: f64 },
+    // n^2 = 1 + sum of b[i] lambda^2 / (lambda^2 - c[i])
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Dispersion {
+    pub const PRESET_NAMES: [&str; 3] = ["bk7", "fused_silica", "diamond"];
+
+    // Sellmeier coefficients of common optical materials named in `PRESET_NAMES`
+    pub fn from_preset(name: &str) -> Option<Dispersion> {
+        let (b, c) = match name {
+            "bk7" => (
+                [1.03961212, 0.231792344, 1.01046945],
+                [0.00600069867, 0.0200179144, 103.560653],
+            ),
+            "fused_silica" => (
+                [0.6961663, 0.4079426, 0.8974794],
+                [0.00467914826, 0.0135120631, 97.9340025],
+            ),
+            "diamond" => ([4.3356, 0.3306, 0.0], [0.011236, 0.030625, 0.0]),
+            _ => return None,
+        };
+
+        Some(Dispersion::Sellmeier { b, c })
+    }
+
+    // Refractive index at a wavelength in nanometers
+    pub fn refraction_index(&self, lambda: f64) -> f64 {
+        let lambda = lambda / 1000.0;
+        let lambda2 = lambda * lambda;
+
+        match self {
+            Dispersion::Cauchy { a, b } => a + b / lambda2,
+            Dispersion::Sellmeier { b, c } => {
+                let n2 = 1.0
+                    + b.iter()
+                        .zip(c)
+                        .map(|(b, c)| b * lambda2 / (lambda2 - c))
+                        .sum::<f64>();
+                n2.max(1.0).sqrt()
+            }
+        }
+    }
+}
+
+// Smooth dielectric whose refractive index depends on the wavelength, which splits white light
+// into its colors in spectral renders. Other renders use the index at `LAMBDA_REFERENCE`.
+pub struct DispersiveDielectric {
+    dispersion: Dispersion,
+}
+
+impl DispersiveDielectric {
+    pub fn new(dispersion: Dispersion) -> Self {
+        DispersiveDielectric { dispersion }
+    }
+}
+
+impl Material for DispersiveDielectric {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+        sampler: &mut Sampler,
+    ) -> bool {
+        *attenuation = Color::with_values(1.0, 1.0, 1.0);
 
-        *scattered = Ray::with_values(rec.p, direction);
+        // Each wavelength refracts its own way, so only the hero wavelength is followed
+        let wavelengths = ray_in.wavelengths();
+        let lambda = wavelengths.map_or(LAMBDA_REFERENCE, |wavelengths| wavelengths.hero());
+        let refraction_index = self.dispersion.refraction_index(lambda);
+
+        let direction = dielectric_direction(ray_in, rec, refraction_index, sampler);
+        *scattered = Ray::with_time(rec.p, direction, ray_in.time());
+        scattered.set_wavelengths(wavelengths.map(|wavelengths| wavelengths.terminate_secondary()));
 
         return true;
     }
 }
+
+// Beer-Lambert transmittance through a medium with the given absorption coefficients, for rays
+// leaving a closed object through its surface. The ray is taken to have started inside the
+// object, at its last scattering event.
+pub fn transmittance(absorption: &Color, ray_in: &Ray, rec: &HitRecord) -> Color {
+    if rec.front_face {
+        return Color::with_values(1.0, 1.0, 1.0);
+    }
+
+    let distance = rec.t * ray_in.direction().length();
+    Color::with_values(
+        (-absorption.x() * distance).exp(),
+        (-absorption.y() * distance).exp(),
+        (-absorption.z() * distance).exp(),
+    )
+}
+
+pub struct DiffuseLight {
+    tex: Arc<dyn Texture>,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Color) -> Self {
+        DiffuseLight::with_texture(Arc::new(SolidColor::new(emit)))
+    }
+
+    pub fn with_texture(tex: Arc<dyn Texture>) -> Self {
+        DiffuseLight { tex }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(
+        &self,
+        _ray_in: &Ray,
+        _rec: &HitRecord,
+        _attenuation: &mut Color,
+        _scattered: &mut Ray,
+        _sampler: &mut Sampler,
+    ) -> bool {
+        false
+    }
+
+    fn emitted(&self, _ray_in: &Ray, rec: &HitRecord) -> Color {
+        // Lights only shine from their front face
+        if !rec.front_face {
+            return Color::new();
+        }
+
+        self.tex.value(rec.u, rec.v, &rec.p)
+    }
+}
+
+// Phase function of a participating medium that scatters equally in every direction
+pub struct Isotropic {
+    pub tex: Arc<dyn Texture>,
+}
+
+impl Isotropic {
+    pub fn new(albedo: Color) -> Self {
+        Isotropic::with_texture(Arc::new(SolidColor::new(albedo)))
+    }
+
+    pub fn with_texture(tex: Arc<dyn Texture>) -> Self {
+        Isotropic { tex }
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+        sampler: &mut Sampler,
+    ) -> bool {
+        *scattered = Ray::with_time(rec.p, Vec3::random_unit_vector(sampler), ray_in.time());
+        *attenuation = self.tex.value(rec.u, rec.v, &rec.p);
+
+        return true;
+    }
+
+    // There is no cosine term inside a volume
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        self.scattering_pdf(ray_in, rec, direction) * self.tex.value(rec.u, rec.v, &rec.p)
+    }
+
+    fn scattering_pdf(&self, _ray_in: &Ray, _rec: &HitRecord, _direction: &Vec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+}
+
+// Helpers shared by the tests of the individual materials
+#[cfg(test)]
+pub mod testing {
+    use super::*;
+
+    const SAMPLES: usize = 10_000;
+
+    // Mean of what `scatter` passes on for light arriving at `cos_theta` to the normal of a
+    // surface at the origin facing +z, counting absorbed samples as black
+    pub fn directional_albedo(mat: &dyn Material, cos_theta: f64, sampler: &mut Sampler) -> Color {
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let ray_in = Ray::with_values(
+            Vec3::with_values(sin_theta, 0.0, cos_theta),
+            Vec3::with_values(-sin_theta, 0.0, -cos_theta),
+        );
+        let mut rec = HitRecord::new();
+        rec.normal = Vec3::with_values(0.0, 0.0, 1.0);
+        rec.geometric_normal = rec.normal;
+        rec.front_face = true;
+
+        let mut total = Color::new();
+        for _ in 0..SAMPLES {
+            let mut attenuation = Color::new();
+            let mut scattered = Ray::new();
+            if mat.scatter(&ray_in, &rec, &mut attenuation, &mut scattered, sampler) {
+                total += attenuation;
+            }
+        }
+
+        total / SAMPLES as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spectrum::{Wavelengths, LAMBDA_MAX, LAMBDA_MIN};
+
+    #[test]
+    fn glass_presets_match_their_catalog_index() {
+        // Refractive indices at the helium d line from the Schott and Malitson data
+        let catalog = [
+            ("bk7", 1.5168),
+            ("fused_silica", 1.4585),
+            ("diamond", 2.4175),
+        ];
+        for (name, n_d) in catalog {
+            let dispersion = Dispersion::from_preset(name).unwrap();
+            let n = dispersion.refraction_index(LAMBDA_REFERENCE);
+            assert!((n - n_d).abs() < 5e-4, "{}: {} instead of {}", name, n, n_d);
+        }
+
+        let cauchy = Dispersion::Cauchy { a: 1.5, b: 0.004 };
+        assert!((cauchy.refraction_index(500.0) - 1.516).abs() < 1e-12);
+    }
+
+    #[test]
+    fn index_falls_with_the_wavelength() {
+        let mut dispersions: Vec<Dispersion> = Dispersion::PRESET_NAMES
+            .iter()
+            .map(|name| Dispersion::from_preset(name).unwrap())
+            .collect();
+        dispersions.push(Dispersion::Cauchy { a: 1.5, b: 0.004 });
+
+        for dispersion in dispersions {
+            let mut previous = f64::INFINITY;
+            let mut lambda = LAMBDA_MIN;
+            while lambda <= LAMBDA_MAX {
+                let n = dispersion.refraction_index(lambda);
+                assert!(n < previous && n > 1.0, "{} at {} nm", n, lambda);
+                previous = n;
+                lambda += 10.0;
+            }
+        }
+    }
+
+    // Index of refraction the refracted rays of `glass` bend by, for light arriving at 45 degrees
+    // onto a surface facing +z
+    fn refracted_index(glass: &DispersiveDielectric, wavelengths: Option<Wavelengths>) -> f64 {
+        let mut ray_in = Ray::with_values(
+            Vec3::with_values(-1.0, 0.0, 1.0),
+            Vec3::with_values(1.0, 0.0, -1.0),
+        );
+        ray_in.set_wavelengths(wavelengths);
+        let mut rec = HitRecord::new();
+        rec.normal = Vec3::with_values(0.0, 0.0, 1.0);
+        rec.front_face = true;
+
+        let mut sampler = Sampler::new(9);
+        loop {
+            let mut attenuation = Color::new();
+            let mut scattered = Ray::new();
+            assert!(glass.scatter(
+                &ray_in,
+                &rec,
+                &mut attenuation,
+                &mut scattered,
+                &mut sampler
+            ));
+            assert_eq!(attenuation.x(), 1.0);
+            assert_eq!(
+                scattered.wavelengths().map(|w| w.secondary_terminated()),
+                wavelengths.map(|_| true)
+            );
+
+            // Skip the reflections
+            let direction = scattered.direction().unit_vector();
+            if direction.z() < 0.0 {
+                return 0.5_f64.sqrt() / direction.x();
+            }
+        }
+    }
+
+    #[test]
+    fn dispersive_glass_bends_each_wavelength_its_own_way() {
+        let dispersion = Dispersion::from_preset("bk7").unwrap();
+        let glass = DispersiveDielectric::new(dispersion);
+
+        let blue = Wavelengths::sample(0.05);
+        let red = Wavelengths::sample(0.95);
+        assert!(blue.hero() < 450.0 && red.hero() > 620.0);
+
+        for wavelengths in [blue, red] {
+            let n = refracted_index(&glass, Some(wavelengths));
+            let expected = dispersion.refraction_index(wavelengths.hero());
+            assert!((n - expected).abs() < 1e-9, "{} != {}", n, expected);
+        }
+        assert!(refracted_index(&glass, Some(blue)) > refracted_index(&glass, Some(red)));
+
+        // RGB renders use the index at the d line
+        let n = refracted_index(&glass, None);
+        assert!((n - dispersion.refraction_index(LAMBDA_REFERENCE)).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,283 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    bvh::BvhNode,
+    hittable::{HitRecord, Hittable},
+    hittable_list::HittableList,
+    interval::Interval,
+    material::Material,
+    ray::Ray,
+    triangle::intersect_triangle,
+    vec3::{Point3, Vec3},
+};
+
+// Vertex and index buffers shared by every triangle of a mesh. Normals and uvs are either empty
+// or hold one entry per position.
+pub struct Mesh {
+    pub positions: Vec<Point3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f64, f64)>,
+    pub indices: Vec<[usize; 3]>,
+    // Index into the mesh materials for every triangle; empty means all use the first material
+    pub face_materials: Vec<usize>,
+}
+
+impl Mesh {
+    pub fn new() -> Mesh {
+        Mesh {
+            positions: Vec::new(),
+            normals: Vec::new(),
+            uvs: Vec::new(),
+            indices: Vec::new(),
+            face_materials: Vec::new(),
+        }
+    }
+
+    pub fn with_values(positions: Vec<Point3>, indices: Vec<[usize; 3]>) -> Mesh {
+        Mesh {
+            positions,
+            indices,
+            ..Mesh::new()
+        }
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len()
+    }
+}
+
+struct MeshData {
+    mesh: Mesh,
+    materials: Vec<Arc<dyn Material>>,
+}
+
+struct MeshTriangle {
+    data: Arc<MeshData>,
+    index: usize,
+    bbox: Aabb,
+}
+
+impl Hittable for MeshTriangle {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let mesh = &self.data.mesh;
+        let [i0, i1, i2] = mesh.indices[self.index];
+        let p0 = mesh.positions[i0];
+        let p1 = mesh.positions[i1];
+        let p2 = mesh.positions[i2];
+
+        let hit = match intersect_triangle(r, ray_t, &p0, &p1, &p2) {
+            Some(hit) => hit,
+            None => return false,
+        };
+
+        rec.t = hit.t;
+        rec.p = r.at(hit.t);
+
+        rec.dpdu = p1 - p0;
+        rec.dpdv = p2 - p0;
+        if mesh.uvs.is_empty() {
+            rec.u = hit.b1;
+            rec.v = hit.b2;
+        } else {
+            let [uv0, uv1, uv2] = [mesh.uvs[i0], mesh.uvs[i1], mesh.uvs[i2]];
+            rec.u = hit.b0 * uv0.0 + hit.b1 * uv1.0 + hit.b2 * uv2.0;
+            rec.v = hit.b0 * uv0.1 + hit.b1 * uv1.1 + hit.b2 * uv2.1;
+
+            // Solve the edges for the derivatives along the uvs, unless they are degenerate
+            let (du1, dv1) = (uv1.0 - uv0.0, uv1.1 - uv0.1);
+            let (du2, dv2) = (uv2.0 - uv0.0, uv2.1 - uv0.1);
+            let determinant = du1 * dv2 - dv1 * du2;
+            if determinant.abs() > 1e-12 {
+                rec.dpdu = (dv2 * (p1 - p0) - dv1 * (p2 - p0)) / determinant;
+                rec.dpdv = (du1 * (p2 - p0) - du2 * (p1 - p0)) / determinant;
+            }
+        }
+
+        // The geometric normal decides which side was hit, the interpolated vertex normal (if
+        // any) only bends the shading normal on that side.
+        let geometric_normal = (p1 - p0).cross(&(p2 - p0)).unit_vector();
+        rec.set_face_normal(r, &geometric_normal);
+
+        if !mesh.normals.is_empty() {
+            let shading_normal =
+                hit.b0 * mesh.normals[i0] + hit.b1 * mesh.normals[i1] + hit.b2 * mesh.normals[i2];
+            if !shading_normal.near_zero() {
+                rec.set_shading_normal(r, &shading_normal.unit_vector());
+            }
+        }
+
+        let mat_index = mesh.face_materials.get(self.index).copied().unwrap_or(0);
+        rec.mat = self.data.materials[mat_index].clone();
+
+        return true;
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+pub struct TriangleMesh {
+    bvh: BvhNode,
+    triangle_count: usize,
+}
+
+impl TriangleMesh {
+    pub fn new(mesh: Mesh, mat: Arc<dyn Material>) -> Result<Self, String> {
+        TriangleMesh::with_materials(mesh, vec![mat])
+    }
+
+    // Meshes with several materials pick theirs per triangle through `Mesh::face_materials`.
+    // Inconsistent buffers are reported here rather than panicking in the middle of a render.
+    pub fn with_materials(mesh: Mesh, materials: Vec<Arc<dyn Material>>) -> Result<Self, String> {
+        if materials.is_empty() {
+            return Err("a mesh needs at least one material".to_string());
+        }
+        if !mesh.normals.is_empty() && mesh.normals.len() != mesh.positions.len() {
+            return Err(format!(
+                "{} normals for {} positions",
+                mesh.normals.len(),
+                mesh.positions.len()
+            ));
+        }
+        if !mesh.uvs.is_empty() && mesh.uvs.len() != mesh.positions.len() {
+            return Err(format!(
+                "{} uvs for {} positions",
+                mesh.uvs.len(),
+                mesh.positions.len()
+            ));
+        }
+        // Normals and uvs share the position indices, so one bound covers all three
+        for (face, indices) in mesh.indices.iter().enumerate() {
+            if let Some(index) = indices.iter().find(|&&i| i >= mesh.positions.len()) {
+                return Err(format!(
+                    "triangle {} uses vertex {}, but the mesh has {} positions",
+                    face,
+                    index,
+                    mesh.positions.len()
+                ));
+            }
+        }
+        if let Some(face) = mesh
+            .face_materials
+            .iter()
+            .position(|&m| m >= materials.len())
+        {
+            return Err(format!(
+                "triangle {} uses material {}, but the mesh has {}",
+                face,
+                mesh.face_materials[face],
+                materials.len()
+            ));
+        }
+
+        let triangle_count = mesh.triangle_count();
+        let data = Arc::new(MeshData { mesh, materials });
+
+        let mut triangles = HittableList::new();
+        for index in 0..triangle_count {
+            let [i0, i1, i2] = data.mesh.indices[index];
+            let p0 = data.mesh.positions[i0];
+            let bbox = Aabb::surrounding(
+                &Aabb::from_points(p0, data.mesh.positions[i1]),
+                &Aabb::from_points(p0, data.mesh.positions[i2]),
+            );
+
+            triangles.add(Box::new(MeshTriangle {
+                data: data.clone(),
+                index,
+                bbox,
+            }));
+        }
+
+        Ok(TriangleMesh {
+            bvh: BvhNode::new(triangles),
+            triangle_count,
+        })
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.triangle_count
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        self.bvh.hit(r, ray_t, rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bvh.bounding_box()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{color::Color, hittable::testing::hit, material::Lambertian};
+
+    fn square() -> Mesh {
+        let positions = vec![
+            Point3::with_values(0.0, 0.0, 0.0),
+            Point3::with_values(1.0, 0.0, 0.0),
+            Point3::with_values(1.0, 1.0, 0.0),
+            Point3::with_values(0.0, 1.0, 0.0),
+        ];
+        Mesh::with_values(positions, vec![[0, 1, 2], [0, 2, 3]])
+    }
+
+    fn build(mesh: Mesh) -> Result<TriangleMesh, String> {
+        TriangleMesh::new(
+            mesh,
+            Arc::new(Lambertian::new(Color::with_values(0.5, 0.5, 0.5))),
+        )
+    }
+
+    #[test]
+    fn builds_consistent_meshes() {
+        let mut mesh = square();
+        mesh.normals = vec![Vec3::with_values(0.0, 0.0, 1.0); 4];
+        mesh.uvs = vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+        let mesh = build(mesh).unwrap();
+
+        assert_eq!(mesh.triangle_count(), 2);
+        let rec = hit(
+            &mesh,
+            Point3::with_values(0.25, 0.75, 1.0),
+            Vec3::with_values(0.0, 0.0, -1.0),
+        )
+        .unwrap();
+        assert!((rec.u - 0.25).abs() < 1e-9 && (rec.v - 0.75).abs() < 1e-9);
+    }
+
+    #[test]
+    fn rejects_indices_past_the_vertices() {
+        let mut mesh = square();
+        mesh.indices[1] = [0, 4, 3];
+        let error = build(mesh).err().unwrap();
+        assert_eq!(
+            error,
+            "triangle 1 uses vertex 4, but the mesh has 4 positions"
+        );
+    }
+
+    #[test]
+    fn rejects_normals_and_uvs_that_miss_vertices() {
+        let mut mesh = square();
+        mesh.normals = vec![Vec3::with_values(0.0, 0.0, 1.0); 3];
+        assert_eq!(build(mesh).err().unwrap(), "3 normals for 4 positions");
+
+        let mut mesh = square();
+        mesh.uvs = vec![(0.0, 0.0); 5];
+        assert_eq!(build(mesh).err().unwrap(), "5 uvs for 4 positions");
+    }
+
+    #[test]
+    fn rejects_unknown_face_materials() {
+        let mut mesh = square();
+        mesh.face_materials = vec![0, 1];
+        let error = build(mesh).err().unwrap();
+        assert_eq!(error, "triangle 1 uses material 1, but the mesh has 1");
+    }
+}
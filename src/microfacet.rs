@@ -0,0 +1,537 @@
+use std::f64::consts::PI;
+
+use crate::{color::Color, hittable::HitRecord, onb::Onb, vec3::Vec3};
+
+// Microfacet models work in a local shading frame whose z axis is the surface normal, with both
+// directions pointing away from the surface.
+
+// Smallest roughness, below which the distributions get too peaked for floating point
+const MIN_ALPHA: f64 = 1e-3;
+
+// Distribution of microfacet normals with the Smith shadowing model
+pub trait MicrofacetDistribution: Send + Sync {
+    // Density of microfacet normals `wm`, per unit projected area
+    fn d(&self, wm: &Vec3) -> f64;
+
+    // Smith's auxiliary function, the ratio of microfacet area hidden from `w` to the visible
+    // area
+    fn lambda(&self, w: &Vec3) -> f64;
+
+    // Samples a normal from `visible_d` given two uniform random numbers
+    fn sample_visible(&self, w: &Vec3, u1: f64, u2: f64) -> Vec3;
+
+    // Fraction of the microfacets visible from `w`
+    fn g1(&self, w: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // Height-correlated fraction of the microfacets visible from both directions
+    fn g(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // Density of the normals `wm` visible from `w`, which face it
+    fn visible_d(&self, w: &Vec3, wm: &Vec3) -> f64 {
+        if w.z() == 0.0 {
+            return 0.0;
+        }
+
+        self.g1(w) / w.z().abs() * self.d(wm) * w.dot(wm).max(0.0)
+    }
+}
+
+// Trowbridge-Reitz (GGX) distribution of microfacet normals, anisotropic with roughness
+// `alpha_x` along x and `alpha_y` along y
+#[derive(Clone, Copy)]
+pub struct Ggx {
+    alpha_x: f64,
+    alpha_y: f64,
+}
+
+impl Ggx {
+    pub fn new(alpha_x: f64, alpha_y: f64) -> Ggx {
+        Ggx {
+            alpha_x: alpha_x.max(MIN_ALPHA),
+            alpha_y: alpha_y.max(MIN_ALPHA),
+        }
+    }
+
+    pub fn isotropic(alpha: f64) -> Ggx {
+        Ggx::new(alpha, alpha)
+    }
+
+    // Perceptually linear roughness in [0, 1], squared as in the Disney BRDF
+    pub fn from_roughness(roughness: f64) -> Ggx {
+        let roughness = roughness.clamp(0.0, 1.0);
+        Ggx::isotropic(roughness * roughness)
+    }
+}
+
+impl MicrofacetDistribution for Ggx {
+    fn d(&self, wm: &Vec3) -> f64 {
+        if wm.z() <= 0.0 {
+            return 0.0;
+        }
+
+        let x = wm.x() / self.alpha_x;
+        let y = wm.y() / self.alpha_y;
+        let t = x * x + y * y + wm.z() * wm.z();
+
+        1.0 / (PI * self.alpha_x * self.alpha_y * t * t)
+    }
+
+    fn lambda(&self, w: &Vec3) -> f64 {
+        let cos2 = w.z() * w.z();
+        if cos2 == 0.0 {
+            return f64::INFINITY;
+        }
+
+        let x = w.x() * self.alpha_x;
+        let y = w.y() * self.alpha_y;
+        let tan2_alpha2 = (x * x + y * y) / cos2;
+
+        0.5 * ((1.0 + tan2_alpha2).sqrt() - 1.0)
+    }
+
+    // Heitz 2018, "Sampling the GGX Distribution of Visible Normals"
+    fn sample_visible(&self, w: &Vec3, u1: f64, u2: f64) -> Vec3 {
+        // Stretch the view direction to the hemisphere configuration of unit roughness
+        let mut wh =
+            Vec3::with_values(self.alpha_x * w.x(), self.alpha_y * w.y(), w.z()).unit_vector();
+        if wh.z() < 0.0 {
+            wh = -wh;
+        }
+
+        let t1 = if wh.z() < 0.99999 {
+            Vec3::with_values(0.0, 0.0, 1.0).cross(&wh).unit_vector()
+        } else {
+            Vec3::with_values(1.0, 0.0, 0.0)
+        };
+        let t2 = wh.cross(&t1);
+
+        // Uniform point on the disk, warped to the projected area of the visible hemisphere
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let mut p2 = r * phi.sin();
+        let s = 0.5 * (1.0 + wh.z());
+        p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * p2;
+
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * wh;
+
+        // Unstretch back to the ellipsoid configuration
+        Vec3::with_values(
+            self.alpha_x * nh.x(),
+            self.alpha_y * nh.y(),
+            nh.z().max(1e-6),
+        )
+        .unit_vector()
+    }
+}
+
+// Beckmann distribution of microfacet normals, the slopes of a Gaussian random surface, with
+// roughness `alpha_x` along x and `alpha_y` along y
+#[derive(Clone, Copy)]
+pub struct Beckmann {
+    alpha_x: f64,
+    alpha_y: f64,
+}
+
+impl Beckmann {
+    pub fn new(alpha_x: f64, alpha_y: f64) -> Beckmann {
+        Beckmann {
+            alpha_x: alpha_x.max(MIN_ALPHA),
+            alpha_y: alpha_y.max(MIN_ALPHA),
+        }
+    }
+
+    pub fn isotropic(alpha: f64) -> Beckmann {
+        Beckmann::new(alpha, alpha)
+    }
+
+    // Slopes of a visible normal of the unit roughness distribution seen at an angle with cosine
+    // `cos_theta` in the xz plane (Jakob's fit of the inverse cumulative distribution, refined
+    // with Newton's method)
+    fn sample_slopes(cos_theta: f64, u1: f64, u2: f64) -> (f64, f64) {
+        if cos_theta > 0.9999 {
+            let r = (-(1.0 - u1).ln()).sqrt();
+            let phi = 2.0 * PI * u2;
+            return (r * phi.cos(), r * phi.sin());
+        }
+
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let tan_theta = sin_theta / cos_theta;
+        let cot_theta = 1.0 / tan_theta;
+
+        let mut a = -1.0;
+        let mut c = erf(cot_theta);
+        let sample_x = u1.max(1e-6);
+
+        let theta = cos_theta.acos();
+        let fit = 1.0 + theta * (-0.876 + theta * (0.4265 - 0.0594 * theta));
+        let mut b = c - (1.0 + c) * (1.0 - sample_x).powf(fit);
+
+        let inv_sqrt_pi = 1.0 / PI.sqrt();
+        let normalization =
+            1.0 / (1.0 + c + inv_sqrt_pi * tan_theta * (-cot_theta * cot_theta).exp());
+
+        for _ in 0..10 {
+            if !(b >= a && b <= c) {
+                b = 0.5 * (a + c);
+            }
+
+            let inv_erf = erf_inv(b);
+            let value = normalization
+                * (1.0 + b + inv_sqrt_pi * tan_theta * (-inv_erf * inv_erf).exp())
+                - sample_x;
+            if value.abs() < 1e-5 {
+                break;
+            }
+
+            if value > 0.0 {
+                c = b;
+            } else {
+                a = b;
+            }
+            let derivative = normalization * (1.0 - inv_erf * tan_theta);
+            b -= value / derivative;
+        }
+
+        (erf_inv(b), erf_inv(2.0 * u2.max(1e-6) - 1.0))
+    }
+}
+
+impl MicrofacetDistribution for Beckmann {
+    fn d(&self, wm: &Vec3) -> f64 {
+        if wm.z() <= 0.0 {
+            return 0.0;
+        }
+
+        let cos2 = wm.z() * wm.z();
+        let x = wm.x() / self.alpha_x;
+        let y = wm.y() / self.alpha_y;
+        let tan2_alpha2 = (x * x + y * y) / cos2;
+
+        (-tan2_alpha2).exp() / (PI * self.alpha_x * self.alpha_y * cos2 * cos2)
+    }
+
+    // Rational fit by Walter et al. 2007
+    fn lambda(&self, w: &Vec3) -> f64 {
+        let cos2 = w.z() * w.z();
+        if cos2 == 0.0 {
+            return f64::INFINITY;
+        }
+
+        let x = w.x() * self.alpha_x;
+        let y = w.y() * self.alpha_y;
+        let a = 1.0 / ((x * x + y * y) / cos2).sqrt();
+        if a >= 1.6 {
+            return 0.0;
+        }
+
+        (1.0 - 1.259 * a + 0.396 * a * a) / (3.535 * a + 2.181 * a * a)
+    }
+
+    // Heitz and d'Eon 2014, "Importance Sampling Microfacet-Based BSDFs using the Distribution of
+    // Visible Normals"
+    fn sample_visible(&self, w: &Vec3, u1: f64, u2: f64) -> Vec3 {
+        let mut stretched =
+            Vec3::with_values(self.alpha_x * w.x(), self.alpha_y * w.y(), w.z()).unit_vector();
+        if stretched.z() < 0.0 {
+            stretched = -stretched;
+        }
+
+        let (slope_x, slope_y) = Beckmann::sample_slopes(stretched.z(), u1, u2);
+
+        // Rotate the slopes to the azimuth of the view direction, then unstretch them
+        let sin_theta = (1.0 - stretched.z() * stretched.z()).max(0.0).sqrt();
+        let (cos_phi, sin_phi) = if sin_theta == 0.0 {
+            (1.0, 0.0)
+        } else {
+            (stretched.x() / sin_theta, stretched.y() / sin_theta)
+        };
+        let x = self.alpha_x * (cos_phi * slope_x - sin_phi * slope_y);
+        let y = self.alpha_y * (sin_phi * slope_x + cos_phi * slope_y);
+
+        Vec3::with_values(-x, -y, 1.0).unit_vector()
+    }
+}
+
+// Error function (Abramowitz and Stegun 7.1.26, absolute error below 1.5e-7)
+fn erf(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.3275911 * x.abs());
+    let polynomial = t
+        * (0.254829592
+            + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    let y = 1.0 - polynomial * (-x * x).exp();
+
+    if x < 0.0 {
+        -y
+    } else {
+        y
+    }
+}
+
+// Inverse of the error function (Giles 2010, "Approximating the erfinv function")
+fn erf_inv(x: f64) -> f64 {
+    let x = x.clamp(-0.99999, 0.99999);
+    let mut w = -((1.0 - x) * (1.0 + x)).ln();
+
+    let p = if w < 5.0 {
+        w -= 2.5;
+        let mut p = 2.810_226_36e-08;
+        p = 3.432_739_39e-07 + p * w;
+        p = -3.523_387_7e-06 + p * w;
+        p = -4.391_506_54e-06 + p * w;
+        p = 0.000_218_580_87 + p * w;
+        p = -0.001_253_725_03 + p * w;
+        p = -0.004_177_681_64 + p * w;
+        p = 0.246_640_727 + p * w;
+        1.501_409_41 + p * w
+    } else {
+        w = w.sqrt() - 3.0;
+        let mut p = -0.000_200_214_257;
+        p = 0.000_100_950_558 + p * w;
+        p = 0.001_349_343_22 + p * w;
+        p = -0.003_673_428_44 + p * w;
+        p = 0.005_739_507_73 + p * w;
+        p = -0.007_622_461_3 + p * w;
+        p = 0.009_438_870_47 + p * w;
+        p = 1.001_674_06 + p * w;
+        2.832_976_82 + p * w
+    };
+
+    p * x
+}
+
+// Shading frame around the shading normal of `rec` for the microfacet models. Anisotropic
+// distributions are oriented with their x roughness along the surface's u direction, so that
+// brushed metal follows the texture coordinates. Surfaces without derivatives use the world x
+// axis projected onto them instead (z for surfaces facing along x).
+pub fn shading_frame(rec: &HitRecord) -> Onb {
+    let tangent = if !rec.dpdu.near_zero() {
+        rec.dpdu
+    } else if rec.normal.x().abs() > 0.999 {
+        Vec3::with_values(0.0, 0.0, 1.0)
+    } else {
+        Vec3::with_values(1.0, 0.0, 0.0)
+    };
+
+    Onb::with_tangent(&rec.normal, &tangent)
+}
+
+// Mirror direction of `wo` about the normal `wm`
+pub fn reflect(wo: &Vec3, wm: &Vec3) -> Vec3 {
+    -*wo + 2.0 * wo.dot(wm) * *wm
+}
+
+// Direction `wo` refracts into through a surface with normal `wm`, on the same side as `wo`,
+// where `eta` is the index of refraction of the far side over the one of the side of `wo`. `None`
+// on total internal reflection.
+pub fn refract(wo: &Vec3, wm: &Vec3, eta: f64) -> Option<Vec3> {
+    let cos_theta_i = wo.dot(wm);
+    let sin2_theta_t = (1.0 - cos_theta_i * cos_theta_i).max(0.0) / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        return None;
+    }
+
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+    Some(-*wo / eta + (cos_theta_i / eta - cos_theta_t) * *wm)
+}
+
+// Unpolarized Fresnel reflectance of a dielectric interface, for light arriving at an angle with
+// cosine `cos_theta_i` on the side that `eta` is relative to
+pub fn fresnel_dielectric(cos_theta_i: f64, eta: f64) -> f64 {
+    let mut cos_theta_i = cos_theta_i.clamp(-1.0, 1.0);
+    let mut eta = eta;
+    if cos_theta_i < 0.0 {
+        eta = 1.0 / eta;
+        cos_theta_i = -cos_theta_i;
+    }
+
+    let sin2_theta_t = (1.0 - cos_theta_i * cos_theta_i) / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+
+    let r_parallel = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let r_perpendicular = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+
+    0.5 * (r_parallel * r_parallel + r_perpendicular * r_perpendicular)
+}
+
+// Fresnel reflectance of a conductor with complex index of refraction `eta` + i `k` relative to
+// the outside medium, per color channel
+pub fn fresnel_conductor(cos_theta_i: f64, eta: &Color, k: &Color) -> Color {
+    let cos2 = cos_theta_i.clamp(0.0, 1.0).powi(2);
+    let sin2 = 1.0 - cos2;
+
+    let channel = |eta: f64, k: f64| {
+        let t0 = eta * eta - k * k - sin2;
+        let a2_plus_b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+        let t1 = a2_plus_b2 + cos2;
+        let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+        let t2 = 2.0 * cos2.sqrt() * a;
+        let r_perpendicular = (t1 - t2) / (t1 + t2);
+
+        let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let r_parallel = r_perpendicular * (t3 - t4) / (t3 + t4);
+
+        0.5 * (r_parallel + r_perpendicular)
+    };
+
+    Color::with_values(
+        channel(eta.x(), k.x()),
+        channel(eta.y(), k.y()),
+        channel(eta.z(), k.z()),
+    )
+}
+
+// Cosine-weighted average of `fresnel_dielectric` over the hemisphere, for `eta` >= 1 (fit by
+// Kulla and Conty 2017)
+pub fn average_fresnel_dielectric(eta: f64) -> f64 {
+    ((eta - 1.0) / (4.08567 + 1.00071 * eta)).max(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::Sampler;
+
+    #[test]
+    fn shading_frame_follows_the_u_direction() {
+        let mut rec = HitRecord::new();
+        rec.normal = Vec3::with_values(0.0, 0.0, 1.0);
+        rec.dpdu = Vec3::with_values(0.0, 2.0, 0.5);
+
+        let frame = shading_frame(&rec);
+        assert!((frame.u() - Vec3::with_values(0.0, 1.0, 0.0)).near_zero());
+        assert!((frame.w() - rec.normal).near_zero());
+
+        // Without derivatives the world x axis takes over
+        rec.dpdu = Vec3::new();
+        let frame = shading_frame(&rec);
+        assert!((frame.u() - Vec3::with_values(1.0, 0.0, 0.0)).near_zero());
+    }
+
+    #[test]
+    fn conductor_fresnel_matches_the_known_limits() {
+        let eta = Color::with_values(0.2, 1.5, 1.0);
+        let k = Color::with_values(3.9, 0.0, 2.0);
+
+        // At normal incidence ((eta - 1)^2 + k^2) / ((eta + 1)^2 + k^2)
+        let normal = fresnel_conductor(1.0, &eta, &k);
+        for channel in 0..3 {
+            let (n, k) = (eta[channel], k[channel]);
+            let expected = ((n - 1.0).powi(2) + k * k) / ((n + 1.0).powi(2) + k * k);
+            assert!((normal[channel] - expected).abs() < 1e-12, "{}", normal);
+        }
+
+        // Everything is reflected at grazing angles
+        let grazing = fresnel_conductor(0.0, &eta, &k);
+        let nearly = fresnel_conductor(1e-3, &eta, &k);
+        for channel in 0..3 {
+            assert!((grazing[channel] - 1.0).abs() < 1e-12, "{}", grazing);
+            assert!(
+                nearly[channel] > 0.99 && nearly[channel] < 1.0,
+                "{}",
+                nearly
+            );
+        }
+
+        // Without absorption it is a dielectric
+        let zero = Color::new();
+        for eta in [1.33, 1.5, 2.4] {
+            for cos_theta in [1.0, 0.8, 0.5, 0.2, 0.05] {
+                let conductor =
+                    fresnel_conductor(cos_theta, &Color::with_values(eta, eta, eta), &zero);
+                let dielectric = fresnel_dielectric(cos_theta, eta);
+                assert!(
+                    (conductor.x() - dielectric).abs() < 1e-12,
+                    "eta {} at cos {}: {} != {}",
+                    eta,
+                    cos_theta,
+                    conductor.x(),
+                    dielectric
+                );
+            }
+        }
+    }
+
+    // Fractions of the normals sampled from `w` in bins of polar and azimuthal angle, against the
+    // integral of `visible_d` over each bin
+    fn assert_samples_follow_visible_d(distribution: &dyn MicrofacetDistribution, w: &Vec3) {
+        const THETA_BINS: usize = 6;
+        const PHI_BINS: usize = 8;
+        const SAMPLES: usize = 100_000;
+        let bin = |wm: &Vec3| {
+            let theta = wm.z().clamp(-1.0, 1.0).acos() / (0.5 * PI);
+            let phi = (wm.y().atan2(wm.x()) + PI) / (2.0 * PI);
+            let i = ((theta * THETA_BINS as f64) as usize).min(THETA_BINS - 1);
+            let j = ((phi * PHI_BINS as f64) as usize).min(PHI_BINS - 1);
+            i * PHI_BINS + j
+        };
+
+        let mut sampled = [0.0; THETA_BINS * PHI_BINS];
+        let mut sampler = Sampler::new(17);
+        for _ in 0..SAMPLES {
+            let wm = distribution.sample_visible(w, sampler.random_f64(), sampler.random_f64());
+            assert!(wm.z() > 0.0 && (wm.length() - 1.0).abs() < 1e-9);
+            sampled[bin(&wm)] += 1.0 / SAMPLES as f64;
+        }
+
+        // Midpoint rule over a finer grid of the hemisphere
+        let mut expected = [0.0; THETA_BINS * PHI_BINS];
+        let (steps_theta, steps_phi) = (THETA_BINS * 40, PHI_BINS * 40);
+        let (d_theta, d_phi) = (0.5 * PI / steps_theta as f64, 2.0 * PI / steps_phi as f64);
+        for a in 0..steps_theta {
+            let theta = (a as f64 + 0.5) * d_theta;
+            for b in 0..steps_phi {
+                let phi = (b as f64 + 0.5) * d_phi - PI;
+                let wm = Vec3::with_values(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                );
+                expected[bin(&wm)] +=
+                    distribution.visible_d(w, &wm) * theta.sin() * d_theta * d_phi;
+            }
+        }
+
+        assert!((expected.iter().sum::<f64>() - 1.0).abs() < 0.01);
+        for (k, (sampled, expected)) in sampled.iter().zip(expected).enumerate() {
+            // Five standard deviations of the fraction, plus a little for the fits in Beckmann
+            let tolerance = 5.0 * (expected * (1.0 - expected) / SAMPLES as f64).sqrt() + 0.001;
+            assert!(
+                (sampled - expected).abs() < tolerance,
+                "bin {}: sampled {} but density gives {}",
+                k,
+                sampled,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn visible_normals_are_sampled_by_their_density() {
+        let distributions: [Box<dyn MicrofacetDistribution>; 4] = [
+            Box::new(Ggx::isotropic(0.3)),
+            Box::new(Ggx::new(0.6, 0.15)),
+            Box::new(Beckmann::isotropic(0.3)),
+            Box::new(Beckmann::new(0.15, 0.5)),
+        ];
+        let views = [
+            Vec3::with_values(0.0, 0.0, 1.0),
+            Vec3::with_values(0.6, 0.3, 0.742).unit_vector(),
+            Vec3::with_values(-0.9, 0.2, 0.2).unit_vector(),
+        ];
+
+        for distribution in &distributions {
+            for w in &views {
+                assert_samples_follow_visible_d(distribution.as_ref(), w);
+            }
+        }
+    }
+}
@@ -0,0 +1,301 @@
+use std::sync::Arc;
+
+use crate::{
+    color::Color,
+    hittable::{HitRecord, Hittable},
+    material::Material,
+    microfacet::shading_frame,
+    ray::Ray,
+    sampler::Sampler,
+    texture::Texture,
+    vec3::Vec3,
+};
+
+// Step in u and v over which bump maps take the slope of their height
+const BUMP_DELTA: f64 = 0.001;
+
+// Where the perturbed shading normal comes from
+pub enum NormalSource {
+    // Tangent-space normals encoded as colors, with red along u, green along v and blue out of
+    // the surface, each mapped from [0, 1] to [-1, 1]
+    Map(Arc<dyn Texture>),
+    // Height above the surface, the mean of the color channels, times `scale`
+    Bump {
+        height: Arc<dyn Texture>,
+        scale: f64,
+    },
+}
+
+// Material `base` shaded with the normal bent by a normal or bump map, for detail the geometry
+// doesn't have. The surface itself doesn't move, so light the bent normal would send through
+// it, or take in from behind it, is dropped.
+pub struct NormalMapped {
+    base: Arc<dyn Material>,
+    source: NormalSource,
+}
+
+impl NormalMapped {
+    pub fn new(base: Arc<dyn Material>, map: Arc<dyn Texture>) -> Self {
+        NormalMapped {
+            base,
+            source: NormalSource::Map(map),
+        }
+    }
+
+    pub fn with_bump(base: Arc<dyn Material>, height: Arc<dyn Texture>, scale: f64) -> Self {
+        NormalMapped {
+            base,
+            source: NormalSource::Bump { height, scale },
+        }
+    }
+
+    // `rec` with its shading normal bent
+    fn shading_record(&self, ray_in: &Ray, rec: &HitRecord) -> HitRecord {
+        let mut shaded = rec.clone();
+
+        // Frame of the surface facing out of it, with the tangents following u and v where the
+        // surface has them
+        let normal = if rec.front_face {
+            rec.normal
+        } else {
+            -rec.normal
+        };
+        let tangent = shading_frame(rec).u();
+        let bitangent = normal.cross(&tangent);
+        let bitangent = if bitangent.dot(&rec.dpdv) < 0.0 {
+            -bitangent
+        } else {
+            bitangent
+        };
+
+        let bent = match &self.source {
+            NormalSource::Map(map) => {
+                let encoded = map.value(rec.u, rec.v, &rec.p);
+                (2.0 * encoded.x() - 1.0) * tangent
+                    + (2.0 * encoded.y() - 1.0) * bitangent
+                    + (2.0 * encoded.z() - 1.0) * normal
+            }
+            NormalSource::Bump { height, scale } => {
+                // Surfaces without derivatives get unit steps along the tangents
+                let (dpdu, dpdv) = if rec.dpdu.near_zero() || rec.dpdv.near_zero() {
+                    (tangent, bitangent)
+                } else {
+                    (rec.dpdu, rec.dpdv)
+                };
+                let height_at = |u: f64, v: f64, p: &Vec3| {
+                    let value = height.value(u, v, p);
+                    scale * (value.x() + value.y() + value.z()) / 3.0
+                };
+
+                let center = height_at(rec.u, rec.v, &rec.p);
+                let slope_u = (height_at(rec.u + BUMP_DELTA, rec.v, &(rec.p + BUMP_DELTA * dpdu))
+                    - center)
+                    / BUMP_DELTA;
+                let slope_v = (height_at(rec.u, rec.v + BUMP_DELTA, &(rec.p + BUMP_DELTA * dpdv))
+                    - center)
+                    / BUMP_DELTA;
+
+                // Normal of the surface displaced along the normal by the height
+                let bent = (dpdu + slope_u * normal).cross(&(dpdv + slope_v * normal));
+                if bent.dot(&normal) < 0.0 {
+                    -bent
+                } else {
+                    bent
+                }
+            }
+        };
+
+        if bent.dot(&normal) > 0.0 && !bent.near_zero() {
+            shaded.set_shading_normal(ray_in, &bent.unit_vector());
+        }
+
+        shaded
+    }
+}
+
+impl Material for NormalMapped {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+        sampler: &mut Sampler,
+    ) -> bool {
+        let shaded = self.shading_record(ray_in, rec);
+        if !self
+            .base
+            .scatter(ray_in, &shaded, attenuation, scattered, sampler)
+        {
+            return false;
+        }
+
+        return shaded.is_consistent(&scattered.direction());
+    }
+
+    fn scatter_in_world(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        world: &dyn Hittable,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+        sampler: &mut Sampler,
+    ) -> bool {
+        let shaded = self.shading_record(ray_in, rec);
+        self.base
+            .scatter_in_world(ray_in, &shaded, world, attenuation, scattered, sampler)
+    }
+
+    fn emitted(&self, ray_in: &Ray, rec: &HitRecord) -> Color {
+        self.base.emitted(ray_in, rec)
+    }
+
+    fn is_spectral(&self, ray_in: &Ray) -> bool {
+        self.base.is_spectral(ray_in)
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        let shaded = self.shading_record(ray_in, rec);
+        if !shaded.is_consistent(direction) {
+            return Color::new();
+        }
+
+        self.base.eval(ray_in, &shaded, direction)
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        let shaded = self.shading_record(ray_in, rec);
+        if !shaded.is_consistent(direction) {
+            return 0.0;
+        }
+
+        self.base.scattering_pdf(ray_in, &shaded, direction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        hittable::testing::assert_near, material::Lambertian, texture::SolidColor, vec3::Point3,
+    };
+
+    fn white() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Color::with_values(1.0, 1.0, 1.0)))
+    }
+
+    fn normal_map(encoded: Color) -> NormalMapped {
+        NormalMapped::new(white(), Arc::new(SolidColor::new(encoded)))
+    }
+
+    // Hit on the plane z = 0 at the origin, with uv derivatives along x and y, for a ray
+    // arriving along `direction`
+    fn plane_hit(direction: Vec3) -> (Ray, HitRecord) {
+        let ray = Ray::with_values(-direction, direction);
+        let mut rec = HitRecord::new();
+        rec.t = 1.0;
+        rec.p = Point3::new();
+        rec.u = 0.5;
+        rec.v = 0.5;
+        rec.dpdu = Vec3::with_values(1.0, 0.0, 0.0);
+        rec.dpdv = Vec3::with_values(0.0, 1.0, 0.0);
+        rec.set_face_normal(&ray, &Vec3::with_values(0.0, 0.0, 1.0));
+        (ray, rec)
+    }
+
+    #[test]
+    fn flat_normal_map_keeps_the_normal() {
+        let mat = normal_map(Color::with_values(0.5, 0.5, 1.0));
+        for direction in [
+            Vec3::with_values(0.3, 0.2, -1.0),
+            Vec3::with_values(-0.5, 0.1, 1.0),
+        ] {
+            let (ray, rec) = plane_hit(direction);
+            let shaded = mat.shading_record(&ray, &rec);
+            assert_near(shaded.normal, rec.normal);
+            assert_near(shaded.geometric_normal, rec.geometric_normal);
+        }
+    }
+
+    #[test]
+    fn constant_bump_map_keeps_the_normal() {
+        let mat = NormalMapped::with_bump(white(), Arc::new(SolidColor::gray(0.7)), 2.0);
+        let (ray, rec) = plane_hit(Vec3::with_values(0.3, 0.2, -1.0));
+        assert_near(mat.shading_record(&ray, &rec).normal, rec.normal);
+
+        // Also with the unit steps taken on surfaces without derivatives
+        let mut bare = rec.clone();
+        bare.dpdu = Vec3::new();
+        bare.dpdv = Vec3::new();
+        assert_near(mat.shading_record(&ray, &bare).normal, rec.normal);
+    }
+
+    #[test]
+    fn tilted_normal_map_leans_along_u() {
+        let mat = normal_map(Color::with_values(1.0, 0.5, 1.0));
+        let (ray, rec) = plane_hit(Vec3::with_values(0.0, 0.0, -1.0));
+        let shaded = mat.shading_record(&ray, &rec);
+        assert_near(
+            shaded.normal,
+            Vec3::with_values(1.0, 0.0, 1.0).unit_vector(),
+        );
+    }
+
+    // Normals bent to or past the horizon of the geometry, seen from either side and at
+    // grazing angles, must neither send light through the surface nor turn it black
+    #[test]
+    fn bent_normals_never_scatter_below_the_surface() {
+        let mut sampler = Sampler::new(4);
+        let maps = [
+            Color::with_values(1.0, 0.5, 0.52),
+            Color::with_values(0.0, 1.0, 0.5),
+            Color::with_values(1.0, 0.5, 0.0),
+        ];
+        let directions = [
+            Vec3::with_values(0.0, 0.0, -1.0),
+            Vec3::with_values(1.0, 0.0, -0.05),
+            Vec3::with_values(-1.0, 0.3, -0.05),
+            Vec3::with_values(0.2, -1.0, 0.1),
+        ];
+
+        for encoded in maps {
+            let mat = normal_map(encoded);
+            for direction in directions {
+                let (ray, rec) = plane_hit(direction);
+                let mut scattered_count = 0;
+
+                for _ in 0..1000 {
+                    let mut attenuation = Color::new();
+                    let mut scattered = Ray::new();
+                    if mat.scatter(&ray, &rec, &mut attenuation, &mut scattered, &mut sampler) {
+                        scattered_count += 1;
+                        assert!(
+                            scattered.direction().dot(&rec.geometric_normal) > 0.0,
+                            "map {} lets light arriving along {} through",
+                            encoded,
+                            direction
+                        );
+                    }
+
+                    // Nor is light taken in from behind the surface
+                    let random = Vec3::random_unit_vector(&mut sampler);
+                    let below = if random.dot(&rec.geometric_normal) > 0.0 {
+                        -random
+                    } else {
+                        random
+                    };
+                    assert_eq!(mat.eval(&ray, &rec, &below).x(), 0.0);
+                    assert_eq!(mat.scattering_pdf(&ray, &rec, &below), 0.0);
+                }
+
+                assert!(
+                    scattered_count > 200,
+                    "map {} turns light arriving along {} black",
+                    encoded,
+                    direction
+                );
+            }
+        }
+    }
+}
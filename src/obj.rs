@@ -0,0 +1,530 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    color::Color,
+    material::{Dielectric, Lambertian, Material, Metal},
+    mesh::{Mesh, TriangleMesh},
+    texture::{ImageTexture, Texture},
+    vec3::{Point3, Vec3},
+};
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+    // The file parsed, but its faces don't make a consistent mesh
+    Mesh {
+        path: PathBuf,
+        message: String,
+    },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            ObjError::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+            ObjError::Mesh { path, message } => write!(f, "{}: {}", path.display(), message),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+// Surface description of a single `newmtl` entry. Only the values that map onto our materials
+// are kept.
+struct MtlMaterial {
+    diffuse: Color,
+    diffuse_texture: Option<Arc<dyn Texture>>,
+    specular: Color,
+    shininess: f64,
+    ior: f64,
+    dissolve: f64,
+    illum: i32,
+}
+
+impl MtlMaterial {
+    fn new() -> MtlMaterial {
+        MtlMaterial {
+            diffuse: Color::with_values(0.8, 0.8, 0.8),
+            diffuse_texture: None,
+            specular: Color::new(),
+            shininess: 0.0,
+            ior: 1.0,
+            dissolve: 1.0,
+            illum: 2,
+        }
+    }
+
+    // Transparent surfaces (dissolve below one or a refraction illumination model) become glass,
+    // mirror-like ones with a dominant specular color become metal and the rest stay diffuse.
+    fn to_material(&self) -> Arc<dyn Material> {
+        let transparent = self.dissolve < 1.0 || matches!(self.illum, 4 | 6 | 7 | 9);
+        if transparent {
+            let ior = if self.ior > 1.0 { self.ior } else { 1.5 };
+            return Arc::new(Dielectric::new(ior));
+        }
+
+        let max_specular = self
+            .specular
+            .x()
+            .max(self.specular.y())
+            .max(self.specular.z());
+        let max_diffuse = self.diffuse.x().max(self.diffuse.y()).max(self.diffuse.z());
+        let reflective = matches!(self.illum, 3 | 5 | 8);
+        if max_specular > 0.0 && (reflective || max_specular > max_diffuse) {
+            // Map the Phong exponent onto a fuzz radius, sharper highlights give less fuzz
+            let fuzz = (2.0 / (self.shininess + 2.0)).sqrt().clamp(0.0, 1.0);
+            return Arc::new(Metal::new(self.specular, fuzz));
+        }
+
+        match &self.diffuse_texture {
+            Some(texture) => Arc::new(Lambertian::with_texture(texture.clone())),
+            None => Arc::new(Lambertian::new(self.diffuse)),
+        }
+    }
+}
+
+struct Parser<'a> {
+    path: &'a Path,
+    line: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: impl Into<String>) -> ObjError {
+        ObjError::Parse {
+            path: self.path.to_path_buf(),
+            line: self.line,
+            message: message.into(),
+        }
+    }
+
+    fn float(&self, token: Option<&str>, what: &str) -> Result<f64, ObjError> {
+        let token = token.ok_or_else(|| self.error(format!("missing {}", what)))?;
+        token
+            .parse::<f64>()
+            .map_err(|_| self.error(format!("invalid {} '{}'", what, token)))
+    }
+
+    fn vec3(&self, tokens: &mut std::str::SplitWhitespace, what: &str) -> Result<Vec3, ObjError> {
+        let x = self.float(tokens.next(), what)?;
+        let y = self.float(tokens.next(), what)?;
+        let z = self.float(tokens.next(), what)?;
+        Ok(Vec3::with_values(x, y, z))
+    }
+
+    // Resolves a 1-based (or negative, relative to the end) OBJ index into a buffer of `count`
+    // elements.
+    fn index(&self, token: &str, count: usize, what: &str) -> Result<usize, ObjError> {
+        let raw = token
+            .parse::<i64>()
+            .map_err(|_| self.error(format!("invalid {} index '{}'", what, token)))?;
+
+        let resolved = if raw > 0 { raw - 1 } else { count as i64 + raw };
+
+        if raw == 0 || resolved < 0 || resolved >= count as i64 {
+            return Err(self.error(format!("{} index {} out of range", what, raw)));
+        }
+
+        Ok(resolved as usize)
+    }
+}
+
+fn open(path: &Path) -> Result<BufReader<File>, ObjError> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|error| ObjError::Io {
+            path: path.to_path_buf(),
+            error,
+        })
+}
+
+fn parse_mtl(path: &Path, materials: &mut HashMap<String, MtlMaterial>) -> Result<(), ObjError> {
+    let reader = open(path)?;
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+    let mut parser = Parser { path, line: 0 };
+    let mut current: Option<String> = None;
+
+    for line in reader.lines() {
+        parser.line += 1;
+        let line = line.map_err(|error| ObjError::Io {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        let mut tokens = line.split_whitespace();
+
+        let keyword = match tokens.next() {
+            Some(keyword) if !keyword.starts_with('#') => keyword,
+            _ => continue,
+        };
+
+        if keyword == "newmtl" {
+            let name = tokens.collect::<Vec<_>>().join(" ");
+            if name.is_empty() {
+                return Err(parser.error("newmtl without a name"));
+            }
+            materials.insert(name.clone(), MtlMaterial::new());
+            current = Some(name);
+            continue;
+        }
+
+        let mtl = match &current {
+            Some(name) => materials.get_mut(name).unwrap(),
+            None => return Err(parser.error(format!("'{}' before any newmtl", keyword))),
+        };
+
+        match keyword {
+            "Kd" => mtl.diffuse = parser.vec3(&mut tokens, "Kd color")?,
+            "Ks" => mtl.specular = parser.vec3(&mut tokens, "Ks color")?,
+            "Ns" => mtl.shininess = parser.float(tokens.next(), "Ns exponent")?,
+            "Ni" => mtl.ior = parser.float(tokens.next(), "Ni index of refraction")?,
+            "d" => mtl.dissolve = parser.float(tokens.next(), "dissolve")?,
+            "Tr" => mtl.dissolve = 1.0 - parser.float(tokens.next(), "transparency")?,
+            "illum" => mtl.illum = parser.float(tokens.next(), "illumination model")? as i32,
+            "map_Kd" => {
+                // Options such as -bm or -s are not supported, the file name comes last
+                let file = tokens
+                    .last()
+                    .ok_or_else(|| parser.error("map_Kd without a file"))?;
+                let texture = ImageTexture::load(&base_dir.join(file))
+                    .map_err(|e| parser.error(format!("map_Kd: {}", e)))?;
+                mtl.diffuse_texture = Some(Arc::new(texture));
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+// Loads a Wavefront OBJ file into a single triangle mesh. Materials come from the `mtllib` files
+// it references; faces without a `usemtl` use `default_mat`. Polygons are triangulated as fans.
+pub fn load_obj(path: &Path, default_mat: Arc<dyn Material>) -> Result<TriangleMesh, ObjError> {
+    let (mesh, materials) = read_obj(path, default_mat)?;
+    TriangleMesh::with_materials(mesh, materials).map_err(|message| ObjError::Mesh {
+        path: path.to_path_buf(),
+        message,
+    })
+}
+
+// Normal of a mesh vertex, either from a `vn` entry or, for corners that have none in a file
+// where other faces do, the geometric normal of the face it belongs to.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum VertexNormal {
+    Vertex(usize),
+    Face(usize),
+}
+
+// Position, uv and normal indices of one face corner
+type Corner = (usize, Option<usize>, Option<usize>);
+
+struct Face {
+    corners: Vec<Corner>,
+    material: usize,
+}
+
+fn read_obj(
+    path: &Path,
+    default_mat: Arc<dyn Material>,
+) -> Result<(Mesh, Vec<Arc<dyn Material>>), ObjError> {
+    let reader = open(path)?;
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+    let mut parser = Parser { path, line: 0 };
+
+    let mut positions: Vec<Point3> = Vec::new();
+    let mut normals: Vec<Vec3> = Vec::new();
+    let mut uvs: Vec<(f64, f64)> = Vec::new();
+
+    let mut mtl_materials: HashMap<String, MtlMaterial> = HashMap::new();
+    let mut materials: Vec<Arc<dyn Material>> = vec![default_mat];
+    let mut material_slots: HashMap<String, usize> = HashMap::new();
+    let mut current_material = 0;
+
+    // Faces are built into the mesh once the whole file is read, when it is known whether any
+    // of them has normals.
+    let mut faces: Vec<Face> = Vec::new();
+    let mut any_uvs = false;
+    let mut any_normals = false;
+
+    for line in reader.lines() {
+        parser.line += 1;
+        let line = line.map_err(|error| ObjError::Io {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        let mut tokens = line.split_whitespace();
+
+        let keyword = match tokens.next() {
+            Some(keyword) if !keyword.starts_with('#') => keyword,
+            _ => continue,
+        };
+
+        match keyword {
+            "v" => positions.push(parser.vec3(&mut tokens, "vertex position")?),
+            "vn" => normals.push(parser.vec3(&mut tokens, "vertex normal")?),
+            "vt" => {
+                let u = parser.float(tokens.next(), "texture coordinate")?;
+                let v = match tokens.next() {
+                    Some(token) => parser.float(Some(token), "texture coordinate")?,
+                    None => 0.0,
+                };
+                uvs.push((u, v));
+            }
+            "f" => {
+                let mut corners = Vec::new();
+
+                for corner in tokens {
+                    let mut parts = corner.split('/');
+                    let position =
+                        parser.index(parts.next().unwrap(), positions.len(), "vertex")?;
+                    let uv = match parts.next() {
+                        Some(part) if !part.is_empty() => {
+                            Some(parser.index(part, uvs.len(), "texture coordinate")?)
+                        }
+                        _ => None,
+                    };
+                    let normal = match parts.next() {
+                        Some(part) if !part.is_empty() => {
+                            Some(parser.index(part, normals.len(), "normal")?)
+                        }
+                        _ => None,
+                    };
+
+                    any_uvs |= uv.is_some();
+                    any_normals |= normal.is_some();
+                    corners.push((position, uv, normal));
+                }
+
+                if corners.len() < 3 {
+                    return Err(parser.error("face with fewer than three vertices"));
+                }
+
+                faces.push(Face {
+                    corners,
+                    material: current_material,
+                });
+            }
+            "mtllib" => {
+                let file = tokens.collect::<Vec<_>>().join(" ");
+                if file.is_empty() {
+                    return Err(parser.error("mtllib without a file name"));
+                }
+                parse_mtl(&base_dir.join(file), &mut mtl_materials)?;
+            }
+            "usemtl" => {
+                let name = tokens.collect::<Vec<_>>().join(" ");
+                current_material = match material_slots.get(&name) {
+                    Some(&slot) => slot,
+                    None => {
+                        let mtl = mtl_materials
+                            .get(&name)
+                            .ok_or_else(|| parser.error(format!("unknown material '{}'", name)))?;
+                        materials.push(mtl.to_material());
+                        material_slots.insert(name, materials.len() - 1);
+                        materials.len() - 1
+                    }
+                };
+            }
+            // Groups, objects, smoothing groups and anything else do not affect the geometry
+            _ => {}
+        }
+    }
+
+    // OBJ indexes positions, uvs and normals separately, so every distinct combination becomes
+    // one mesh vertex.
+    let mut vertex_slots: HashMap<(usize, Option<usize>, Option<VertexNormal>), usize> =
+        HashMap::new();
+    let mut mesh = Mesh::new();
+
+    for (face_index, face) in faces.iter().enumerate() {
+        // Newell's method, which also handles non-convex polygons
+        let face_normal = || {
+            let mut normal = Vec3::new();
+            for (i, &(a, _, _)) in face.corners.iter().enumerate() {
+                let (b, _, _) = face.corners[(i + 1) % face.corners.len()];
+                normal += positions[a].cross(&positions[b]);
+            }
+            if normal.near_zero() {
+                normal
+            } else {
+                normal.unit_vector()
+            }
+        };
+
+        let mut corners = Vec::with_capacity(face.corners.len());
+        for &(position, uv, normal) in &face.corners {
+            let normal = match normal {
+                Some(i) => Some(VertexNormal::Vertex(i)),
+                None if any_normals => Some(VertexNormal::Face(face_index)),
+                None => None,
+            };
+
+            let slot = *vertex_slots
+                .entry((position, uv, normal))
+                .or_insert_with(|| {
+                    mesh.positions.push(positions[position]);
+                    mesh.uvs.push(uv.map_or((0.0, 0.0), |i| uvs[i]));
+                    mesh.normals.push(match normal {
+                        Some(VertexNormal::Vertex(i)) => normals[i],
+                        Some(VertexNormal::Face(_)) => face_normal(),
+                        None => Vec3::new(),
+                    });
+                    mesh.positions.len() - 1
+                });
+            corners.push(slot);
+        }
+
+        for i in 1..corners.len() - 1 {
+            mesh.indices.push([corners[0], corners[i], corners[i + 1]]);
+            mesh.face_materials.push(face.material);
+        }
+    }
+
+    if !any_uvs {
+        mesh.uvs.clear();
+    }
+    if !any_normals {
+        mesh.normals.clear();
+    }
+
+    Ok((mesh, materials))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // Writes the given files into a fresh directory and loads the first one
+    fn read(files: &[(&str, &str)]) -> Result<(Mesh, Vec<Arc<dyn Material>>), ObjError> {
+        let dir = std::env::temp_dir().join(format!(
+            "obj-test-{}-{:?}",
+            std::process::id(),
+            std::thread::current().id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        for (name, contents) in files {
+            fs::write(dir.join(name), contents).unwrap();
+        }
+        let result = read_obj(
+            &dir.join(files[0].0),
+            Arc::new(Lambertian::new(Color::new())),
+        );
+        fs::remove_dir_all(&dir).unwrap();
+        result
+    }
+
+    fn assert_vec(actual: Vec3, expected: [f64; 3]) {
+        let actual = [actual.x(), actual.y(), actual.z()];
+        assert_eq!(actual, expected);
+    }
+
+    const SQUARE: &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n";
+
+    #[test]
+    fn parses_every_face_corner_form() {
+        let obj = format!(
+            "{}vt 0 0\nvt 1 0\nvt 1 1\nvn 0 0 1\n\
+             f 1 2 3\nf 1/1 2/2 3/3\nf 1//1 2//1 3//1\nf 1/1/1 2/2/1 3/3/1\n",
+            SQUARE
+        );
+        let (mesh, _) = read(&[("forms.obj", &obj)]).unwrap();
+        assert_eq!(mesh.triangle_count(), 4);
+        assert_eq!(mesh.uvs.len(), mesh.positions.len());
+        assert_eq!(mesh.normals.len(), mesh.positions.len());
+
+        let [_, i1, _] = mesh.indices[3];
+        assert_vec(mesh.positions[i1], [1.0, 0.0, 0.0]);
+        assert_eq!(mesh.uvs[i1], (1.0, 0.0));
+        assert_vec(mesh.normals[i1], [0.0, 0.0, 1.0]);
+
+        // Identical corners share a vertex
+        let (same, _) = read(&[("shared.obj", &format!("{}f 1 2 3\nf 1 3 4\n", SQUARE))]).unwrap();
+        assert_eq!(same.positions.len(), 4);
+        assert!(same.normals.is_empty() && same.uvs.is_empty());
+    }
+
+    #[test]
+    fn resolves_negative_indices_from_the_end() {
+        let obj = "v 5 5 5\nv 0 0 0\nv 1 0 0\nv 0 1 0\nf -3 -2 -1\n";
+        let (mesh, _) = read(&[("negative.obj", obj)]).unwrap();
+        let [i0, i1, i2] = mesh.indices[0];
+        assert_vec(mesh.positions[i0], [0.0, 0.0, 0.0]);
+        assert_vec(mesh.positions[i1], [1.0, 0.0, 0.0]);
+        assert_vec(mesh.positions[i2], [0.0, 1.0, 0.0]);
+
+        for face in ["f -4 -2 -1", "f 0 1 2", "f 1 2 9"] {
+            let obj = format!("v 0 0 0\nv 1 0 0\nv 0 1 0\n{}\n", face);
+            match read(&[("range.obj", &obj)]) {
+                Err(ObjError::Parse { line, .. }) => assert_eq!(line, 4),
+                _ => panic!("'{}' should fail to parse", face),
+            }
+        }
+    }
+
+    #[test]
+    fn triangulates_polygons_as_fans() {
+        let obj = "v 0 0 0\nv 2 0 0\nv 3 1 0\nv 1 2 0\nv -1 1 0\nf 1 2 3 4 5\n";
+        let (mesh, _) = read(&[("fan.obj", obj)]).unwrap();
+        assert_eq!(mesh.indices, vec![[0, 1, 2], [0, 2, 3], [0, 3, 4]]);
+
+        assert!(read(&[("line.obj", "v 0 0 0\nv 1 0 0\nf 1 2\n")]).is_err());
+    }
+
+    #[test]
+    fn assigns_materials_per_face() {
+        let mtl = "newmtl red\nKd 1 0 0\n\nnewmtl mirror\nKs 1 1 1\nillum 3\n";
+        let obj = format!(
+            "mtllib scene.mtl\n{}f 1 2 3\nusemtl red\nf 1 3 4\nusemtl mirror\nf 1 2 4\n\
+             usemtl red\nf 2 3 4\n",
+            SQUARE
+        );
+        let (mesh, materials) = read(&[("scene.obj", &obj), ("scene.mtl", mtl)]).unwrap();
+        assert_eq!(materials.len(), 3);
+        assert_eq!(mesh.face_materials, vec![0, 1, 2, 1]);
+
+        let unknown = format!("{}usemtl missing\n", SQUARE);
+        match read(&[("unknown.obj", &unknown)]) {
+            Err(ObjError::Parse { line, message, .. }) => {
+                assert_eq!(line, 5);
+                assert!(message.contains("missing"), "{}", message);
+            }
+            _ => panic!("an unknown material should fail to load"),
+        }
+    }
+
+    #[test]
+    fn faces_without_normals_use_their_geometric_normal() {
+        // The first face is smooth, the second one, facing -z, has no normals
+        let obj = format!("{}vn 0 0 1\nf 1//1 2//1 3//1\nf 1 4 3\n", SQUARE);
+        let (mesh, _) = read(&[("mixed.obj", &obj)]).unwrap();
+        assert_eq!(mesh.normals.len(), mesh.positions.len());
+
+        for &i in &mesh.indices[1] {
+            assert_vec(mesh.normals[i], [0.0, 0.0, -1.0]);
+        }
+        // Corners 1 and 3 appear in both faces, but keep their own normal in each
+        assert_eq!(mesh.positions.len(), 6);
+        for &i in &mesh.indices[0] {
+            assert_vec(mesh.normals[i], [0.0, 0.0, 1.0]);
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use crate::vec3::Vec3;
+
+// Orthonormal basis whose w axis is aligned with a given direction
+pub struct Onb {
+    axis: [Vec3; 3],
+}
+
+impl Onb {
+    pub fn new(n: &Vec3) -> Onb {
+        let w = n.unit_vector();
+        let a = if w.x().abs() > 0.9 {
+            Vec3::with_values(0.0, 1.0, 0.0)
+        } else {
+            Vec3::with_values(1.0, 0.0, 0.0)
+        };
+        let v = w.cross(&a).unit_vector();
+        let u = w.cross(&v);
+
+        Onb { axis: [u, v, w] }
+    }
+
+    // Basis with w along `n` and u along the part of `tangent` perpendicular to it, or any basis
+    // around `n` when `tangent` is (nearly) parallel to it
+    pub fn with_tangent(n: &Vec3, tangent: &Vec3) -> Onb {
+        let w = n.unit_vector();
+        let u = *tangent - tangent.dot(&w) * w;
+        if u.length_squared() < 1e-6 * tangent.length_squared() {
+            return Onb::new(n);
+        }
+        let u = u.unit_vector();
+        let v = w.cross(&u);
+
+        Onb { axis: [u, v, w] }
+    }
+
+    pub fn u(&self) -> Vec3 {
+        self.axis[0]
+    }
+
+    pub fn v(&self) -> Vec3 {
+        self.axis[1]
+    }
+
+    pub fn w(&self) -> Vec3 {
+        self.axis[2]
+    }
+
+    // Transform from basis coordinates to local space
+    pub fn transform(&self, v: &Vec3) -> Vec3 {
+        (v[0] * self.axis[0]) + (v[1] * self.axis[1]) + (v[2] * self.axis[2])
+    }
+
+    // Transform from local space to basis coordinates
+    pub fn inverse_transform(&self, v: &Vec3) -> Vec3 {
+        Vec3::with_values(
+            v.dot(&self.axis[0]),
+            v.dot(&self.axis[1]),
+            v.dot(&self.axis[2]),
+        )
+    }
+}
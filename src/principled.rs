@@ -0,0 +1,447 @@
+use std::{f64::consts::PI, sync::Arc};
+
+use crate::{
+    color::Color,
+    hittable::HitRecord,
+    material::Material,
+    microfacet::{
+        average_fresnel_dielectric, fresnel_dielectric, reflect, refract, Ggx,
+        MicrofacetDistribution,
+    },
+    onb::Onb,
+    ray::Ray,
+    sampler::Sampler,
+    texture::{SolidColor, Texture},
+    vec3::Vec3,
+};
+
+// Index of refraction of the clearcoat layer
+const CLEARCOAT_IOR: f64 = 1.5;
+
+// Disney-style principled BSDF, a single material spanning plastics, metals, coated paints and
+// rough glass. Scalar parameters are textures too, of which the mean of the color channels is
+// used, so every parameter can vary over the surface.
+//
+// The lobes are layered so that they never reflect more light than arrives: a clearcoat on top
+// lets through what it doesn't reflect, below it the metallic and transmissive fractions select
+// a GGX conductor and a rough dielectric, and the rest is a GGX dielectric specular layer over a
+// diffuse base. The diffuse base only receives the light the specular layer transmits on the
+// way in and out, renormalized so that a white base under a smooth coat reflects everything.
+pub struct Principled {
+    pub base_color: Arc<dyn Texture>,
+    // 0 is a dielectric, 1 a metal whose reflectance is the base color
+    pub metallic: Arc<dyn Texture>,
+    pub roughness: Arc<dyn Texture>,
+    // Reflectance of the dielectric specular layer, 0.5 is 4% at normal incidence
+    pub specular: Arc<dyn Texture>,
+    pub clearcoat: Arc<dyn Texture>,
+    pub clearcoat_roughness: Arc<dyn Texture>,
+    // Fraction of the diffuse base replaced by white at grazing angles, as seen on cloth
+    pub sheen: Arc<dyn Texture>,
+    // Fraction of the dielectric that is glass rather than the diffuse base
+    pub transmission: Arc<dyn Texture>,
+    // Index of refraction of the transmissive part
+    pub ior: f64,
+}
+
+impl Principled {
+    pub fn new(base_color: Color) -> Self {
+        Principled::with_texture(Arc::new(SolidColor::new(base_color)))
+    }
+
+    // Rough white plastic in the given color, with the other parameters set with the fields
+    pub fn with_texture(base_color: Arc<dyn Texture>) -> Self {
+        let constant = |value: f64| -> Arc<dyn Texture> { Arc::new(SolidColor::gray(value)) };
+
+        Principled {
+            base_color,
+            metallic: constant(0.0),
+            roughness: constant(0.5),
+            specular: constant(0.5),
+            clearcoat: constant(0.0),
+            clearcoat_roughness: constant(0.05),
+            sheen: constant(0.0),
+            transmission: constant(0.0),
+            ior: 1.5,
+        }
+    }
+
+    fn lobes(&self, ray_in: &Ray, rec: &HitRecord) -> Option<Lobes> {
+        let frame = Onb::new(&rec.normal);
+        let wo = frame.inverse_transform(&(-ray_in.direction().unit_vector()));
+        if wo.z() <= 0.0 {
+            return None;
+        }
+
+        let scalar = |tex: &Arc<dyn Texture>| {
+            let value = tex.value(rec.u, rec.v, &rec.p);
+            ((value.x() + value.y() + value.z()) / 3.0).clamp(0.0, 1.0)
+        };
+
+        let metallic = scalar(&self.metallic);
+        let transmission = scalar(&self.transmission);
+        let clearcoat = scalar(&self.clearcoat);
+
+        // Specular 0 to 1 maps to a reflectance of 0 to 8% at normal incidence
+        let f0 = 0.08 * scalar(&self.specular);
+        let specular_eta = (1.0 + f0.sqrt()) / (1.0 - f0.sqrt());
+
+        // Lobe weights seen from wo, which sum to one
+        let coat = clearcoat * fresnel_dielectric(wo.z(), CLEARCOAT_IOR);
+        let under_coat = 1.0 - coat;
+        let dielectric = under_coat * (1.0 - metallic) * (1.0 - transmission);
+        let specular_reflectance = fresnel_dielectric(wo.z(), specular_eta);
+        let weights = [
+            coat,
+            under_coat * metallic,
+            under_coat * (1.0 - metallic) * transmission,
+            dielectric * specular_reflectance,
+            dielectric * (1.0 - specular_reflectance),
+        ];
+
+        Some(Lobes {
+            frame,
+            wo,
+            base_color: self.base_color.value(rec.u, rec.v, &rec.p),
+            metallic,
+            transmission,
+            clearcoat,
+            sheen: scalar(&self.sheen),
+            specular_eta,
+            glass_eta: if rec.front_face {
+                self.ior
+            } else {
+                1.0 / self.ior
+            },
+            entering: rec.front_face,
+            distribution: Ggx::from_roughness(scalar(&self.roughness)),
+            clearcoat_distribution: Ggx::from_roughness(scalar(&self.clearcoat_roughness)),
+            weights,
+        })
+    }
+}
+
+impl Material for Principled {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+        sampler: &mut Sampler,
+    ) -> bool {
+        let lobes = match self.lobes(ray_in, rec) {
+            Some(lobes) => lobes,
+            None => return false,
+        };
+
+        let wi = match lobes.sample(sampler) {
+            Some(wi) => wi,
+            None => return false,
+        };
+
+        let pdf = lobes.pdf(&wi);
+        if pdf <= 0.0 {
+            return false;
+        }
+
+        *attenuation = lobes.eval(&wi) / pdf;
+        *scattered = Ray::with_time(rec.p, lobes.frame.transform(&wi), ray_in.time());
+
+        return true;
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        match self.lobes(ray_in, rec) {
+            Some(lobes) => lobes.eval(&lobes.frame.inverse_transform(&direction.unit_vector())),
+            None => Color::new(),
+        }
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        match self.lobes(ray_in, rec) {
+            Some(lobes) => lobes.pdf(&lobes.frame.inverse_transform(&direction.unit_vector())),
+            None => 0.0,
+        }
+    }
+}
+
+// Parameters of a Principled material at one hit point, in the shading frame of the hit
+struct Lobes {
+    frame: Onb,
+    wo: Vec3,
+    base_color: Color,
+    metallic: f64,
+    transmission: f64,
+    clearcoat: f64,
+    sheen: f64,
+    specular_eta: f64,
+    // Index of refraction of the side wi transmits into over the one of wo
+    glass_eta: f64,
+    entering: bool,
+    distribution: Ggx,
+    clearcoat_distribution: Ggx,
+    // Chance of sampling the clearcoat, metal, glass, specular and diffuse lobes
+    weights: [f64; 5],
+}
+
+impl Lobes {
+    const CLEARCOAT: usize = 0;
+    const METAL: usize = 1;
+    const GLASS: usize = 2;
+    const SPECULAR: usize = 3;
+    const DIFFUSE: usize = 4;
+
+    fn sample(&self, sampler: &mut Sampler) -> Option<Vec3> {
+        let mut choice = sampler.random_f64();
+        let mut lobe = Lobes::DIFFUSE;
+        for (i, weight) in self.weights.iter().enumerate() {
+            if choice < *weight {
+                lobe = i;
+                break;
+            }
+            choice -= weight;
+        }
+
+        let u1 = sampler.random_f64();
+        let u2 = sampler.random_f64();
+        let wo = &self.wo;
+
+        // Reflections must stay above the surface and refractions go below it, samples that
+        // end up on the wrong side are lost to single scattering
+        let (wi, transmitted) = match lobe {
+            Lobes::CLEARCOAT => (
+                reflect(wo, &self.clearcoat_distribution.sample_visible(wo, u1, u2)),
+                false,
+            ),
+            Lobes::METAL | Lobes::SPECULAR => (
+                reflect(wo, &self.distribution.sample_visible(wo, u1, u2)),
+                false,
+            ),
+            Lobes::GLASS => {
+                let wm = self.distribution.sample_visible(wo, u1, u2);
+                let reflectance = fresnel_dielectric(wo.dot(&wm), self.glass_eta);
+                match refract(wo, &wm, self.glass_eta) {
+                    Some(wt) if sampler.random_f64() >= reflectance => (wt, true),
+                    _ => (reflect(wo, &wm), false),
+                }
+            }
+            _ => {
+                // Cosine-weighted hemisphere
+                let r = u1.sqrt();
+                let phi = 2.0 * PI * u2;
+                let wi = Vec3::with_values(r * phi.cos(), r * phi.sin(), (1.0 - u1).sqrt());
+                (wi, false)
+            }
+        };
+
+        if wi.near_zero() || (wi.z() < 0.0) != transmitted {
+            return None;
+        }
+
+        Some(wi.unit_vector())
+    }
+
+    // BSDF times the cosine of wi
+    fn eval(&self, wi: &Vec3) -> Color {
+        let wo = &self.wo;
+        let white = Color::with_values(1.0, 1.0, 1.0);
+
+        if wi.z() < 0.0 {
+            // Only the glass transmits, tinted by the base color on the way in
+            let tint = if self.entering {
+                self.base_color
+            } else {
+                white
+            };
+            let weight = (1.0 - self.clearcoat * fresnel_dielectric(wo.z(), CLEARCOAT_IOR))
+                * (1.0 - self.metallic)
+                * self.transmission;
+            return weight * self.glass_transmission(wi) * tint;
+        }
+
+        let wm = *wo + *wi;
+        if wi.z() == 0.0 || wm.near_zero() {
+            return Color::new();
+        }
+        let wm = wm.unit_vector();
+
+        let coat_out = self.clearcoat * fresnel_dielectric(wo.z(), CLEARCOAT_IOR);
+        let coat_in = self.clearcoat * fresnel_dielectric(wi.z(), CLEARCOAT_IOR);
+        let clearcoat = self.clearcoat
+            * self.microfacet_reflection(
+                &self.clearcoat_distribution,
+                wi,
+                &wm,
+                fresnel_dielectric(wo.dot(&wm), CLEARCOAT_IOR),
+            );
+
+        // Schlick's approximation with the base color as the reflectance at normal incidence
+        let schlick = (1.0 - wo.dot(&wm).clamp(0.0, 1.0)).powi(5);
+        let metal_fresnel = self.base_color + (white - self.base_color) * schlick;
+        let metal = self.microfacet_reflection(&self.distribution, wi, &wm, 1.0) * metal_fresnel;
+
+        let glass = self.microfacet_reflection(
+            &self.distribution,
+            wi,
+            &wm,
+            fresnel_dielectric(wo.dot(&wm), self.glass_eta),
+        );
+
+        let specular = self.microfacet_reflection(
+            &self.distribution,
+            wi,
+            &wm,
+            fresnel_dielectric(wo.dot(&wm), self.specular_eta),
+        );
+
+        // Diffuse base under the specular layer, shifting to white at grazing angles with sheen
+        let sheen_weight = self.sheen * (1.0 - wi.dot(&wm).clamp(0.0, 1.0)).powi(5);
+        let diffuse_color = (1.0 - sheen_weight) * self.base_color + sheen_weight * white;
+        let through_specular = (1.0 - fresnel_dielectric(wo.z(), self.specular_eta))
+            * (1.0 - fresnel_dielectric(wi.z(), self.specular_eta))
+            / (1.0 - average_fresnel_dielectric(self.specular_eta));
+        let diffuse = through_specular * wi.z() / PI * diffuse_color;
+
+        let dielectric = (1.0 - self.metallic) * (1.0 - self.transmission);
+        let base = self.metallic * metal
+            + (1.0 - self.metallic) * self.transmission * glass * white
+            + dielectric * (specular * white + diffuse);
+
+        clearcoat * white + (1.0 - coat_out) * (1.0 - coat_in) * base
+    }
+
+    fn pdf(&self, wi: &Vec3) -> f64 {
+        let wo = &self.wo;
+
+        if wi.z() < 0.0 {
+            return self.weights[Lobes::GLASS] * self.glass_transmission_pdf(wi);
+        }
+
+        let wm = *wo + *wi;
+        if wm.near_zero() {
+            return 0.0;
+        }
+        let wm = wm.unit_vector();
+
+        let reflection_pdf =
+            |distribution: &Ggx| distribution.visible_d(wo, &wm) / (4.0 * wo.dot(&wm).abs());
+
+        let clearcoat = reflection_pdf(&self.clearcoat_distribution);
+        let reflection = reflection_pdf(&self.distribution);
+        let glass_reflectance = fresnel_dielectric(wo.dot(&wm), self.glass_eta);
+
+        self.weights[Lobes::CLEARCOAT] * clearcoat
+            + (self.weights[Lobes::METAL] + self.weights[Lobes::SPECULAR]) * reflection
+            + self.weights[Lobes::GLASS] * glass_reflectance * reflection
+            + self.weights[Lobes::DIFFUSE] * wi.z() / PI
+    }
+
+    // Single-scattering GGX reflection with Fresnel factor `fresnel`, times the cosine of wi
+    fn microfacet_reflection(&self, distribution: &Ggx, wi: &Vec3, wm: &Vec3, fresnel: f64) -> f64 {
+        let wo = &self.wo;
+        distribution.d(wm) * distribution.g(wo, wi) * fresnel / (4.0 * wo.z())
+    }
+
+    // Half vector of a refraction from wo into wi, facing wo's side
+    fn transmission_half_vector(&self, wi: &Vec3) -> Option<Vec3> {
+        let wm = self.wo + self.glass_eta * *wi;
+        if wm.near_zero() {
+            return None;
+        }
+        let wm = wm.unit_vector();
+        let wm = if wm.z() < 0.0 { -wm } else { wm };
+
+        // Both directions must be on the expected sides of the microfacet
+        if self.wo.dot(&wm) <= 0.0 || wi.dot(&wm) >= 0.0 {
+            return None;
+        }
+
+        Some(wm)
+    }
+
+    // Rough dielectric transmission (Walter et al. 2007) times the cosine of wi, without the
+    // radiance scaling by the squared ratio of the indices, as for `Dielectric`
+    fn glass_transmission(&self, wi: &Vec3) -> f64 {
+        let wo = &self.wo;
+        let wm = match self.transmission_half_vector(wi) {
+            Some(wm) => wm,
+            None => return 0.0,
+        };
+
+        let eta = self.glass_eta;
+        let denominator = wo.dot(&wm) + eta * wi.dot(&wm);
+        let transmittance = 1.0 - fresnel_dielectric(wo.dot(&wm), eta);
+
+        self.distribution.d(&wm)
+            * self.distribution.g(wo, wi)
+            * transmittance
+            * eta
+            * eta
+            * (wi.dot(&wm) * wo.dot(&wm)).abs()
+            / (wo.z() * denominator * denominator)
+    }
+
+    fn glass_transmission_pdf(&self, wi: &Vec3) -> f64 {
+        let wo = &self.wo;
+        let wm = match self.transmission_half_vector(wi) {
+            Some(wm) => wm,
+            None => return 0.0,
+        };
+
+        let eta = self.glass_eta;
+        let denominator = wo.dot(&wm) + eta * wi.dot(&wm);
+        let transmittance = 1.0 - fresnel_dielectric(wo.dot(&wm), eta);
+
+        self.distribution.visible_d(wo, &wm) * transmittance * eta * eta * wi.dot(&wm).abs()
+            / (denominator * denominator)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::testing::directional_albedo;
+
+    // With a white base color every combination of lobes may lose light to single scattering
+    // but must never reflect and transmit more than arrives
+    #[test]
+    fn white_furnace_never_gains_energy() {
+        let white = Color::with_values(1.0, 1.0, 1.0);
+        let constant = |value: f64| -> Arc<dyn Texture> { Arc::new(SolidColor::gray(value)) };
+        let mut sampler = Sampler::new(5);
+
+        for metallic in [0.0, 1.0] {
+            for roughness in [0.05, 0.5, 1.0] {
+                for transmission in [0.0, 1.0] {
+                    for clearcoat in [0.0, 1.0] {
+                        for sheen in [0.0, 1.0] {
+                            let mut mat = Principled::new(white);
+                            mat.metallic = constant(metallic);
+                            mat.roughness = constant(roughness);
+                            mat.transmission = constant(transmission);
+                            mat.clearcoat = constant(clearcoat);
+                            mat.sheen = constant(sheen);
+
+                            for cos_theta in [1.0, 0.7, 0.3, 0.1] {
+                                let albedo = directional_albedo(&mat, cos_theta, &mut sampler);
+                                assert!(
+                                    albedo.x() <= 1.01 && albedo.y() <= 1.01 && albedo.z() <= 1.01,
+                                    "metallic {}, roughness {}, transmission {}, clearcoat {}, \
+                                     sheen {} at cos {} reflects {}",
+                                    metallic,
+                                    roughness,
+                                    transmission,
+                                    clearcoat,
+                                    sheen,
+                                    cos_theta,
+                                    albedo
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,293 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable},
+    hittable_list::HittableList,
+    interval::Interval,
+    material::Material,
+    ray::Ray,
+    sampler::Sampler,
+    vec3::{Point3, Vec3},
+};
+
+// Parallelogram with a corner at `q` and sides `u` and `v`. The front face is the one the normal
+// u x v points out of.
+pub struct Quad {
+    q: Point3,
+    u: Vec3,
+    v: Vec3,
+    // Maps a point in the plane to its (alpha, beta) coordinates along u and v
+    w: Vec3,
+    normal: Vec3,
+    d: f64,
+    area: f64,
+    mat: Arc<dyn Material>,
+    bbox: Aabb,
+}
+
+impl Quad {
+    pub fn new(q: Point3, u: Vec3, v: Vec3, mat: Arc<dyn Material>) -> Quad {
+        let n = u.cross(&v);
+        let normal = n.unit_vector();
+        let bbox_diagonal1 = Aabb::from_points(q, q + u + v);
+        let bbox_diagonal2 = Aabb::from_points(q + u, q + v);
+
+        Quad {
+            q,
+            u,
+            v,
+            w: n / n.length_squared(),
+            normal,
+            d: normal.dot(&q),
+            area: n.length(),
+            mat,
+            bbox: Aabb::surrounding(&bbox_diagonal1, &bbox_diagonal2),
+        }
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let denom = self.normal.dot(&r.direction());
+
+        // No hit if the ray is parallel to the plane
+        if denom.abs() < 1e-8 {
+            return false;
+        }
+
+        let t = (self.d - self.normal.dot(&r.origin())) / denom;
+        if !ray_t.surrounds(t) {
+            return false;
+        }
+
+        let intersection = r.at(t);
+        let planar_hitpt_vector = intersection - self.q;
+        let alpha = self.w.dot(&planar_hitpt_vector.cross(&self.v));
+        let beta = self.w.dot(&self.u.cross(&planar_hitpt_vector));
+
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return false;
+        }
+
+        rec.t = t;
+        rec.p = intersection;
+        rec.u = alpha;
+        rec.v = beta;
+        rec.dpdu = self.u;
+        rec.dpdv = self.v;
+        rec.set_face_normal(r, &self.normal);
+        rec.mat = self.mat.clone();
+
+        return true;
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    // Points are sampled uniformly over the area, converted to a density over solid angle
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let mut rec = HitRecord::new();
+        let ray = Ray::with_values(*origin, *direction);
+        if !self.hit(&ray, Interval::with_values(0.001, f64::INFINITY), &mut rec) {
+            return 0.0;
+        }
+
+        let distance_squared = rec.t * rec.t * direction.length_squared();
+        let cosine = (direction.dot(&rec.normal) / direction.length()).abs();
+
+        distance_squared / (cosine * self.area)
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut Sampler) -> Vec3 {
+        let p = self.q + (sampler.random_f64() * self.u) + (sampler.random_f64() * self.v);
+        p - *origin
+    }
+}
+
+// Axis-aligned box with opposite corners `a` and `b`, made of six quads facing outwards
+pub fn make_box(a: Point3, b: Point3, mat: Arc<dyn Material>) -> HittableList {
+    let mut sides = HittableList::new();
+
+    let min = Point3::with_values(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z()));
+    let max = Point3::with_values(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z()));
+
+    let dx = Vec3::with_values(max.x() - min.x(), 0.0, 0.0);
+    let dy = Vec3::with_values(0.0, max.y() - min.y(), 0.0);
+    let dz = Vec3::with_values(0.0, 0.0, max.z() - min.z());
+
+    // front, right, back, left, top and bottom
+    sides.add(Box::new(Quad::new(
+        Point3::with_values(min.x(), min.y(), max.z()),
+        dx,
+        dy,
+        mat.clone(),
+    )));
+    sides.add(Box::new(Quad::new(
+        Point3::with_values(max.x(), min.y(), max.z()),
+        -dz,
+        dy,
+        mat.clone(),
+    )));
+    sides.add(Box::new(Quad::new(
+        Point3::with_values(max.x(), min.y(), min.z()),
+        -dx,
+        dy,
+        mat.clone(),
+    )));
+    sides.add(Box::new(Quad::new(
+        Point3::with_values(min.x(), min.y(), min.z()),
+        dz,
+        dy,
+        mat.clone(),
+    )));
+    sides.add(Box::new(Quad::new(
+        Point3::with_values(min.x(), max.y(), max.z()),
+        dx,
+        -dz,
+        mat.clone(),
+    )));
+    sides.add(Box::new(Quad::new(
+        Point3::with_values(min.x(), min.y(), min.z()),
+        dx,
+        dz,
+        mat,
+    )));
+
+    sides
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        color::Color,
+        hittable::testing::{assert_near, assert_pdf_matches_solid_angle, hit},
+        material::Lambertian,
+    };
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Color::with_values(0.5, 0.5, 0.5)))
+    }
+
+    // 2 by 1 rectangle in the z = 0 plane from the origin, facing +z
+    fn rectangle() -> Quad {
+        Quad::new(
+            Point3::new(),
+            Vec3::with_values(2.0, 0.0, 0.0),
+            Vec3::with_values(0.0, 1.0, 0.0),
+            material(),
+        )
+    }
+
+    fn down_onto(quad: &Quad, x: f64, y: f64) -> Option<HitRecord> {
+        hit(
+            quad,
+            Point3::with_values(x, y, 1.0),
+            Vec3::with_values(0.0, 0.0, -1.0),
+        )
+    }
+
+    #[test]
+    fn hits_stop_at_the_edges() {
+        let quad = rectangle();
+        let e = 1e-6;
+
+        for (x, y) in [(e, 0.5), (2.0 - e, 0.5), (1.0, e), (1.0, 1.0 - e)] {
+            assert!(down_onto(&quad, x, y).is_some(), "({}, {})", x, y);
+        }
+        for (x, y) in [(-e, 0.5), (2.0 + e, 0.5), (1.0, -e), (1.0, 1.0 + e)] {
+            assert!(down_onto(&quad, x, y).is_none(), "({}, {})", x, y);
+        }
+
+        // Parallel to the plane
+        let along = hit(
+            &quad,
+            Point3::with_values(-1.0, 0.5, 0.0),
+            Vec3::with_values(1.0, 0.0, 0.0),
+        );
+        assert!(along.is_none());
+    }
+
+    #[test]
+    fn texture_coordinates_run_along_the_sides() {
+        let quad = rectangle();
+
+        for (x, y, u, v) in [
+            (0.0, 0.0, 0.0, 0.0),
+            (2.0, 0.0, 1.0, 0.0),
+            (0.0, 1.0, 0.0, 1.0),
+            (2.0, 1.0, 1.0, 1.0),
+            (0.5, 0.25, 0.25, 0.25),
+        ] {
+            let rec = down_onto(&quad, x, y).unwrap();
+            assert!(
+                (rec.u - u).abs() < 1e-12 && (rec.v - v).abs() < 1e-12,
+                "({}, {})",
+                x,
+                y
+            );
+        }
+    }
+
+    #[test]
+    fn front_face_is_the_side_the_normal_points_out_of() {
+        let quad = rectangle();
+        let up = Vec3::with_values(0.0, 0.0, 1.0);
+
+        let rec = down_onto(&quad, 1.0, 0.5).unwrap();
+        assert!(rec.front_face);
+        assert_near(rec.normal, up);
+
+        let rec = hit(&quad, Point3::with_values(1.0, 0.5, -1.0), up).unwrap();
+        assert!(!rec.front_face);
+        assert_near(rec.normal, -up);
+    }
+
+    #[test]
+    fn box_faces_point_outwards() {
+        let cube = make_box(
+            Point3::with_values(1.0, 1.0, 1.0),
+            Point3::with_values(-1.0, 3.0, -2.0),
+            material(),
+        );
+        let center = Point3::with_values(0.0, 2.0, -0.5);
+
+        for axis in 0..3 {
+            for sign in [-1.0, 1.0] {
+                let mut outward = Vec3::new();
+                outward[axis] = sign;
+
+                // Entering from outside, and leaving from inside, through the same face
+                let rec = hit(&cube, center + 5.0 * outward, -outward).unwrap();
+                assert!(rec.front_face);
+                assert_near(rec.normal, outward);
+
+                let rec = hit(&cube, center, outward).unwrap();
+                assert!(!rec.front_face);
+                assert_near(rec.normal, -outward);
+            }
+        }
+    }
+
+    #[test]
+    fn pdf_matches_the_sampled_directions() {
+        let quad = rectangle();
+        assert_pdf_matches_solid_angle(&quad, Point3::with_values(0.5, 0.2, 1.5));
+
+        // Towards its center from far away, the quad covers its area times the cosine over the
+        // distance squared
+        let origin = Point3::with_values(1.0, 0.5 + 30.0, 40.0);
+        let direction = Point3::with_values(1.0, 0.5, 0.0) - origin;
+        let solid_angle = 2.0 * 0.8 / (50.0 * 50.0);
+        let pdf = quad.pdf_value(&origin, &direction);
+        assert!((pdf * solid_angle - 1.0).abs() < 1e-9, "{}", pdf);
+
+        // Directions missing it have no density
+        assert_eq!(
+            quad.pdf_value(&origin, &Vec3::with_values(0.0, 0.0, 1.0)),
+            0.0
+        );
+    }
+}
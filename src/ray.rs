@@ -1,8 +1,14 @@
-use crate::vec3::{Point3, Vec3};
+use crate::{
+    spectrum::Wavelengths,
+    vec3::{Point3, Vec3},
+};
 
 pub struct Ray {
     origin: Point3,
     dir: Vec3,
+    tm: f64,
+    // Wavelengths carried by rays of spectral renders
+    wavelengths: Option<Wavelengths>,
 }
 
 impl Ray {
@@ -10,13 +16,21 @@ impl Ray {
         Ray {
             origin: Point3::new(),
             dir: Vec3::new(),
+            tm: 0.0,
+            wavelengths: None,
         }
     }
 
     pub fn with_values(origin: Point3, direction: Vec3) -> Ray {
+        Ray::with_time(origin, direction, 0.0)
+    }
+
+    pub fn with_time(origin: Point3, direction: Vec3, time: f64) -> Ray {
         Ray {
             origin,
             dir: direction,
+            tm: time,
+            wavelengths: None,
         }
     }
 
@@ -28,6 +42,18 @@ impl Ray {
         self.dir
     }
 
+    pub fn time(&self) -> f64 {
+        self.tm
+    }
+
+    pub fn wavelengths(&self) -> Option<Wavelengths> {
+        self.wavelengths
+    }
+
+    pub fn set_wavelengths(&mut self, wavelengths: Option<Wavelengths>) {
+        self.wavelengths = wavelengths;
+    }
+
     pub fn at(&self, t: f64) -> Point3 {
         return self.origin + t * self.dir;
     }
@@ -0,0 +1,363 @@
+use crate::{
+    color::Color,
+    hittable::HitRecord,
+    material::{transmittance, Material},
+    microfacet::{fresnel_dielectric, reflect, refract, shading_frame, MicrofacetDistribution},
+    ray::Ray,
+    sampler::Sampler,
+    spectrum::in_spectrum,
+    thin_film::ThinFilm,
+    vec3::Vec3,
+};
+
+// Frosted glass: microfacet reflection and transmission through a rough dielectric interface
+// (Walter et al. 2007), with Beer-Lambert absorption inside the material. As for `Dielectric`,
+// transmitted radiance isn't scaled by the squared ratio of the indices.
+pub struct RoughDielectric {
+    // Refractive index relative to the enclosing media
+    refraction_index: f64,
+    distribution: Box<dyn MicrofacetDistribution>,
+    // Absorption coefficients per unit distance inside the material
+    absorption: Color,
+    // Coating that colors the reflection with interference
+    pub film: Option<ThinFilm>,
+}
+
+impl RoughDielectric {
+    pub fn new(refraction_index: f64, distribution: Box<dyn MicrofacetDistribution>) -> Self {
+        RoughDielectric::with_absorption(refraction_index, distribution, Color::new())
+    }
+
+    pub fn with_absorption(
+        refraction_index: f64,
+        distribution: Box<dyn MicrofacetDistribution>,
+        absorption: Color,
+    ) -> Self {
+        RoughDielectric {
+            refraction_index,
+            distribution,
+            absorption,
+            film: None,
+        }
+    }
+
+    // Index of refraction of the side opposite the incoming ray over the one it arrives from
+    fn eta(&self, rec: &HitRecord) -> f64 {
+        if rec.front_face {
+            self.refraction_index
+        } else {
+            1.0 / self.refraction_index
+        }
+    }
+
+    // Reflectance of a microfacet seen at an angle with cosine `cos_theta`, per color channel, or
+    // at the ray's wavelengths when the film makes the material spectral
+    fn reflectance(&self, ray_in: &Ray, cos_theta: f64, eta: f64, rec: &HitRecord) -> Color {
+        match &self.film {
+            Some(film) => {
+                let (incident_ior, substrate_ior) = if rec.front_face {
+                    (1.0, self.refraction_index)
+                } else {
+                    (self.refraction_index, 1.0)
+                };
+                film.dielectric_reflectance(ray_in, cos_theta, incident_ior, substrate_ior, rec)
+            }
+            None => {
+                let reflectance = fresnel_dielectric(cos_theta, eta);
+                Color::with_values(reflectance, reflectance, reflectance)
+            }
+        }
+    }
+
+    // Chance of reflecting off a microfacet rather than refracting through it
+    fn reflect_chance(reflectance: &Color) -> f64 {
+        (reflectance.x() + reflectance.y() + reflectance.z()) / 3.0
+    }
+
+    // Beer-Lambert absorption of the light that crossed the inside of the material to reach
+    // the hit, in the same terms as the reflectance
+    fn absorption_transmittance(&self, ray_in: &Ray, rec: &HitRecord) -> Color {
+        let transmitted = transmittance(&self.absorption, ray_in, rec);
+        if self.is_spectral(ray_in) {
+            return in_spectrum(ray_in, transmitted);
+        }
+
+        transmitted
+    }
+
+    // Half vector between wo and wi, facing wo's side, with both directions on the expected
+    // sides of the microfacet for a reflection or a refraction
+    fn half_vector(wo: &Vec3, wi: &Vec3, eta: f64) -> Option<Vec3> {
+        let reflected = wi.z() > 0.0;
+        let wm = if reflected {
+            *wo + *wi
+        } else {
+            *wo + eta * *wi
+        };
+        if wi.z() == 0.0 || wm.near_zero() {
+            return None;
+        }
+        let wm = wm.unit_vector();
+        let wm = if wm.z() < 0.0 { -wm } else { wm };
+
+        if wo.dot(&wm) <= 0.0 || (wi.dot(&wm) > 0.0) != reflected {
+            return None;
+        }
+
+        Some(wm)
+    }
+
+    // BSDF times the cosine of wi and the density of sampling wi, in the shading frame, for the
+    // half vector `wm` with the given reflectance
+    fn eval_and_pdf_local(
+        &self,
+        wo: &Vec3,
+        wi: &Vec3,
+        wm: &Vec3,
+        eta: f64,
+        reflectance: &Color,
+    ) -> (Color, f64) {
+        let d = self.distribution.d(wm);
+        let g = self.distribution.g(wo, wi);
+        let visible = self.distribution.visible_d(wo, wm);
+        let reflect_chance = RoughDielectric::reflect_chance(reflectance);
+
+        if wi.z() > 0.0 {
+            return (
+                d * g / (4.0 * wo.z()) * *reflectance,
+                visible * reflect_chance / (4.0 * wo.dot(wm)),
+            );
+        }
+
+        let denominator = wo.dot(wm) + eta * wi.dot(wm);
+        let jacobian = eta * eta * wi.dot(wm).abs() / (denominator * denominator);
+        let white = Color::with_values(1.0, 1.0, 1.0);
+        (
+            d * g * jacobian * wo.dot(wm).abs() / wo.z() * (white - *reflectance),
+            visible * (1.0 - reflect_chance) * jacobian,
+        )
+    }
+
+    // `eval_and_pdf_local` for any pair of directions, black with zero density when no
+    // microfacet connects them
+    fn eval_and_pdf_between(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        wo: &Vec3,
+        wi: &Vec3,
+    ) -> (Color, f64) {
+        let eta = self.eta(rec);
+        let wm = match RoughDielectric::half_vector(wo, wi, eta) {
+            Some(wm) => wm,
+            None => return (Color::new(), 0.0),
+        };
+        let reflectance = self.reflectance(ray_in, wo.dot(&wm), eta, rec);
+
+        self.eval_and_pdf_local(wo, wi, &wm, eta, &reflectance)
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+        sampler: &mut Sampler,
+    ) -> bool {
+        let frame = shading_frame(rec);
+        let wo = frame.inverse_transform(&(-ray_in.direction().unit_vector()));
+        if wo.z() <= 0.0 {
+            return false;
+        }
+        let eta = self.eta(rec);
+
+        // Reflect or refract about a visible microfacet normal in proportion to its Fresnel
+        // reflectance. Directions that end up on the wrong side of the surface are lost to
+        // single scattering.
+        let wm = self
+            .distribution
+            .sample_visible(&wo, sampler.random_f64(), sampler.random_f64());
+        let reflectance = self.reflectance(ray_in, wo.dot(&wm), eta, rec);
+        let reflect_chance = RoughDielectric::reflect_chance(&reflectance);
+        let (wi, transmitted) = match refract(&wo, &wm, eta) {
+            Some(wt) if sampler.random_f64() >= reflect_chance => (wt, true),
+            _ => (reflect(&wo, &wm), false),
+        };
+        if wi.near_zero() || (wi.z() < 0.0) != transmitted {
+            return false;
+        }
+        let wi = wi.unit_vector();
+
+        // The sampled microfacet is the half vector of wo and wi, so its reflectance carries over
+        let wm = match RoughDielectric::half_vector(&wo, &wi, eta) {
+            Some(wm) => wm,
+            None => return false,
+        };
+        let (f, pdf) = self.eval_and_pdf_local(&wo, &wi, &wm, eta, &reflectance);
+        if pdf <= 0.0 {
+            return false;
+        }
+
+        *attenuation = f / pdf * self.absorption_transmittance(ray_in, rec);
+        *scattered = Ray::with_time(rec.p, frame.transform(&wi), ray_in.time());
+
+        return true;
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        let frame = shading_frame(rec);
+        let wo = frame.inverse_transform(&(-ray_in.direction().unit_vector()));
+        let wi = frame.inverse_transform(&direction.unit_vector());
+        if wo.z() <= 0.0 {
+            return Color::new();
+        }
+
+        let (f, _) = self.eval_and_pdf_between(ray_in, rec, &wo, &wi);
+        f * self.absorption_transmittance(ray_in, rec)
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        let frame = shading_frame(rec);
+        let wo = frame.inverse_transform(&(-ray_in.direction().unit_vector()));
+        let wi = frame.inverse_transform(&direction.unit_vector());
+        if wo.z() <= 0.0 {
+            return 0.0;
+        }
+
+        let (_, pdf) = self.eval_and_pdf_between(ray_in, rec, &wo, &wi);
+        pdf
+    }
+
+    // The film's reflectance is computed at the ray's wavelengths
+    fn is_spectral(&self, ray_in: &Ray) -> bool {
+        self.film.is_some() && ray_in.wavelengths().is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        hittable::Hittable, interval::Interval, material::testing::directional_albedo,
+        microfacet::Ggx, quad::make_box, vec3::Point3,
+    };
+    use std::sync::Arc;
+
+    // Green glass absorbs red and blue
+    fn green_glass() -> RoughDielectric {
+        RoughDielectric::with_absorption(
+            1.5,
+            Box::new(Ggx::isotropic(0.1)),
+            Color::with_values(2.0, 0.0, 2.0),
+        )
+    }
+
+    // Transmittance for light that crossed a slab of glass of the given thickness from its
+    // bottom to its top face
+    fn slab_transmittance(thickness: f64) -> Color {
+        let glass = Arc::new(green_glass());
+        let slab = make_box(
+            Point3::with_values(-1.0, 0.0, -1.0),
+            Point3::with_values(1.0, thickness, 1.0),
+            glass.clone(),
+        );
+        // Slanted, and with a direction that isn't of unit length
+        let ray = Ray::with_values(
+            Point3::with_values(0.0, 1e-9, 0.0),
+            Vec3::with_values(0.0, 2.0, 0.5),
+        );
+        let mut rec = HitRecord::new();
+        assert!(slab.hit(&ray, Interval::with_values(0.0, f64::INFINITY), &mut rec));
+        assert!(!rec.front_face);
+
+        glass.absorption_transmittance(&ray, &rec)
+    }
+
+    #[test]
+    fn thick_glass_transmits_less_than_thin_glass() {
+        let thin = slab_transmittance(0.1);
+        let thick = slab_transmittance(1.0);
+
+        assert!(thick.x() < thin.x() && thick.z() < thin.z());
+        assert_eq!(thick.y(), 1.0);
+        assert_eq!(thin.y(), 1.0);
+
+        // Beer-Lambert falls off exponentially with the distance traveled inside
+        let path_per_height = Vec3::with_values(0.0, 1.0, 0.25).length();
+        for (slab, thickness) in [(thin, 0.1), (thick, 1.0)] {
+            let expected = (-2.0 * thickness * path_per_height).exp();
+            assert!(
+                (slab.x() - expected).abs() < 1e-6,
+                "{} != {}",
+                slab.x(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn light_arriving_from_outside_is_not_absorbed() {
+        let glass = green_glass();
+        let ray = Ray::with_values(
+            Point3::with_values(0.0, 0.0, 5.0),
+            Vec3::with_values(0.0, 0.0, -1.0),
+        );
+        let mut rec = HitRecord::new();
+        rec.t = 5.0;
+        rec.set_face_normal(&ray, &Vec3::with_values(0.0, 0.0, 1.0));
+
+        let transmitted = glass.absorption_transmittance(&ray, &rec);
+        assert_eq!(
+            [transmitted.x(), transmitted.y(), transmitted.z()],
+            [1.0; 3]
+        );
+    }
+
+    // Surface at the origin facing +z, hit from above by light leaving along `wo`
+    fn surface(wo: Vec3) -> (Ray, HitRecord) {
+        let ray = Ray::with_values(wo, -wo);
+        let mut rec = HitRecord::new();
+        rec.set_face_normal(&ray, &Vec3::with_values(0.0, 0.0, 1.0));
+        (ray, rec)
+    }
+
+    #[test]
+    fn reflection_is_reciprocal() {
+        let glass = RoughDielectric::new(1.5, Box::new(Ggx::new(0.3, 0.1)));
+        let a = Vec3::with_values(0.3, 0.2, 0.9).unit_vector();
+        let b = Vec3::with_values(-0.6, 0.1, 0.4).unit_vector();
+
+        // `eval` includes the cosine of the outgoing direction
+        let (ray_a, rec_a) = surface(a);
+        let (ray_b, rec_b) = surface(b);
+        let ab = glass.eval(&ray_a, &rec_a, &b) / b.z();
+        let ba = glass.eval(&ray_b, &rec_b, &a) / a.z();
+
+        assert!(ab.x() > 0.0);
+        assert!((ab.x() - ba.x()).abs() < 1e-9 * ab.x(), "{} != {}", ab, ba);
+    }
+
+    #[test]
+    fn clear_glass_white_furnace() {
+        let mut sampler = Sampler::new(3);
+
+        for alpha in [0.01, 0.1, 0.5, 1.0] {
+            let glass = RoughDielectric::new(1.5, Box::new(Ggx::isotropic(alpha)));
+            for cos_theta in [1.0, 0.7, 0.3, 0.1] {
+                let albedo = directional_albedo(&glass, cos_theta, &mut sampler);
+                // Rougher surfaces lose more light to single scattering, but none is absorbed
+                let lower = if alpha <= 0.01 { 0.99 } else { 0.6 };
+                assert!(
+                    albedo.x() <= 1.01 && albedo.x() >= lower,
+                    "alpha {} at cos {} passes on {}",
+                    alpha,
+                    cos_theta,
+                    albedo
+                );
+            }
+        }
+    }
+}
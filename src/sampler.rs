@@ -0,0 +1,30 @@
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+// Source of the random numbers used while rendering. Every pixel gets its own sampler derived
+// from the render seed, so a seed reproduces the same image no matter how pixels are spread over
+// threads.
+pub struct Sampler {
+    rng: SmallRng,
+}
+
+impl Sampler {
+    pub fn new(seed: u64) -> Sampler {
+        Sampler {
+            rng: SmallRng::seed_from_u64(seed),
+        }
+    }
+
+    // Independent stream for pixel (i, j) of a render seeded with `seed`
+    pub fn for_pixel(seed: u64, i: i32, j: i32) -> Sampler {
+        let pixel = ((j as u32 as u64) << 32) | i as u32 as u64;
+        Sampler::new(seed ^ pixel.wrapping_mul(0x9e37_79b9_7f4a_7c15))
+    }
+
+    pub fn random_f64(&mut self) -> f64 {
+        self.rng.gen_range(0.0..1.0)
+    }
+
+    pub fn random_between(&mut self, min: f64, max: f64) -> f64 {
+        self.rng.gen_range(min..max)
+    }
+}
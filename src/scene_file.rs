@@ -0,0 +1,1581 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, fs, io,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::Deserialize;
+use toml::{
+    de::{DeTable, DeValue, ValueDeserializer},
+    Spanned,
+};
+
+use crate::{
+    camera::{Background, Camera},
+    color::Color,
+    conductor::Conductor,
+    cone::Cone,
+    constant_medium::ConstantMedium,
+    csg::{Csg, CsgOperation},
+    cylinder::Cylinder,
+    disk::Disk,
+    ellipsoid::Ellipsoid,
+    heightfield::Heightfield,
+    hittable::Hittable,
+    hittable_list::HittableList,
+    image::Image,
+    layered::Layered,
+    mat4::Mat4,
+    material::{
+        Dielectric, DiffuseLight, Dispersion, DispersiveDielectric, Isotropic, Lambertian,
+        Material, Metal,
+    },
+    microfacet::{Beckmann, Ggx, MicrofacetDistribution},
+    normal_map::NormalMapped,
+    obj::{load_obj, ObjError},
+    principled::Principled,
+    quad::{make_box, Quad},
+    rough_dielectric::RoughDielectric,
+    scenes::Scene,
+    sdf::{
+        Sdf, SdfIntersection, SdfMandelbulb, SdfObject, SdfRepetition, SdfRoundedBox,
+        SdfSmoothUnion, SdfSphere, SdfSubtraction, SdfTorus, SdfTwist, SdfUnion,
+    },
+    sphere::Sphere,
+    subsurface::Subsurface,
+    texture::{CheckerTexture, ImageTexture, SolidColor, Texture},
+    thin_film::ThinFilm,
+    torus::Torus,
+    transform::Transform,
+    triangle::Triangle,
+    vec3::Vec3,
+};
+
+// Scene description files are TOML documents with a `[camera]` table, named `[textures.<name>]`
+// and `[materials.<name>]` tables and an `[[objects]]` array. For example:
+//
+//     [camera]
+//     lookfrom = [-2.0, 2.0, 1.0]
+//     lookat = [0.0, 0.0, -1.0]
+//     v_fov = 20.0
+//
+//     [materials.ground]
+//     type = "lambertian"
+//     albedo = [0.8, 0.8, 0.0]
+//
+//     [[objects]]
+//     type = "sphere"
+//     center = [0.0, -100.5, -1.0]
+//     radius = 100.0
+//     material = "ground"
+//
+// Colors can be given inline as `[r, g, b]` or as the name of a texture. Relative file paths are
+// resolved against the directory of the scene file. Objects are placed with a `transform` object
+// wrapping another one:
+//
+//     [[objects]]
+//     type = "transform"
+//     scale = [2.0, 1.0, 1.0]
+//     rotate = { axis = [0.0, 1.0, 0.0], angle = 45.0 }
+//     translate = [0.0, 1.0, 0.0]
+//     object = { type = "mesh", path = "teapot.obj" }
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        message: String,
+    },
+    Invalid {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+    Obj(ObjError),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            SceneError::Parse { path, message } => write!(f, "{}: {}", path.display(), message),
+            SceneError::Invalid {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+            SceneError::Obj(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDef {
+    #[serde(default)]
+    camera: CameraDef,
+    #[serde(default)]
+    textures: HashMap<String, Spanned<TextureDef>>,
+    #[serde(default)]
+    materials: HashMap<String, Spanned<MaterialDef>>,
+    #[serde(default)]
+    objects: Vec<Spanned<ObjectDef>>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct CameraDef {
+    aspect_ratio: Option<f64>,
+    image_width: Option<i32>,
+    samples_per_pixel: Option<i32>,
+    max_depth: Option<i32>,
+    v_fov: Option<f64>,
+    lookfrom: Option<[f64; 3]>,
+    lookat: Option<[f64; 3]>,
+    vup: Option<[f64; 3]>,
+    defocus_angle: Option<f64>,
+    focus_dist: Option<f64>,
+    // Solid background color, the sky gradient when missing
+    background: Option<[f64; 3]>,
+    shutter_open: Option<f64>,
+    shutter_close: Option<f64>,
+    // Trace wavelengths instead of RGB colors, for dispersion
+    spectral: Option<bool>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ColorDef {
+    Rgb([f64; 3]),
+    Texture(String),
+}
+
+// Scalar material parameters can be given as a number or as the name of a texture, of which the
+// mean of the color channels is used
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ScalarDef {
+    Value(f64),
+    Texture(String),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureDef {
+    Solid {
+        color: [f64; 3],
+    },
+    Checker {
+        scale: f64,
+        even: ColorDef,
+        odd: ColorDef,
+    },
+    // Gamma encoded colors, or with `raw` the stored values as they are, for data such as normal
+    // and height maps
+    Image {
+        path: String,
+        #[serde(default)]
+        raw: bool,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDef {
+    Lambertian {
+        albedo: ColorDef,
+    },
+    Metal {
+        albedo: ColorDef,
+        #[serde(default)]
+        fuzz: f64,
+    },
+    // Glass, frosted when `roughness` is above zero, and tinted by `absorption` coefficients per
+    // unit distance traveled inside
+    Dielectric {
+        refraction_index: f64,
+        #[serde(default)]
+        distribution: DistributionDef,
+        roughness: Option<RoughnessDef>,
+        absorption: Option<[f64; 3]>,
+        film: Option<FilmDef>,
+    },
+    // Glass whose refractive index varies with the wavelength, given by exactly one of a
+    // `preset`, Cauchy coefficients `[a, b]` or Sellmeier coefficients
+    DispersiveDielectric {
+        preset: Option<String>,
+        cauchy: Option<[f64; 2]>,
+        sellmeier: Option<SellmeierDef>,
+    },
+    DiffuseLight {
+        emit: ColorDef,
+    },
+    // Phase function for constant_medium objects
+    Isotropic {
+        albedo: ColorDef,
+    },
+    // Disney-style material, parameters left out keep their defaults
+    Principled {
+        base_color: ColorDef,
+        metallic: Option<ScalarDef>,
+        roughness: Option<ScalarDef>,
+        specular: Option<ScalarDef>,
+        clearcoat: Option<ScalarDef>,
+        clearcoat_roughness: Option<ScalarDef>,
+        sheen: Option<ScalarDef>,
+        transmission: Option<ScalarDef>,
+        ior: Option<f64>,
+    },
+    // Rough metal, either a named `preset` or a complex index of refraction `eta` + i `k`
+    Conductor {
+        preset: Option<String>,
+        eta: Option<[f64; 3]>,
+        k: Option<[f64; 3]>,
+        #[serde(default)]
+        distribution: DistributionDef,
+        roughness: Option<RoughnessDef>,
+        film: Option<FilmDef>,
+    },
+    // Dielectric coat of refractive index `ior` over the material named `base`, colored by `tint`
+    // at unit `thickness`
+    Layered {
+        base: String,
+        ior: Option<f64>,
+        #[serde(default)]
+        distribution: DistributionDef,
+        roughness: Option<RoughnessDef>,
+        tint: Option<[f64; 3]>,
+        thickness: Option<f64>,
+    },
+    // Translucent closed object, lit by light walking through it between events spaced by the
+    // `mean_free_path` per color channel
+    Subsurface {
+        albedo: [f64; 3],
+        mean_free_path: [f64; 3],
+        ior: Option<f64>,
+    },
+    // The material named `base` with its shading normal taken from the tangent-space normal map
+    // texture `map`
+    NormalMap {
+        base: String,
+        map: String,
+    },
+    // The material named `base` with its shading normal bent by the slopes of the texture
+    // `height`, whose values are scaled by `scale`
+    BumpMap {
+        base: String,
+        height: String,
+        scale: f64,
+    },
+}
+
+// Thin-film coating with a refractive index relative to the outside medium and a thickness in
+// micrometers
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FilmDef {
+    ior: f64,
+    thickness: ScalarDef,
+}
+
+// Coefficients for wavelengths in micrometers
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SellmeierDef {
+    b: [f64; 3],
+    c: [f64; 3],
+}
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+enum DistributionDef {
+    #[default]
+    Ggx,
+    Beckmann,
+}
+
+// Perceptual roughness, squared to give the distribution's alpha. Two values make it
+// anisotropic, with the first along the surface's u direction, or the world x axis projected
+// onto surfaces without texture coordinate derivatives.
+#[derive(Deserialize, Clone, Copy)]
+#[serde(untagged)]
+enum RoughnessDef {
+    Isotropic(f64),
+    Anisotropic([f64; 2]),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ObjectDef {
+    // With `center2` the sphere moves from `center` at time 0 to `center2` at time 1
+    Sphere {
+        center: [f64; 3],
+        center2: Option<[f64; 3]>,
+        radius: f64,
+        material: String,
+    },
+    Triangle {
+        p0: [f64; 3],
+        p1: [f64; 3],
+        p2: [f64; 3],
+        material: String,
+    },
+    // Parallelogram with corner `q` and sides `u` and `v`, facing along u x v
+    Quad {
+        q: [f64; 3],
+        u: [f64; 3],
+        v: [f64; 3],
+        material: String,
+    },
+    Disk {
+        center: [f64; 3],
+        normal: [f64; 3],
+        radius: f64,
+        material: String,
+    },
+    // Axis-aligned box with opposite corners `a` and `b`
+    Box {
+        a: [f64; 3],
+        b: [f64; 3],
+        material: String,
+    },
+    // Closed cylinder between the centers of its caps
+    Cylinder {
+        p0: [f64; 3],
+        p1: [f64; 3],
+        radius: f64,
+        material: String,
+    },
+    Cone {
+        base: [f64; 3],
+        apex: [f64; 3],
+        radius: f64,
+        material: String,
+    },
+    Torus {
+        center: [f64; 3],
+        axis: [f64; 3],
+        major_radius: f64,
+        minor_radius: f64,
+        material: String,
+    },
+    // Axis-aligned, with semi-axes `radii`
+    Ellipsoid {
+        center: [f64; 3],
+        radii: [f64; 3],
+        material: String,
+    },
+    // Wavefront OBJ file; `material` applies to faces without an MTL material
+    Mesh {
+        path: String,
+        material: Option<String>,
+    },
+    // Terrain from a grayscale PNG or PGM height map, spanning `size` from `corner`
+    Heightfield {
+        path: String,
+        corner: [f64; 3],
+        size: [f64; 3],
+        material: String,
+    },
+    // Fog filling a convex boundary object, whose material scatters inside the volume
+    ConstantMedium {
+        boundary: Box<ObjectDef>,
+        density: f64,
+    },
+    // Another object, scaled, then rotated, then translated
+    Transform {
+        object: Box<ObjectDef>,
+        scale: Option<[f64; 3]>,
+        rotate: Option<RotateDef>,
+        translate: Option<[f64; 3]>,
+    },
+    // Surface of a signed distance function, rendered by sphere tracing. A `step_scale` below
+    // one is needed for shapes whose distances overshoot, such as twists and fractals.
+    Sdf {
+        shape: SdfDef,
+        step_scale: Option<f64>,
+        material: String,
+    },
+    // Boolean combination of two closed objects
+    Csg {
+        operation: CsgOperationDef,
+        left: Box<ObjectDef>,
+        right: Box<ObjectDef>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum SdfDef {
+    Sphere {
+        center: [f64; 3],
+        radius: f64,
+    },
+    RoundedBox {
+        center: [f64; 3],
+        half_extents: [f64; 3],
+        radius: f64,
+    },
+    // Around the y axis
+    Torus {
+        center: [f64; 3],
+        major_radius: f64,
+        minor_radius: f64,
+    },
+    Mandelbulb {
+        power: f64,
+        iterations: usize,
+    },
+    Union {
+        a: Box<SdfDef>,
+        b: Box<SdfDef>,
+    },
+    Intersection {
+        a: Box<SdfDef>,
+        b: Box<SdfDef>,
+    },
+    SmoothUnion {
+        a: Box<SdfDef>,
+        b: Box<SdfDef>,
+        k: f64,
+    },
+    Subtraction {
+        a: Box<SdfDef>,
+        b: Box<SdfDef>,
+    },
+    Repetition {
+        shape: Box<SdfDef>,
+        period: [f64; 3],
+    },
+    Twist {
+        shape: Box<SdfDef>,
+        rate: f64,
+    },
+}
+
+impl SdfDef {
+    fn build(&self, field: &str) -> Result<Box<dyn Sdf>, String> {
+        let operands = |a: &SdfDef, b: &SdfDef| -> Result<_, String> {
+            Ok((
+                a.build(&format!("{}.a", field))?,
+                b.build(&format!("{}.b", field))?,
+            ))
+        };
+        let inner = |shape: &SdfDef| shape.build(&format!("{}.shape", field));
+
+        let sdf: Box<dyn Sdf> = match self {
+            SdfDef::Sphere { center, radius } => Box::new(SdfSphere::new(vec3(*center), *radius)),
+            SdfDef::RoundedBox {
+                center,
+                half_extents,
+                radius,
+            } => Box::new(SdfRoundedBox::new(
+                vec3(*center),
+                vec3(*half_extents),
+                *radius,
+            )),
+            SdfDef::Torus {
+                center,
+                major_radius,
+                minor_radius,
+            } => Box::new(SdfTorus::new(vec3(*center), *major_radius, *minor_radius)),
+            SdfDef::Mandelbulb { power, iterations } => {
+                if !(*power > 1.0 && power.is_finite()) {
+                    return Err(format!("{}.power: must be above 1, got {}", field, power));
+                }
+                Box::new(SdfMandelbulb::new(*power, *iterations))
+            }
+            SdfDef::Union { a, b } => {
+                let (a, b) = operands(a, b)?;
+                Box::new(SdfUnion::new(a, b))
+            }
+            SdfDef::Intersection { a, b } => {
+                let (a, b) = operands(a, b)?;
+                Box::new(SdfIntersection::new(a, b))
+            }
+            SdfDef::SmoothUnion { a, b, k } => {
+                let (a, b) = operands(a, b)?;
+                Box::new(SdfSmoothUnion::new(a, b, *k))
+            }
+            SdfDef::Subtraction { a, b } => {
+                let (a, b) = operands(a, b)?;
+                Box::new(SdfSubtraction::new(a, b))
+            }
+            SdfDef::Repetition { shape, period } => {
+                Box::new(SdfRepetition::new(inner(shape)?, vec3(*period)))
+            }
+            SdfDef::Twist { shape, rate } => Box::new(SdfTwist::new(inner(shape)?, *rate)),
+        };
+
+        Ok(sdf)
+    }
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum CsgOperationDef {
+    Union,
+    Intersection,
+    Difference,
+}
+
+impl ObjectDef {
+    // Material the object itself is made of; wrappers of several objects have none
+    fn material(&self) -> Option<&String> {
+        match self {
+            ObjectDef::Sphere { material, .. }
+            | ObjectDef::Triangle { material, .. }
+            | ObjectDef::Quad { material, .. }
+            | ObjectDef::Disk { material, .. }
+            | ObjectDef::Box { material, .. }
+            | ObjectDef::Cylinder { material, .. }
+            | ObjectDef::Cone { material, .. }
+            | ObjectDef::Torus { material, .. }
+            | ObjectDef::Ellipsoid { material, .. }
+            | ObjectDef::Heightfield { material, .. }
+            | ObjectDef::Sdf { material, .. } => Some(material),
+            ObjectDef::Mesh { material, .. } => material.as_ref(),
+            ObjectDef::ConstantMedium { boundary, .. } => boundary.material(),
+            ObjectDef::Transform { .. } | ObjectDef::Csg { .. } => None,
+        }
+    }
+}
+
+// Rotation by `angle` degrees about `axis`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RotateDef {
+    axis: [f64; 3],
+    angle: f64,
+}
+
+fn vec3(v: [f64; 3]) -> Vec3 {
+    Vec3::with_values(v[0], v[1], v[2])
+}
+
+// 1-based line of the byte at `offset`
+fn line_of(source: &str, offset: usize) -> usize {
+    source[..offset.min(source.len())].matches('\n').count() + 1
+}
+
+struct Loader<'a> {
+    path: &'a Path,
+    source: &'a str,
+    def: &'a SceneDef,
+    textures: HashMap<String, Arc<dyn Texture>>,
+    materials: HashMap<String, Arc<dyn Material>>,
+    // Textures currently being built, to report reference cycles
+    resolving: HashSet<String>,
+    // Same for materials
+    resolving_materials: HashSet<String>,
+}
+
+impl Loader<'_> {
+    fn invalid(&self, span: Range<usize>, message: String) -> SceneError {
+        SceneError::Invalid {
+            path: self.path.to_path_buf(),
+            line: line_of(self.source, span.start),
+            message,
+        }
+    }
+
+    fn resolve_path(&self, file: &str) -> PathBuf {
+        self.path.parent().unwrap_or(Path::new("")).join(file)
+    }
+
+    // `span` and `field` locate the reference for error messages
+    fn color(
+        &mut self,
+        def: &ColorDef,
+        span: Range<usize>,
+        field: &str,
+    ) -> Result<Arc<dyn Texture>, SceneError> {
+        match def {
+            ColorDef::Rgb(rgb) => Ok(Arc::new(SolidColor::new(vec3(*rgb)))),
+            ColorDef::Texture(name) => self.texture(name, span, field),
+        }
+    }
+
+    fn scalar(
+        &mut self,
+        def: &ScalarDef,
+        span: Range<usize>,
+        field: &str,
+    ) -> Result<Arc<dyn Texture>, SceneError> {
+        match def {
+            ScalarDef::Value(value) => Ok(Arc::new(SolidColor::gray(*value))),
+            ScalarDef::Texture(name) => self.texture(name, span, field),
+        }
+    }
+
+    fn texture(
+        &mut self,
+        name: &str,
+        span: Range<usize>,
+        field: &str,
+    ) -> Result<Arc<dyn Texture>, SceneError> {
+        if let Some(texture) = self.textures.get(name) {
+            return Ok(texture.clone());
+        }
+
+        let def = self.def;
+        let texture_def = def.textures.get(name).ok_or_else(|| {
+            self.invalid(
+                span.clone(),
+                format!("{}: unknown texture '{}'", field, name),
+            )
+        })?;
+
+        if !self.resolving.insert(name.to_string()) {
+            return Err(self.invalid(
+                span,
+                format!("{}: texture '{}' is part of a reference cycle", field, name),
+            ));
+        }
+
+        let texture: Arc<dyn Texture> = match texture_def.get_ref() {
+            TextureDef::Solid { color } => Arc::new(SolidColor::new(vec3(*color))),
+            TextureDef::Checker { scale, even, odd } => {
+                let field = format!("textures.{}", name);
+                let even = self.color(even, texture_def.span(), &format!("{}.even", field))?;
+                let odd = self.color(odd, texture_def.span(), &format!("{}.odd", field))?;
+                Arc::new(CheckerTexture::new(*scale, even, odd))
+            }
+            TextureDef::Image { path, raw } => {
+                let path = self.resolve_path(path);
+                let image = if *raw {
+                    Image::load_raw(&path)
+                } else {
+                    Image::load(&path)
+                }
+                .map_err(|e| {
+                    let field = format!("textures.{}.path", name);
+                    self.invalid(texture_def.span(), format!("{}: {}", field, e))
+                })?;
+                Arc::new(ImageTexture::new(image))
+            }
+        };
+
+        self.resolving.remove(name);
+        self.textures.insert(name.to_string(), texture.clone());
+        Ok(texture)
+    }
+
+    fn material(
+        &mut self,
+        name: &str,
+        span: Range<usize>,
+        field: &str,
+    ) -> Result<Arc<dyn Material>, SceneError> {
+        if let Some(material) = self.materials.get(name) {
+            return Ok(material.clone());
+        }
+
+        let def = self.def;
+        let material_def = def.materials.get(name).ok_or_else(|| {
+            self.invalid(
+                span.clone(),
+                format!("{}: unknown material '{}'", field, name),
+            )
+        })?;
+
+        if !self.resolving_materials.insert(name.to_string()) {
+            return Err(self.invalid(
+                span,
+                format!(
+                    "{}: material '{}' is part of a reference cycle",
+                    field, name
+                ),
+            ));
+        }
+
+        let span = material_def.span();
+        let field = format!("materials.{}", name);
+        let material: Arc<dyn Material> = match material_def.get_ref() {
+            MaterialDef::Lambertian { albedo } => {
+                let albedo = self.color(albedo, span, &format!("{}.albedo", field))?;
+                Arc::new(Lambertian::with_texture(albedo))
+            }
+            MaterialDef::Metal { albedo, fuzz } => {
+                let albedo = self.color(albedo, span, &format!("{}.albedo", field))?;
+                Arc::new(Metal::with_texture(albedo, *fuzz))
+            }
+            MaterialDef::Dielectric {
+                refraction_index,
+                distribution,
+                roughness,
+                absorption,
+                film,
+            } => {
+                let absorption = absorption.map(vec3).unwrap_or(Color::new());
+                if !(absorption.x() >= 0.0 && absorption.y() >= 0.0 && absorption.z() >= 0.0) {
+                    let message = format!("{}.absorption: must not be negative", field);
+                    return Err(self.invalid(span, message));
+                }
+
+                let film = self.film(film, span.clone(), &field)?;
+
+                match roughness {
+                    Some(roughness) => {
+                        let distribution =
+                            self.distribution(*distribution, *roughness, span, &field)?;
+                        let mut dielectric = RoughDielectric::with_absorption(
+                            *refraction_index,
+                            distribution,
+                            absorption,
+                        );
+                        dielectric.film = film;
+                        Arc::new(dielectric)
+                    }
+                    None => {
+                        let mut dielectric =
+                            Dielectric::with_absorption(*refraction_index, absorption);
+                        dielectric.film = film;
+                        Arc::new(dielectric)
+                    }
+                }
+            }
+            MaterialDef::DispersiveDielectric {
+                preset,
+                cauchy,
+                sellmeier,
+            } => {
+                let dispersion = match (preset, cauchy, sellmeier) {
+                    (Some(name), None, None) => Dispersion::from_preset(name).ok_or_else(|| {
+                        let message = format!(
+                            "{}.preset: unknown preset '{}', expected one of {}",
+                            field,
+                            name,
+                            Dispersion::PRESET_NAMES.join(", ")
+                        );
+                        self.invalid(span.clone(), message)
+                    })?,
+                    (None, Some([a, b]), None) => {
+                        if !(*a >= 1.0 && a.is_finite() && b.is_finite()) {
+                            let message =
+                                format!("{}.cauchy: a must be at least 1, got {}", field, a);
+                            return Err(self.invalid(span, message));
+                        }
+                        Dispersion::Cauchy { a: *a, b: *b }
+                    }
+                    (None, None, Some(SellmeierDef { b, c })) => {
+                        Dispersion::Sellmeier { b: *b, c: *c }
+                    }
+                    _ => {
+                        let message = format!(
+                            "{}: give exactly one of preset, cauchy and sellmeier",
+                            field
+                        );
+                        return Err(self.invalid(span, message));
+                    }
+                };
+                Arc::new(DispersiveDielectric::new(dispersion))
+            }
+            MaterialDef::DiffuseLight { emit } => {
+                let emit = self.color(emit, span, &format!("{}.emit", field))?;
+                Arc::new(DiffuseLight::with_texture(emit))
+            }
+            MaterialDef::Isotropic { albedo } => {
+                let albedo = self.color(albedo, span, &format!("{}.albedo", field))?;
+                Arc::new(Isotropic::with_texture(albedo))
+            }
+            MaterialDef::Principled {
+                base_color,
+                metallic,
+                roughness,
+                specular,
+                clearcoat,
+                clearcoat_roughness,
+                sheen,
+                transmission,
+                ior,
+            } => {
+                let base_color =
+                    self.color(base_color, span.clone(), &format!("{}.base_color", field))?;
+                let mut principled = Principled::with_texture(base_color);
+                let parameters = [
+                    (metallic, "metallic", &mut principled.metallic),
+                    (roughness, "roughness", &mut principled.roughness),
+                    (specular, "specular", &mut principled.specular),
+                    (clearcoat, "clearcoat", &mut principled.clearcoat),
+                    (
+                        clearcoat_roughness,
+                        "clearcoat_roughness",
+                        &mut principled.clearcoat_roughness,
+                    ),
+                    (sheen, "sheen", &mut principled.sheen),
+                    (transmission, "transmission", &mut principled.transmission),
+                ];
+                for (def, name, parameter) in parameters {
+                    if let Some(def) = def {
+                        *parameter =
+                            self.scalar(def, span.clone(), &format!("{}.{}", field, name))?;
+                    }
+                }
+                if let Some(ior) = ior {
+                    if !(*ior > 0.0 && ior.is_finite()) {
+                        let message = format!("{}.ior: must be positive, got {}", field, ior);
+                        return Err(self.invalid(span, message));
+                    }
+                    principled.ior = *ior;
+                }
+                Arc::new(principled)
+            }
+            MaterialDef::Conductor {
+                preset,
+                eta,
+                k,
+                distribution,
+                roughness,
+                film,
+            } => {
+                let roughness = roughness.unwrap_or(RoughnessDef::Isotropic(0.2));
+                let distribution =
+                    self.distribution(*distribution, roughness, span.clone(), &field)?;
+
+                let film = self.film(film, span.clone(), &field)?;
+                let mut conductor = match (preset, eta, k) {
+                    (Some(name), None, None) => Conductor::from_preset(name, distribution)
+                        .ok_or_else(|| {
+                            let message = format!(
+                                "{}.preset: unknown preset '{}', expected one of {}",
+                                field,
+                                name,
+                                Conductor::PRESET_NAMES.join(", ")
+                            );
+                            self.invalid(span.clone(), message)
+                        })?,
+                    (None, Some(eta), Some(k)) => {
+                        Conductor::new(vec3(*eta), vec3(*k), distribution)
+                    }
+                    _ => {
+                        let message = format!("{}: give either a preset or both eta and k", field);
+                        return Err(self.invalid(span, message));
+                    }
+                };
+                conductor.film = film;
+                Arc::new(conductor)
+            }
+            MaterialDef::Layered {
+                base,
+                ior,
+                distribution,
+                roughness,
+                tint,
+                thickness,
+            } => {
+                let base = self.material(base, span.clone(), &format!("{}.base", field))?;
+
+                let ior = ior.unwrap_or(1.5);
+                if !(ior > 0.0 && ior.is_finite()) {
+                    let message = format!("{}.ior: must be positive, got {}", field, ior);
+                    return Err(self.invalid(span, message));
+                }
+                let tint = tint.map(vec3).unwrap_or(Color::with_values(1.0, 1.0, 1.0));
+                if !(tint.x() >= 0.0 && tint.y() >= 0.0 && tint.z() >= 0.0) {
+                    let message = format!("{}.tint: must not be negative", field);
+                    return Err(self.invalid(span, message));
+                }
+                let thickness = thickness.unwrap_or(1.0);
+                if !(thickness >= 0.0 && thickness.is_finite()) {
+                    let message = format!(
+                        "{}.thickness: must not be negative, got {}",
+                        field, thickness
+                    );
+                    return Err(self.invalid(span, message));
+                }
+
+                let roughness = roughness.unwrap_or(RoughnessDef::Isotropic(0.0));
+                let distribution = self.distribution(*distribution, roughness, span, &field)?;
+                Arc::new(Layered::new(base, ior, distribution, tint, thickness))
+            }
+            MaterialDef::Subsurface {
+                albedo,
+                mean_free_path,
+                ior,
+            } => {
+                if !albedo.iter().all(|a| (0.0..=1.0).contains(a)) {
+                    let message = format!("{}.albedo: must be between 0 and 1", field);
+                    return Err(self.invalid(span, message));
+                }
+                if !mean_free_path.iter().all(|d| *d > 0.0 && d.is_finite()) {
+                    let message = format!("{}.mean_free_path: must be positive", field);
+                    return Err(self.invalid(span, message));
+                }
+
+                let mut subsurface = Subsurface::new(vec3(*albedo), vec3(*mean_free_path));
+                if let Some(ior) = ior {
+                    if !(*ior > 0.0 && ior.is_finite()) {
+                        let message = format!("{}.ior: must be positive, got {}", field, ior);
+                        return Err(self.invalid(span, message));
+                    }
+                    subsurface.ior = *ior;
+                }
+                Arc::new(subsurface)
+            }
+            MaterialDef::NormalMap { base, map } => {
+                let base = self.material(base, span.clone(), &format!("{}.base", field))?;
+                let map = self.texture(map, span, &format!("{}.map", field))?;
+                Arc::new(NormalMapped::new(base, map))
+            }
+            MaterialDef::BumpMap {
+                base,
+                height,
+                scale,
+            } => {
+                if !scale.is_finite() {
+                    let message = format!("{}.scale: must be finite, got {}", field, scale);
+                    return Err(self.invalid(span, message));
+                }
+
+                let base = self.material(base, span.clone(), &format!("{}.base", field))?;
+                let height = self.texture(height, span, &format!("{}.height", field))?;
+                Arc::new(NormalMapped::with_bump(base, height, *scale))
+            }
+        };
+
+        self.resolving_materials.remove(name);
+        self.materials.insert(name.to_string(), material.clone());
+        Ok(material)
+    }
+
+    fn distribution(
+        &self,
+        distribution: DistributionDef,
+        roughness: RoughnessDef,
+        span: Range<usize>,
+        field: &str,
+    ) -> Result<Box<dyn MicrofacetDistribution>, SceneError> {
+        let (roughness_x, roughness_y) = match roughness {
+            RoughnessDef::Isotropic(roughness) => (roughness, roughness),
+            RoughnessDef::Anisotropic([x, y]) => (x, y),
+        };
+        if !(roughness_x >= 0.0 && roughness_y >= 0.0) {
+            let message = format!("{}.roughness: must not be negative", field);
+            return Err(self.invalid(span, message));
+        }
+
+        let (alpha_x, alpha_y) = (roughness_x * roughness_x, roughness_y * roughness_y);
+        Ok(match distribution {
+            DistributionDef::Ggx => Box::new(Ggx::new(alpha_x, alpha_y)),
+            DistributionDef::Beckmann => Box::new(Beckmann::new(alpha_x, alpha_y)),
+        })
+    }
+
+    fn film(
+        &mut self,
+        def: &Option<FilmDef>,
+        span: Range<usize>,
+        field: &str,
+    ) -> Result<Option<ThinFilm>, SceneError> {
+        let def = match def {
+            Some(def) => def,
+            None => return Ok(None),
+        };
+        if !(def.ior > 0.0 && def.ior.is_finite()) {
+            let message = format!("{}.film.ior: must be positive, got {}", field, def.ior);
+            return Err(self.invalid(span, message));
+        }
+
+        let thickness = self.scalar(&def.thickness, span, &format!("{}.film.thickness", field))?;
+        Ok(Some(ThinFilm::with_texture(def.ior, thickness)))
+    }
+
+    fn is_light(&self, name: &str) -> bool {
+        matches!(
+            self.def.materials.get(name).map(|m| m.get_ref()),
+            Some(MaterialDef::DiffuseLight { .. })
+        )
+    }
+
+    fn camera(&self) -> Camera {
+        let def = &self.def.camera;
+        let mut camera = Camera::new();
+
+        if let Some(aspect_ratio) = def.aspect_ratio {
+            camera.aspect_ratio = aspect_ratio;
+        }
+        if let Some(image_width) = def.image_width {
+            camera.image_width = image_width;
+        }
+        if let Some(samples_per_pixel) = def.samples_per_pixel {
+            camera.smaples_per_pixel = samples_per_pixel;
+        }
+        if let Some(max_depth) = def.max_depth {
+            camera.max_depth = max_depth;
+        }
+        if let Some(v_fov) = def.v_fov {
+            camera.v_fov = v_fov;
+        }
+        if let Some(lookfrom) = def.lookfrom {
+            camera.lookfrom = vec3(lookfrom);
+        }
+        if let Some(lookat) = def.lookat {
+            camera.lookat = vec3(lookat);
+        }
+        if let Some(vup) = def.vup {
+            camera.vup = vec3(vup);
+        }
+        if let Some(defocus_angle) = def.defocus_angle {
+            camera.defocus_angle = defocus_angle;
+        }
+        if let Some(focus_dist) = def.focus_dist {
+            camera.focus_dist = focus_dist;
+        }
+        if let Some(background) = def.background {
+            camera.background = Background::Solid(vec3(background));
+        }
+        if let Some(shutter_open) = def.shutter_open {
+            camera.shutter_open = shutter_open;
+        }
+        if let Some(shutter_close) = def.shutter_close {
+            camera.shutter_close = shutter_close;
+        }
+        if let Some(spectral) = def.spectral {
+            camera.spectral = spectral;
+        }
+
+        camera
+    }
+
+    // `span` and `field` locate the object for error messages
+    fn object(
+        &mut self,
+        def: &ObjectDef,
+        span: Range<usize>,
+        field: &str,
+    ) -> Result<Box<dyn Hittable>, SceneError> {
+        let mat: Arc<dyn Material> = match def.material() {
+            Some(name) => self.material(name, span.clone(), &format!("{}.material", field))?,
+            None => Arc::new(Lambertian::new(Color::with_values(0.5, 0.5, 0.5))),
+        };
+
+        let object: Box<dyn Hittable> = match def {
+            ObjectDef::Sphere {
+                center,
+                center2,
+                radius,
+                ..
+            } => {
+                let center2 = center2.unwrap_or(*center);
+                Box::new(Sphere::moving(vec3(*center), vec3(center2), *radius, mat))
+            }
+            ObjectDef::Triangle { p0, p1, p2, .. } => {
+                Box::new(Triangle::new(vec3(*p0), vec3(*p1), vec3(*p2), mat))
+            }
+            ObjectDef::Quad { q, u, v, .. } => {
+                if vec3(*u).cross(&vec3(*v)).near_zero() {
+                    let message = format!("{}: u and v must span a parallelogram", field);
+                    return Err(self.invalid(span, message));
+                }
+                Box::new(Quad::new(vec3(*q), vec3(*u), vec3(*v), mat))
+            }
+            ObjectDef::Disk {
+                center,
+                normal,
+                radius,
+                ..
+            } => {
+                if vec3(*normal).near_zero() {
+                    let message = format!("{}.normal: must not be zero", field);
+                    return Err(self.invalid(span, message));
+                }
+                Box::new(Disk::new(vec3(*center), vec3(*normal), *radius, mat))
+            }
+            ObjectDef::Box { a, b, .. } => Box::new(make_box(vec3(*a), vec3(*b), mat)),
+            ObjectDef::Cylinder { p0, p1, radius, .. } => {
+                if (vec3(*p1) - vec3(*p0)).near_zero() {
+                    let message = format!("{}: p0 and p1 must be different points", field);
+                    return Err(self.invalid(span, message));
+                }
+                if !(*radius > 0.0 && radius.is_finite()) {
+                    let message = format!("{}.radius: must be positive, got {}", field, radius);
+                    return Err(self.invalid(span, message));
+                }
+                Box::new(Cylinder::new(vec3(*p0), vec3(*p1), *radius, mat))
+            }
+            ObjectDef::Cone {
+                base, apex, radius, ..
+            } => {
+                if (vec3(*apex) - vec3(*base)).near_zero() {
+                    let message = format!("{}: base and apex must be different points", field);
+                    return Err(self.invalid(span, message));
+                }
+                if !(*radius > 0.0 && radius.is_finite()) {
+                    let message = format!("{}.radius: must be positive, got {}", field, radius);
+                    return Err(self.invalid(span, message));
+                }
+                Box::new(Cone::new(vec3(*base), vec3(*apex), *radius, mat))
+            }
+            ObjectDef::Torus {
+                center,
+                axis,
+                major_radius,
+                minor_radius,
+                ..
+            } => {
+                if vec3(*axis).near_zero() {
+                    let message = format!("{}.axis: must not be zero", field);
+                    return Err(self.invalid(span, message));
+                }
+                for (name, value) in [
+                    ("major_radius", major_radius),
+                    ("minor_radius", minor_radius),
+                ] {
+                    if !(*value > 0.0 && value.is_finite()) {
+                        let message =
+                            format!("{}.{}: must be positive, got {}", field, name, value);
+                        return Err(self.invalid(span, message));
+                    }
+                }
+                Box::new(Torus::new(
+                    vec3(*center),
+                    vec3(*axis),
+                    *major_radius,
+                    *minor_radius,
+                    mat,
+                ))
+            }
+            ObjectDef::Ellipsoid { center, radii, .. } => {
+                if !radii.iter().all(|r| *r > 0.0 && r.is_finite()) {
+                    let message = format!("{}.radii: must all be positive", field);
+                    return Err(self.invalid(span, message));
+                }
+                Box::new(Ellipsoid::new(vec3(*center), vec3(*radii), mat))
+            }
+            ObjectDef::Mesh { path, .. } => {
+                Box::new(load_obj(&self.resolve_path(path), mat).map_err(SceneError::Obj)?)
+            }
+            ObjectDef::Heightfield {
+                path, corner, size, ..
+            } => {
+                if !(size[0] > 0.0 && size[2] > 0.0) {
+                    let message = format!("{}.size: x and z must be positive", field);
+                    return Err(self.invalid(span, message));
+                }
+                let image = Image::load_raw(&self.resolve_path(path))
+                    .map_err(|e| self.invalid(span.clone(), format!("{}.path: {}", field, e)))?;
+                if image.width() < 2 || image.height() < 2 {
+                    let message = format!("{}.path: height maps need at least 2x2 pixels", field);
+                    return Err(self.invalid(span, message));
+                }
+                Box::new(Heightfield::new(&image, vec3(*corner), vec3(*size), mat))
+            }
+            ObjectDef::ConstantMedium { boundary, density } => {
+                if !(*density > 0.0 && density.is_finite()) {
+                    let message = format!("{}.density: must be positive, got {}", field, density);
+                    return Err(self.invalid(span, message));
+                }
+
+                let field = format!("{}.boundary", field);
+                let boundary = self.object(boundary, span, &field)?;
+                Box::new(ConstantMedium::new(boundary, *density, mat))
+            }
+            ObjectDef::Transform {
+                object,
+                scale,
+                rotate,
+                translate,
+            } => {
+                let mut matrix = Mat4::new();
+
+                if let Some(scale) = scale {
+                    if scale.contains(&0.0) {
+                        let message = format!("{}.scale: factors must not be zero", field);
+                        return Err(self.invalid(span, message));
+                    }
+                    matrix = Mat4::scaling(&vec3(*scale)) * matrix;
+                }
+                if let Some(rotate) = rotate {
+                    if vec3(rotate.axis).near_zero() {
+                        let message = format!("{}.rotate.axis: must not be zero", field);
+                        return Err(self.invalid(span, message));
+                    }
+                    matrix = Mat4::rotation(&vec3(rotate.axis), rotate.angle) * matrix;
+                }
+                if let Some(translate) = translate {
+                    matrix = Mat4::translation(&vec3(*translate)) * matrix;
+                }
+
+                let object = self.object(object, span, &format!("{}.object", field))?;
+                Box::new(Transform::new(object, matrix))
+            }
+            ObjectDef::Sdf {
+                shape, step_scale, ..
+            } => {
+                let step_scale = step_scale.unwrap_or(1.0);
+                if !(step_scale > 0.0 && step_scale <= 1.0) {
+                    let message = format!(
+                        "{}.step_scale: must be between 0 and 1, got {}",
+                        field, step_scale
+                    );
+                    return Err(self.invalid(span, message));
+                }
+                let shape = shape
+                    .build(&format!("{}.shape", field))
+                    .map_err(|message| self.invalid(span, message))?;
+                Box::new(SdfObject::with_step_scale(shape, step_scale, mat))
+            }
+            ObjectDef::Csg {
+                operation,
+                left,
+                right,
+            } => {
+                let operation = match operation {
+                    CsgOperationDef::Union => CsgOperation::Union,
+                    CsgOperationDef::Intersection => CsgOperation::Intersection,
+                    CsgOperationDef::Difference => CsgOperation::Difference,
+                };
+                let left = self.object(left, span.clone(), &format!("{}.left", field))?;
+                let right = self.object(right, span, &format!("{}.right", field))?;
+                Box::new(Csg::new(left, right, operation))
+            }
+        };
+
+        Ok(object)
+    }
+}
+
+// Reads a scene description file and builds its world, light list and camera. Static spheres,
+// quads and disks with a `diffuse_light` material are also added to the lights so they are
+// sampled directly.
+pub fn load_scene(path: &Path) -> Result<Scene, SceneError> {
+    let source = fs::read_to_string(path).map_err(|error| SceneError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+
+    build_scene(path, &source)
+}
+
+// Syntax errors, unknown fields and wrong types, at the position they were found at and with
+// the name of the field.
+//
+// Serde reads tables tagged with a `type` into a buffer before picking the variant, which loses
+// the positions inside them: errors in their fields point at the whole table. Such a table is
+// read again with one key left out at a time, and the key whose absence changes the error is
+// the one at fault.
+fn syntax_error(path: &Path, source: &str, error: &toml::de::Error) -> SceneError {
+    let invalid = |offset: usize, message: String| SceneError::Invalid {
+        path: path.to_path_buf(),
+        line: line_of(source, offset),
+        message,
+    };
+    let span = match error.span() {
+        Some(span) => span,
+        None => {
+            return SceneError::Parse {
+                path: path.to_path_buf(),
+                message: error.message().to_string(),
+            }
+        }
+    };
+    let root = match DeTable::parse(source) {
+        Ok(root) => Spanned::new(root.span(), DeValue::Table(root.into_inner())),
+        Err(_) => return invalid(span.start, error.message().to_string()),
+    };
+
+    let mut field = Vec::new();
+    let value = value_at(&root, span.start, &mut field);
+    let check: Option<fn(DeValue) -> Option<String>> = match field.as_slice() {
+        [section, _] if section == "textures" => Some(deserialize_error::<TextureDef>),
+        [section, _] if section == "materials" => Some(deserialize_error::<MaterialDef>),
+        [section, index] if section == "objects" && index.starts_with('[') => {
+            Some(deserialize_error::<ObjectDef>)
+        }
+        _ => None,
+    };
+    let field = field.iter().fold(String::new(), |path, name| {
+        if path.is_empty() || name.starts_with('[') {
+            path + name
+        } else {
+            path + "." + name
+        }
+    });
+
+    if let (Some(check), DeValue::Table(keys)) = (check, value.get_ref()) {
+        if value.span() == span {
+            let message = check(value.get_ref().clone());
+            for key in keys.keys().filter(|key| key.get_ref() != "type") {
+                let mut rest = keys.clone();
+                rest.remove(key.get_ref().as_ref());
+                if check(DeValue::Table(rest)) != message {
+                    return invalid(
+                        key.span().start,
+                        format!("{}.{}: {}", field, key.get_ref(), error.message()),
+                    );
+                }
+            }
+        }
+    }
+
+    if field.is_empty() {
+        invalid(span.start, error.message().to_string())
+    } else {
+        invalid(span.start, format!("{}: {}", field, error.message()))
+    }
+}
+
+// Innermost value around `offset`, with the keys and `[index]`es leading to it added to `field`
+fn value_at<'a, 'i>(
+    value: &'a Spanned<DeValue<'i>>,
+    offset: usize,
+    field: &mut Vec<String>,
+) -> &'a Spanned<DeValue<'i>> {
+    let inner = match value.get_ref() {
+        DeValue::Table(table) => table.iter().find_map(|(key, inner)| {
+            let found = key.span().contains(&offset) || covers(inner, offset);
+            found.then(|| (key.get_ref().to_string(), inner))
+        }),
+        DeValue::Array(array) => array.iter().enumerate().find_map(|(index, inner)| {
+            covers(inner, offset).then(|| (format!("[{}]", index), inner))
+        }),
+        _ => None,
+    };
+
+    match inner {
+        Some((name, inner)) => {
+            field.push(name);
+            value_at(inner, offset, field)
+        }
+        None => value,
+    }
+}
+
+// Whether `offset` lies in `value` or any key or value nested in it. The spans of tables only
+// cover their `[header]`, not the keys below it.
+fn covers(value: &Spanned<DeValue>, offset: usize) -> bool {
+    value.span().contains(&offset)
+        || match value.get_ref() {
+            DeValue::Table(table) => table
+                .iter()
+                .any(|(key, inner)| key.span().contains(&offset) || covers(inner, offset)),
+            DeValue::Array(array) => array.iter().any(|inner| covers(inner, offset)),
+            _ => false,
+        }
+}
+
+fn deserialize_error<T: for<'de> Deserialize<'de>>(value: DeValue) -> Option<String> {
+    T::deserialize(ValueDeserializer::from(Spanned::new(0..0, value)))
+        .err()
+        .map(|e| e.message().to_string())
+}
+
+// Builds the scene in `source`, the contents of the file at `path`
+fn build_scene(path: &Path, source: &str) -> Result<Scene, SceneError> {
+    let def: SceneDef = toml::from_str(source).map_err(|e| syntax_error(path, source, &e))?;
+
+    let mut loader = Loader {
+        path,
+        source,
+        def: &def,
+        textures: HashMap::new(),
+        materials: HashMap::new(),
+        resolving: HashSet::new(),
+        resolving_materials: HashSet::new(),
+    };
+
+    let mut world = HittableList::new();
+    let mut lights = HittableList::new();
+
+    for (index, object) in def.objects.iter().enumerate() {
+        let field = format!("objects[{}]", index);
+        let material_name = object.get_ref().material();
+
+        world.add(loader.object(object.get_ref(), object.span(), &field)?);
+
+        let can_sample = matches!(
+            object.get_ref(),
+            ObjectDef::Sphere { center2: None, .. }
+                | ObjectDef::Quad { .. }
+                | ObjectDef::Disk { .. }
+        );
+        if can_sample && material_name.is_some_and(|name| loader.is_light(name)) {
+            lights.add(loader.object(object.get_ref(), object.span(), &field)?);
+        }
+    }
+
+    // Materials that no object uses are still built, so mistakes in them don't go unnoticed
+    let mut material_names: Vec<_> = def.materials.keys().collect();
+    material_names.sort();
+    for name in material_names {
+        let span = def.materials[name].span();
+        loader.material(name, span, "materials")?;
+    }
+
+    Ok(Scene {
+        world,
+        lights,
+        camera: loader.camera(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(source: &str) -> Result<Scene, SceneError> {
+        build_scene(Path::new("scene.toml"), source)
+    }
+
+    // Line and message of the error `source` fails to load with, checking how it is displayed
+    fn error(source: &str) -> (usize, String) {
+        let error = match load(source) {
+            Ok(_) => panic!("the scene should fail to load"),
+            Err(error) => error,
+        };
+        match &error {
+            SceneError::Invalid { line, message, .. } => {
+                assert_eq!(
+                    error.to_string(),
+                    format!("scene.toml:{}: {}", line, message)
+                );
+                (*line, message.clone())
+            }
+            _ => panic!("{} has no line", error),
+        }
+    }
+
+    const SPHERE: &str = "
+[materials.red]
+type = \"lambertian\"
+albedo = [1.0, 0.0, 0.0]
+
+[[objects]]
+type = \"sphere\"
+center = [0.0, 0.0, -1.0]
+radius = 0.5
+material = \"red\"
+";
+
+    #[test]
+    fn loads_camera_objects_and_lights() {
+        let source = format!(
+            "[camera]\nimage_width = 64\nv_fov = 30.0\nlookfrom = [0.0, 1.0, 2.0]\n{}
+[materials.lamp]
+type = \"diffuse_light\"
+emit = \"warm\"
+
+[textures.warm]
+type = \"solid\"
+color = [4.0, 3.0, 2.0]
+
+[[objects]]
+type = \"transform\"
+translate = [0.0, 3.0, 0.0]
+object = {{ type = \"quad\", q = [0.0, 0.0, 0.0], u = [1.0, 0.0, 0.0], v = [0.0, 0.0, 1.0], \
+           material = \"lamp\" }}
+
+[[objects]]
+type = \"disk\"
+center = [0.0, 2.0, 0.0]
+normal = [0.0, -1.0, 0.0]
+radius = 0.5
+material = \"lamp\"
+",
+            SPHERE
+        );
+        let scene = load(&source).unwrap();
+
+        assert_eq!(scene.camera.image_width, 64);
+        assert_eq!(scene.camera.v_fov, 30.0);
+        assert_eq!(scene.camera.lookfrom.y(), 1.0);
+        assert_eq!(scene.world.list.len(), 3);
+        // Only the disk is sampled, the quad is moved by a transform
+        assert_eq!(scene.lights.list.len(), 1);
+    }
+
+    #[test]
+    fn unknown_fields_are_reported_where_they_are() {
+        let (line, message) = error("[camera]\nv_fov = 20.0\nlook_from = [1.0, 2.0, 3.0]\n");
+        assert_eq!(line, 3);
+        assert!(
+            message.starts_with("camera.look_from: unknown field"),
+            "{}",
+            message
+        );
+
+        // Inside tables tagged with their type
+        let (line, message) = error(&SPHERE.replace("albedo", "albdo"));
+        assert_eq!(line, 4);
+        assert!(
+            message.starts_with("materials.red.albdo: unknown field"),
+            "{}",
+            message
+        );
+        assert!(message.contains("expected `albedo`"), "{}", message);
+
+        let (line, message) = error("[[objects]]\ntype = \"spheer\"\n");
+        assert_eq!(line, 2);
+        assert!(
+            message.starts_with("objects[0].type: unknown variant"),
+            "{}",
+            message
+        );
+
+        let (line, message) = error("[lights]\n");
+        assert_eq!(line, 1);
+        assert!(message.contains("unknown field `lights`"), "{}", message);
+    }
+
+    #[test]
+    fn wrong_types_name_the_field() {
+        let (line, message) = error("[camera]\nv_fov = \"wide\"\n");
+        assert_eq!(line, 2);
+        assert!(
+            message.starts_with("camera.v_fov: invalid type"),
+            "{}",
+            message
+        );
+
+        let (line, message) = error(&SPHERE.replace("0.5", "\"big\""));
+        assert_eq!(line, 9);
+        assert!(
+            message.starts_with("objects[0].radius: invalid type"),
+            "{}",
+            message
+        );
+
+        // Errors nested in an inline table are traced back to the key holding it
+        let source = "[[objects]]
+type = \"transform\"
+translate = [0.0, 1.0, 0.0]
+object = { type = \"sphere\", center = [0.0, 0.0, 0.0], radius = [1.0], material = \"red\" }
+";
+        let (line, message) = error(source);
+        assert_eq!(line, 4);
+        assert!(message.starts_with("objects[0].object: "), "{}", message);
+    }
+
+    #[test]
+    fn unknown_references_name_the_field() {
+        let (line, message) = error(&SPHERE.replace("material = \"red\"", "material = \"blue\""));
+        assert_eq!(line, 6);
+        assert_eq!(message, "objects[0].material: unknown material 'blue'");
+
+        let (line, message) = error(&SPHERE.replace("[1.0, 0.0, 0.0]", "\"marble\""));
+        assert_eq!(line, 2);
+        assert_eq!(message, "materials.red.albedo: unknown texture 'marble'");
+    }
+
+    #[test]
+    fn invalid_sdf_shapes_name_the_field() {
+        let source = SPHERE.replace(
+            "type = \"sphere\"\ncenter = [0.0, 0.0, -1.0]\nradius = 0.5",
+            "type = \"sdf\"
+shape = { type = \"union\", a = { type = \"sphere\", center = [0.0, 0.0, 0.0], radius = 1.0 }, \
+          b = { type = \"mandelbulb\", power = 1.0, iterations = 8 } }",
+        );
+        let (line, message) = error(&source);
+        assert_eq!(line, 6);
+        assert_eq!(message, "objects[0].shape.b.power: must be above 1, got 1");
+    }
+}
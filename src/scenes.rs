@@ -0,0 +1,288 @@
+use std::sync::Arc;
+
+use crate::{
+    camera::{Background, Camera},
+    color::Color,
+    hittable_list::HittableList,
+    material::{Dielectric, DiffuseLight, Lambertian, Metal},
+    quad::{make_box, Quad},
+    sphere::Sphere,
+    texture::CheckerTexture,
+    transform::Transform,
+    vec3::{Point3, Vec3},
+};
+
+pub struct Scene {
+    pub world: HittableList,
+    // Emitters that are sampled explicitly; they must also be part of the world to be visible
+    pub lights: HittableList,
+    pub camera: Camera,
+}
+
+// Names of the built-in scenes, as accepted by `by_name`
+pub const SCENE_NAMES: [&str; 3] = ["spheres", "checkered-spheres", "cornell-box"];
+
+pub fn by_name(name: &str) -> Option<Scene> {
+    match name {
+        "spheres" => Some(spheres()),
+        "checkered-spheres" => Some(checkered_spheres()),
+        "cornell-box" => Some(cornell_box()),
+        _ => None,
+    }
+}
+
+pub fn spheres() -> Scene {
+    let mut world = HittableList::new();
+    let material_ground = Arc::new(Lambertian::new(Color::with_values(0.8, 0.8, 0.0)));
+    let material_center = Arc::new(Lambertian::new(Color::with_values(0.1, 0.2, 0.5)));
+    let material_left = Arc::new(Dielectric::new(1.50));
+    let material_bubble = Arc::new(Dielectric::new(1.00 / 1.50));
+    let material_right = Arc::new(Metal::new(Color::with_values(0.8, 0.6, 0.2), 1.0));
+
+    world.add(Box::new(Sphere::new(
+        Point3::with_values(0.0, -100.5, -1.0),
+        100.0,
+        material_ground,
+    )));
+
+    world.add(Box::new(Sphere::new(
+        Point3::with_values(0.0, 0.0, -1.2),
+        0.5,
+        material_center,
+    )));
+
+    world.add(Box::new(Sphere::new(
+        Point3::with_values(-1.0, 0.0, -1.0),
+        0.5,
+        material_left,
+    )));
+    world.add(Box::new(Sphere::new(
+        Point3::with_values(-1.0, 0.0, -1.0),
+        0.4,
+        material_bubble,
+    )));
+    world.add(Box::new(Sphere::new(
+        Point3::with_values(1.0, 0.0, -1.0),
+        0.5,
+        material_right,
+    )));
+    let mut camera = Camera::new();
+
+    camera.aspect_ratio = 16.0 / 9.0;
+    camera.image_width = 800;
+    camera.smaples_per_pixel = 100;
+    camera.max_depth = 50;
+    camera.v_fov = 20.0;
+
+    camera.focus_dist = 3.4;
+    camera.defocus_angle = 10.0;
+
+    camera.lookfrom = Point3::with_values(-2.0, 2.0, 1.0);
+    camera.lookat = Point3::with_values(0.0, 0.0, -1.0);
+    camera.vup = Point3::with_values(0.0, 1.0, 0.0);
+
+    Scene {
+        world,
+        lights: HittableList::new(),
+        camera,
+    }
+}
+
+pub fn checkered_spheres() -> Scene {
+    let mut world = HittableList::new();
+    let checker = Arc::new(CheckerTexture::with_colors(
+        0.32,
+        Color::with_values(0.2, 0.3, 0.1),
+        Color::with_values(0.9, 0.9, 0.9),
+    ));
+
+    world.add(Box::new(Sphere::new(
+        Point3::with_values(0.0, -10.0, 0.0),
+        10.0,
+        Arc::new(Lambertian::with_texture(checker.clone())),
+    )));
+    world.add(Box::new(Sphere::new(
+        Point3::with_values(0.0, 10.0, 0.0),
+        10.0,
+        Arc::new(Lambertian::with_texture(checker)),
+    )));
+
+    let mut camera = Camera::new();
+
+    camera.aspect_ratio = 16.0 / 9.0;
+    camera.image_width = 400;
+    camera.smaples_per_pixel = 100;
+    camera.max_depth = 50;
+
+    camera.v_fov = 20.0;
+    camera.lookfrom = Point3::with_values(13.0, 2.0, 3.0);
+    camera.lookat = Point3::with_values(0.0, 0.0, 0.0);
+    camera.vup = Point3::with_values(0.0, 1.0, 0.0);
+
+    camera.defocus_angle = 0.0;
+
+    Scene {
+        world,
+        lights: HittableList::new(),
+        camera,
+    }
+}
+
+// Cornell-box style room lit by a rectangular light in the ceiling
+pub fn cornell_box() -> Scene {
+    let mut world = HittableList::new();
+    let red = Arc::new(Lambertian::new(Color::with_values(0.65, 0.05, 0.05)));
+    let white = Arc::new(Lambertian::new(Color::with_values(0.73, 0.73, 0.73)));
+    let green = Arc::new(Lambertian::new(Color::with_values(0.12, 0.45, 0.15)));
+    let light = Arc::new(DiffuseLight::new(Color::with_values(15.0, 15.0, 15.0)));
+
+    // The room spans -1 to 1 on every axis and is open towards the camera
+    world.add(Box::new(Quad::new(
+        Point3::with_values(-1.0, -1.0, -1.0),
+        Vec3::with_values(0.0, 0.0, 2.0),
+        Vec3::with_values(0.0, 2.0, 0.0),
+        red,
+    )));
+    world.add(Box::new(Quad::new(
+        Point3::with_values(1.0, -1.0, -1.0),
+        Vec3::with_values(0.0, 2.0, 0.0),
+        Vec3::with_values(0.0, 0.0, 2.0),
+        green,
+    )));
+    world.add(Box::new(Quad::new(
+        Point3::with_values(-1.0, -1.0, -1.0),
+        Vec3::with_values(2.0, 0.0, 0.0),
+        Vec3::with_values(0.0, 0.0, 2.0),
+        white.clone(),
+    )));
+    world.add(Box::new(Quad::new(
+        Point3::with_values(-1.0, 1.0, -1.0),
+        Vec3::with_values(0.0, 0.0, 2.0),
+        Vec3::with_values(2.0, 0.0, 0.0),
+        white.clone(),
+    )));
+    world.add(Box::new(Quad::new(
+        Point3::with_values(-1.0, -1.0, -1.0),
+        Vec3::with_values(0.0, 2.0, 0.0),
+        Vec3::with_values(2.0, 0.0, 0.0),
+        white.clone(),
+    )));
+
+    // Facing down, just below the ceiling
+    let light_corner = Point3::with_values(-0.25, 0.999, -0.2);
+    let light_u = Vec3::with_values(0.5, 0.0, 0.0);
+    let light_v = Vec3::with_values(0.0, 0.0, 0.4);
+    world.add(Box::new(Quad::new(light_corner, light_u, light_v, light)));
+
+    let tall_box = make_box(
+        Point3::with_values(-0.3, -1.0, -0.3),
+        Point3::with_values(0.3, 0.2, 0.3),
+        white,
+    );
+    let tall_box = Transform::rotate(Box::new(tall_box), Vec3::with_values(0.0, 1.0, 0.0), 15.0);
+    world.add(Box::new(Transform::translate(
+        Box::new(tall_box),
+        Vec3::with_values(-0.35, 0.0, -0.3),
+    )));
+    world.add(Box::new(Sphere::new(
+        Point3::with_values(0.45, -0.65, 0.2),
+        0.35,
+        Arc::new(Dielectric::new(1.5)),
+    )));
+
+    let mut lights = HittableList::new();
+    lights.add(Box::new(Quad::new(
+        light_corner,
+        light_u,
+        light_v,
+        Arc::new(DiffuseLight::new(Color::new())),
+    )));
+
+    let mut camera = Camera::new();
+
+    camera.aspect_ratio = 1.0;
+    camera.image_width = 600;
+    camera.smaples_per_pixel = 100;
+    camera.max_depth = 50;
+    camera.background = Background::Solid(Color::new());
+
+    camera.v_fov = 40.0;
+    camera.lookfrom = Point3::with_values(0.0, 0.0, 3.7);
+    camera.lookat = Point3::with_values(0.0, 0.0, 0.0);
+    camera.vup = Point3::with_values(0.0, 1.0, 0.0);
+
+    camera.defocus_angle = 0.0;
+
+    Scene {
+        world,
+        lights,
+        camera,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::Image;
+
+    const RENDERS: usize = 8;
+    const WIDTH: i32 = 16;
+
+    // Small crop of the back wall of the Cornell box, lit directly by the ceiling light
+    fn render_cornell_crop(light_sampling: bool, seed: u64) -> Image {
+        let mut scene = cornell_box();
+        scene.camera.image_width = WIDTH;
+        scene.camera.smaples_per_pixel = 32;
+        scene.camera.max_depth = 8;
+        scene.camera.v_fov = 10.0;
+        scene.camera.lookat = Point3::with_values(0.5, 0.0, -1.0);
+        scene.camera.light_sampling = light_sampling;
+        scene.camera.seed = Some(seed);
+        scene.camera.show_progress = false;
+
+        scene.camera.render(&scene.world, &scene.lights)
+    }
+
+    // Mean over the pixels of the brightness and of its variance between renders
+    fn mean_and_variance(light_sampling: bool) -> (f64, f64) {
+        let renders: Vec<Image> = (0..RENDERS as u64)
+            .map(|seed| render_cornell_crop(light_sampling, seed))
+            .collect();
+        let pixel_count = renders[0].pixels().len();
+        let brightness = |c: &Color| (c.x() + c.y() + c.z()) / 3.0;
+
+        let mut mean = 0.0;
+        let mut variance = 0.0;
+        for i in 0..pixel_count {
+            let values: Vec<f64> = renders.iter().map(|r| brightness(&r.pixels()[i])).collect();
+            let pixel_mean = values.iter().sum::<f64>() / RENDERS as f64;
+            mean += pixel_mean;
+            variance +=
+                values.iter().map(|v| (v - pixel_mean).powi(2)).sum::<f64>() / (RENDERS - 1) as f64;
+        }
+
+        (mean / pixel_count as f64, variance / pixel_count as f64)
+    }
+
+    #[test]
+    fn light_sampling_reduces_noise_without_bias() {
+        let (mean_on, variance_on) = mean_and_variance(true);
+        let (mean_off, variance_off) = mean_and_variance(false);
+        assert!(
+            variance_on * 20.0 < variance_off,
+            "variance {} with light sampling, {} without",
+            variance_on,
+            variance_off
+        );
+
+        // Both estimate the same image, so the means only differ by noise
+        let samples = (WIDTH * WIDTH) as f64 * RENDERS as f64;
+        let standard_error = ((variance_on + variance_off) / samples).sqrt();
+        assert!(
+            (mean_on - mean_off).abs() < 4.0 * standard_error,
+            "mean {} with light sampling, {} without",
+            mean_on,
+            mean_off
+        );
+    }
+}
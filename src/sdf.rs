@@ -0,0 +1,678 @@
+use std::{f64::consts::PI, sync::Arc};
+
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable},
+    interval::Interval,
+    material::Material,
+    ray::Ray,
+    vec3::{Point3, Vec3},
+};
+
+// Closest distance from a point to a surface, negative inside of it. Distances may
+// underestimate but should not overestimate, or sphere tracing steps through the surface.
+pub trait Sdf: Send + Sync {
+    fn distance(&self, p: &Point3) -> f64;
+
+    // Box outside of which the distance is never negative
+    fn bounding_box(&self) -> Aabb;
+}
+
+// Arbitrary distance function, bounded by a box given along with it
+pub struct SdfFunction {
+    f: Box<dyn Fn(&Point3) -> f64 + Send + Sync>,
+    bbox: Aabb,
+}
+
+impl SdfFunction {
+    pub fn new(f: impl Fn(&Point3) -> f64 + Send + Sync + 'static, bbox: Aabb) -> SdfFunction {
+        SdfFunction {
+            f: Box::new(f),
+            bbox,
+        }
+    }
+}
+
+impl Sdf for SdfFunction {
+    fn distance(&self, p: &Point3) -> f64 {
+        (self.f)(p)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+pub struct SdfSphere {
+    center: Point3,
+    radius: f64,
+}
+
+impl SdfSphere {
+    pub fn new(center: Point3, radius: f64) -> SdfSphere {
+        SdfSphere { center, radius }
+    }
+}
+
+impl Sdf for SdfSphere {
+    fn distance(&self, p: &Point3) -> f64 {
+        (*p - self.center).length() - self.radius
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let rvec = Vec3::with_values(self.radius, self.radius, self.radius);
+        Aabb::from_points(self.center - rvec, self.center + rvec)
+    }
+}
+
+// Axis-aligned box with half sizes `half_extents` whose edges are rounded off with `radius`
+pub struct SdfRoundedBox {
+    center: Point3,
+    half_extents: Vec3,
+    radius: f64,
+}
+
+impl SdfRoundedBox {
+    pub fn new(center: Point3, half_extents: Vec3, radius: f64) -> SdfRoundedBox {
+        SdfRoundedBox {
+            center,
+            half_extents,
+            radius,
+        }
+    }
+}
+
+impl Sdf for SdfRoundedBox {
+    fn distance(&self, p: &Point3) -> f64 {
+        let d = *p - self.center;
+        let q = Vec3::with_values(
+            d.x().abs() - self.half_extents.x() + self.radius,
+            d.y().abs() - self.half_extents.y() + self.radius,
+            d.z().abs() - self.half_extents.z() + self.radius,
+        );
+        let outside = Vec3::with_values(q.x().max(0.0), q.y().max(0.0), q.z().max(0.0));
+        let inside = q.x().max(q.y()).max(q.z()).min(0.0);
+
+        outside.length() + inside - self.radius
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::from_points(
+            self.center - self.half_extents,
+            self.center + self.half_extents,
+        )
+    }
+}
+
+// Torus around the y axis through `center`
+pub struct SdfTorus {
+    center: Point3,
+    major_radius: f64,
+    minor_radius: f64,
+}
+
+impl SdfTorus {
+    pub fn new(center: Point3, major_radius: f64, minor_radius: f64) -> SdfTorus {
+        SdfTorus {
+            center,
+            major_radius,
+            minor_radius,
+        }
+    }
+}
+
+impl Sdf for SdfTorus {
+    fn distance(&self, p: &Point3) -> f64 {
+        let d = *p - self.center;
+        let ring = (d.x() * d.x() + d.z() * d.z()).sqrt() - self.major_radius;
+        (ring * ring + d.y() * d.y()).sqrt() - self.minor_radius
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let extent = self.major_radius + self.minor_radius;
+        let e = Vec3::with_values(extent, self.minor_radius, extent);
+        Aabb::from_points(self.center - e, self.center + e)
+    }
+}
+
+// Mandelbulb fractal around the origin, using the usual distance estimator. Its distances are
+// estimates, so render it with a step scale below one. The power must be above one.
+pub struct SdfMandelbulb {
+    power: f64,
+    iterations: usize,
+}
+
+impl SdfMandelbulb {
+    pub fn new(power: f64, iterations: usize) -> SdfMandelbulb {
+        assert!(
+            power > 1.0,
+            "Mandelbulb power must be above 1, got {}",
+            power
+        );
+        SdfMandelbulb { power, iterations }
+    }
+
+    // Points farther out than this escape: when |p|^(power - 1) > 2, every iteration grows |z| by
+    // at least |z|^power - |p| > |z|, so r stays above one and the distance positive
+    pub fn escape_radius(&self) -> f64 {
+        2.0_f64.powf(1.0 / (self.power - 1.0))
+    }
+}
+
+impl Sdf for SdfMandelbulb {
+    fn distance(&self, p: &Point3) -> f64 {
+        let mut z = *p;
+        let mut dr = 1.0;
+        let mut r = z.length();
+
+        for _ in 0..self.iterations {
+            if r > 2.0 {
+                break;
+            }
+
+            // Raise z to the power in spherical coordinates and add the starting point
+            let theta = (z.z() / r).clamp(-1.0, 1.0).acos() * self.power;
+            let phi = z.y().atan2(z.x()) * self.power;
+            let zr = r.powf(self.power);
+            dr = r.powf(self.power - 1.0) * self.power * dr + 1.0;
+
+            z =
+                zr * Vec3::with_values(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                ) + *p;
+            r = z.length();
+        }
+
+        if r == 0.0 {
+            return 0.0;
+        }
+
+        0.5 * r.ln() * r / dr
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let radius = self.escape_radius();
+        let extent = Vec3::with_values(radius, radius, radius);
+        Aabb::from_points(-extent, extent)
+    }
+}
+
+pub struct SdfUnion {
+    a: Box<dyn Sdf>,
+    b: Box<dyn Sdf>,
+}
+
+impl SdfUnion {
+    pub fn new(a: Box<dyn Sdf>, b: Box<dyn Sdf>) -> SdfUnion {
+        SdfUnion { a, b }
+    }
+}
+
+impl Sdf for SdfUnion {
+    fn distance(&self, p: &Point3) -> f64 {
+        self.a.distance(p).min(self.b.distance(p))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::surrounding(&self.a.bounding_box(), &self.b.bounding_box())
+    }
+}
+
+pub struct SdfIntersection {
+    a: Box<dyn Sdf>,
+    b: Box<dyn Sdf>,
+}
+
+impl SdfIntersection {
+    pub fn new(a: Box<dyn Sdf>, b: Box<dyn Sdf>) -> SdfIntersection {
+        SdfIntersection { a, b }
+    }
+}
+
+impl Sdf for SdfIntersection {
+    fn distance(&self, p: &Point3) -> f64 {
+        self.a.distance(p).max(self.b.distance(p))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::overlap(&self.a.bounding_box(), &self.b.bounding_box())
+    }
+}
+
+// Union that blends the two surfaces together within a distance of about `k`
+pub struct SdfSmoothUnion {
+    a: Box<dyn Sdf>,
+    b: Box<dyn Sdf>,
+    k: f64,
+}
+
+impl SdfSmoothUnion {
+    pub fn new(a: Box<dyn Sdf>, b: Box<dyn Sdf>, k: f64) -> SdfSmoothUnion {
+        SdfSmoothUnion { a, b, k }
+    }
+}
+
+impl Sdf for SdfSmoothUnion {
+    fn distance(&self, p: &Point3) -> f64 {
+        let d1 = self.a.distance(p);
+        let d2 = self.b.distance(p);
+        if self.k <= 0.0 {
+            return d1.min(d2);
+        }
+
+        let h = (0.5 + 0.5 * (d2 - d1) / self.k).clamp(0.0, 1.0);
+        d2 + (d1 - d2) * h - self.k * h * (1.0 - h)
+    }
+
+    // The blend bulges out by at most k / 4 where the surfaces meet
+    fn bounding_box(&self) -> Aabb {
+        let bbox = Aabb::surrounding(&self.a.bounding_box(), &self.b.bounding_box());
+        let bulge = self.k.max(0.0) / 4.0;
+        let pad = Vec3::with_values(bulge, bulge, bulge);
+        Aabb::from_points(
+            Point3::with_values(bbox.x.min, bbox.y.min, bbox.z.min) - pad,
+            Point3::with_values(bbox.x.max, bbox.y.max, bbox.z.max) + pad,
+        )
+    }
+}
+
+// `a` with `b` carved out of it
+pub struct SdfSubtraction {
+    a: Box<dyn Sdf>,
+    b: Box<dyn Sdf>,
+}
+
+impl SdfSubtraction {
+    pub fn new(a: Box<dyn Sdf>, b: Box<dyn Sdf>) -> SdfSubtraction {
+        SdfSubtraction { a, b }
+    }
+}
+
+impl Sdf for SdfSubtraction {
+    fn distance(&self, p: &Point3) -> f64 {
+        self.a.distance(p).max(-self.b.distance(p))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.a.bounding_box()
+    }
+}
+
+// Copies of a shape centered on the origin, repeated every `period` along each axis. Axes with a
+// period of zero are not repeated. The shape should fit inside one cell.
+pub struct SdfRepetition {
+    sdf: Box<dyn Sdf>,
+    period: Vec3,
+}
+
+impl SdfRepetition {
+    pub fn new(sdf: Box<dyn Sdf>, period: Vec3) -> SdfRepetition {
+        SdfRepetition { sdf, period }
+    }
+}
+
+impl Sdf for SdfRepetition {
+    fn distance(&self, p: &Point3) -> f64 {
+        let mut q = *p;
+        for axis in 0..3 {
+            let period = self.period[axis];
+            if period > 0.0 {
+                q[axis] -= period * (q[axis] / period).round();
+            }
+        }
+
+        self.sdf.distance(&q)
+    }
+
+    // Unbounded along the repeated axes
+    fn bounding_box(&self) -> Aabb {
+        let mut bbox = self.sdf.bounding_box();
+        for axis in 0..3 {
+            if self.period[axis] > 0.0 {
+                match axis {
+                    0 => bbox.x = Interval::UNIVERSE,
+                    1 => bbox.y = Interval::UNIVERSE,
+                    _ => bbox.z = Interval::UNIVERSE,
+                }
+            }
+        }
+
+        bbox
+    }
+}
+
+// Twists a shape around the y axis by `rate` radians per unit of height. This stretches
+// distances, so lower the step scale the faster it twists.
+pub struct SdfTwist {
+    sdf: Box<dyn Sdf>,
+    rate: f64,
+}
+
+impl SdfTwist {
+    pub fn new(sdf: Box<dyn Sdf>, rate: f64) -> SdfTwist {
+        SdfTwist { sdf, rate }
+    }
+}
+
+impl Sdf for SdfTwist {
+    fn distance(&self, p: &Point3) -> f64 {
+        let (s, c) = (self.rate * p.y()).sin_cos();
+        let q = Point3::with_values(c * p.x() - s * p.z(), p.y(), s * p.x() + c * p.z());
+
+        self.sdf.distance(&q)
+    }
+
+    // Any rotation around the y axis stays within the circle through the farthest corner
+    fn bounding_box(&self) -> Aabb {
+        let bbox = self.sdf.bounding_box();
+        let x = bbox.x.min.abs().max(bbox.x.max.abs());
+        let z = bbox.z.min.abs().max(bbox.z.max.abs());
+        let radius = (x * x + z * z).sqrt();
+
+        Aabb::with_values(
+            Interval::with_values(-radius, radius),
+            bbox.y,
+            Interval::with_values(-radius, radius),
+        )
+    }
+}
+
+const MAX_STEPS: usize = 1000;
+// Surfaces closer than this are considered hit, and the smallest step taken towards them
+const HIT_DISTANCE: f64 = 1e-5;
+
+// Renders the zero level set of a signed distance function by sphere tracing: the ray advances by
+// the distance to the closest surface, which can't be stepped through, until that distance is
+// below HIT_DISTANCE or changes sign. A sign change is refined by bisection.
+pub struct SdfObject {
+    sdf: Box<dyn Sdf>,
+    // Fraction of the distance stepped at a time, below one for distance estimates that
+    // overshoot
+    step_scale: f64,
+    mat: Arc<dyn Material>,
+    bbox: Aabb,
+}
+
+impl SdfObject {
+    pub fn new(sdf: Box<dyn Sdf>, mat: Arc<dyn Material>) -> SdfObject {
+        SdfObject::with_step_scale(sdf, 1.0, mat)
+    }
+
+    pub fn with_step_scale(
+        sdf: Box<dyn Sdf>,
+        step_scale: f64,
+        mat: Arc<dyn Material>,
+    ) -> SdfObject {
+        let bbox = sdf.bounding_box();
+
+        SdfObject {
+            sdf,
+            step_scale: step_scale.clamp(0.01, 1.0),
+            mat,
+            bbox,
+        }
+    }
+
+    // Gradient of the distance by central differences on a tetrahedron
+    fn normal(&self, p: &Point3) -> Vec3 {
+        let h = 1e-5;
+        let k0 = Vec3::with_values(1.0, -1.0, -1.0);
+        let k1 = Vec3::with_values(-1.0, -1.0, 1.0);
+        let k2 = Vec3::with_values(-1.0, 1.0, -1.0);
+        let k3 = Vec3::with_values(1.0, 1.0, 1.0);
+
+        let gradient = self.sdf.distance(&(*p + h * k0)) * k0
+            + self.sdf.distance(&(*p + h * k1)) * k1
+            + self.sdf.distance(&(*p + h * k2)) * k2
+            + self.sdf.distance(&(*p + h * k3)) * k3;
+
+        if gradient.near_zero() {
+            return Vec3::with_values(0.0, 1.0, 0.0);
+        }
+
+        gradient.unit_vector()
+    }
+
+    fn record_hit(&self, r: &Ray, t: f64, rec: &mut HitRecord) -> bool {
+        rec.t = t;
+        rec.p = r.at(t);
+        let outward_normal = self.normal(&rec.p);
+        rec.set_face_normal(r, &outward_normal);
+
+        // Spherical mapping of the normal, the surface has no parameterization of its own
+        rec.u = (-outward_normal.z()).atan2(outward_normal.x()) / (2.0 * PI) + 0.5;
+        rec.v = (-outward_normal.y()).clamp(-1.0, 1.0).acos() / PI;
+        rec.dpdu = Vec3::new();
+        rec.dpdv = Vec3::new();
+        rec.mat = self.mat.clone();
+
+        true
+    }
+}
+
+impl Hittable for SdfObject {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let span = match self.bbox.clip(r, ray_t) {
+            Some(span) => span,
+            None => return false,
+        };
+
+        let direction_length = r.direction().length();
+        let mut t = span.min;
+        let mut distance = self.sdf.distance(&r.at(t));
+        // Which side of the surface the ray starts on
+        let side = if distance < 0.0 { -1.0 } else { 1.0 };
+
+        for _ in 0..MAX_STEPS {
+            // Distance estimates of fractals approach zero without changing sign
+            if distance.abs() < HIT_DISTANCE {
+                return self.record_hit(r, t, rec);
+            }
+
+            // The last step stops at the end of the span, where the surface may lie on the box
+            let step = (self.step_scale * distance.abs()).max(HIT_DISTANCE) / direction_length;
+            let next_t = (t + step).min(span.max);
+
+            let next_distance = self.sdf.distance(&r.at(next_t));
+            if side * next_distance > 0.0 {
+                if next_t >= span.max {
+                    return false;
+                }
+                t = next_t;
+                distance = next_distance;
+                continue;
+            }
+
+            // Crossed the surface between t and next_t
+            let (mut lo, mut hi) = (t, next_t);
+            for _ in 0..40 {
+                let mid = 0.5 * (lo + hi);
+                if side * self.sdf.distance(&r.at(mid)) > 0.0 {
+                    lo = mid;
+                } else {
+                    hi = mid;
+                }
+            }
+
+            return self.record_hit(r, hi, rec);
+        }
+
+        return false;
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{color::Color, hittable::testing::hit, material::Lambertian};
+
+    fn point(x: f64, y: f64, z: f64) -> Point3 {
+        Point3::with_values(x, y, z)
+    }
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Color::with_values(0.5, 0.5, 0.5)))
+    }
+
+    fn object(sdf: impl Sdf + 'static) -> SdfObject {
+        SdfObject::new(Box::new(sdf), material())
+    }
+
+    fn assert_distance(sdf: &dyn Sdf, p: Point3, expected: f64) {
+        let d = sdf.distance(&p);
+        assert!(
+            (d - expected).abs() < 1e-9,
+            "{} at {}, expected {}",
+            d,
+            p,
+            expected
+        );
+    }
+
+    #[test]
+    fn primitive_distances() {
+        let sphere = SdfSphere::new(point(1.0, 0.0, 0.0), 0.5);
+        assert_distance(&sphere, point(1.0, 2.0, 0.0), 1.5);
+        assert_distance(&sphere, point(1.0, 0.0, 0.0), -0.5);
+
+        let rounded = SdfRoundedBox::new(Point3::new(), Vec3::with_values(1.0, 2.0, 3.0), 0.25);
+        assert_distance(&rounded, point(3.0, 0.0, 0.0), 2.0);
+        assert_distance(&rounded, point(0.0, 0.0, 2.5), -0.5);
+        // Past a corner the distance is to the rounding sphere, not the sharp corner
+        let corner = point(0.75, 1.75, 2.75);
+        let p = corner + Vec3::with_values(1.0, 1.0, 1.0);
+        assert_distance(&rounded, p, (p - corner).length() - 0.25);
+
+        let torus = SdfTorus::new(point(0.0, 1.0, 0.0), 2.0, 0.5);
+        assert_distance(&torus, point(0.0, 1.0, 2.0), -0.5);
+        assert_distance(&torus, point(0.0, 1.0, 0.0), 1.5);
+        assert_distance(&torus, point(-2.0, 2.0, 0.0), 0.5);
+    }
+
+    #[test]
+    fn smooth_union_blends_only_near_both_surfaces() {
+        let spheres = || {
+            (
+                Box::new(SdfSphere::new(point(-1.0, 0.0, 0.0), 0.5)),
+                Box::new(SdfSphere::new(point(1.0, 0.0, 0.0), 0.5)),
+            )
+        };
+        let (a, b) = spheres();
+        let sharp = SdfSmoothUnion::new(a, b, 0.0);
+        let (a, b) = spheres();
+        let smooth = SdfSmoothUnion::new(a, b, 0.4);
+
+        // Halfway between the spheres both are 0.5 away, and the blend pulls in by k / 4
+        assert_distance(&sharp, Point3::new(), 0.5);
+        assert_distance(&smooth, Point3::new(), 0.4);
+        // Where one surface is more than k closer, it's the plain union
+        assert_distance(&smooth, point(-1.0, 0.0, 0.0), -0.5);
+        assert_distance(&smooth, point(-1.0, 1.0, 0.0), 0.5);
+
+        let bbox = smooth.bounding_box();
+        assert_eq!((bbox.x.min, bbox.x.max), (-1.6, 1.6));
+        assert_eq!((bbox.y.min, bbox.y.max), (-0.6, 0.6));
+    }
+
+    #[test]
+    fn subtraction_carves_out_the_second_shape() {
+        let carved = SdfSubtraction::new(
+            Box::new(SdfSphere::new(Point3::new(), 1.0)),
+            Box::new(SdfSphere::new(point(1.0, 0.0, 0.0), 0.5)),
+        );
+
+        assert_distance(&carved, point(-0.5, 0.0, 0.0), -0.5);
+        // Inside the carved out sphere is now outside, by the distance to its surface
+        assert_distance(&carved, point(0.9, 0.0, 0.0), 0.4);
+        assert_distance(&carved, point(-2.0, 0.0, 0.0), 1.0);
+    }
+
+    #[test]
+    fn sphere_tracing_finds_the_surface() {
+        let sphere = object(SdfSphere::new(point(0.0, 0.0, -2.0), 0.5));
+
+        let rec = hit(&sphere, Point3::new(), Vec3::with_values(0.0, 0.0, -2.0)).unwrap();
+        assert!((rec.t - 0.75).abs() < 1e-6, "t = {}", rec.t);
+        assert!(rec.front_face);
+        assert!((rec.normal - Vec3::with_values(0.0, 0.0, 1.0)).length() < 1e-6);
+
+        // From inside, the ray leaves through the far side
+        let rec = hit(
+            &sphere,
+            point(0.0, 0.0, -2.0),
+            Vec3::with_values(0.0, 0.0, -1.0),
+        )
+        .unwrap();
+        assert!((rec.p - point(0.0, 0.0, -2.5)).length() < 1e-6, "{}", rec.p);
+        assert!(!rec.front_face);
+
+        assert!(hit(&sphere, Point3::new(), Vec3::with_values(0.0, 1.0, -2.0)).is_none());
+
+        // Surfaces past the end of the ray aren't hit
+        let mut rec = HitRecord::new();
+        let ray = Ray::with_values(Point3::new(), Vec3::with_values(0.0, 0.0, -1.0));
+        assert!(!sphere.hit(&ray, Interval::with_values(0.001, 1.4), &mut rec));
+        assert!(sphere.hit(&ray, Interval::with_values(0.001, 1.6), &mut rec));
+    }
+
+    #[test]
+    fn sphere_tracing_hits_a_sharp_edge() {
+        let cube = object(SdfRoundedBox::new(
+            Point3::new(),
+            Vec3::with_values(1.0, 1.0, 1.0),
+            0.0,
+        ));
+
+        let diagonal = Vec3::with_values(1.0, -1.0, 0.0);
+        let rec = hit(&cube, point(-3.0, 3.0, 0.0), diagonal).unwrap();
+        assert!((rec.p - point(-1.0, 1.0, 0.0)).length() < 1e-6, "{}", rec.p);
+        assert!(hit(&cube, point(-3.0, 3.0, 1.1), diagonal).is_none());
+
+        // Leaving from inside, through a face that is also the bounding box
+        let rec = hit(&cube, Point3::new(), Vec3::with_values(0.0, 0.0, 1.0)).unwrap();
+        assert!((rec.t - 1.0).abs() < 1e-6, "t = {}", rec.t);
+        assert!(!rec.front_face);
+    }
+
+    #[test]
+    fn mandelbulb_is_inside_its_escape_radius() {
+        for power in [1.5, 2.0, 3.0, 8.0] {
+            let bulb = SdfMandelbulb::new(power, 20);
+            let radius = bulb.escape_radius();
+            let bbox = bulb.bounding_box();
+            assert_eq!((bbox.x.min, bbox.x.max), (-radius, radius));
+
+            // Directions spread over the sphere, just outside the radius
+            for i in 0..200 {
+                let y = 1.0 - (i as f64 + 0.5) / 100.0;
+                let around = (1.0 - y * y).sqrt();
+                let phi = i as f64 * 2.399963;
+                let p = 1.001 * radius * point(around * phi.cos(), y, around * phi.sin());
+
+                let d = bulb.distance(&p);
+                assert!(d > 0.0, "power {}: {} at {}", power, d, p);
+            }
+        }
+
+        // The surface of the power 8 bulb is well inside the radius along the x axis
+        let bulb =
+            SdfObject::with_step_scale(Box::new(SdfMandelbulb::new(8.0, 10)), 0.5, material());
+        let rec = hit(
+            &bulb,
+            point(3.0, 0.01, 0.02),
+            Vec3::with_values(-1.0, 0.0, 0.0),
+        )
+        .unwrap();
+        assert!(
+            rec.p.x() > 0.5 && rec.p.x() < 2.0_f64.powf(1.0 / 7.0),
+            "{}",
+            rec.p
+        );
+    }
+}
@@ -0,0 +1,346 @@
+use std::sync::OnceLock;
+
+use crate::{color::Color, ray::Ray};
+
+// Visible range the wavelengths are sampled in, in nanometers
+pub const LAMBDA_MIN: f64 = 380.0;
+pub const LAMBDA_MAX: f64 = 720.0;
+
+// Wavelength at which dispersive materials use their index of refraction outside of spectral
+// rendering, the helium d line that refractive indices are usually quoted at
+pub const LAMBDA_REFERENCE: f64 = 587.56;
+
+// Basis spectra of Smits' RGB to spectrum conversion ("An RGB-to-Spectrum Conversion for
+// Reflectances", 1999), in 10 equal bins from `LAMBDA_MIN` to `LAMBDA_MAX`
+const WHITE: [f64; 10] = [
+    1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000,
+];
+const CYAN: [f64; 10] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000,
+];
+const MAGENTA: [f64; 10] = [
+    1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959,
+];
+const YELLOW: [f64; 10] = [
+    0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const RED: [f64; 10] = [
+    0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const GREEN: [f64; 10] = [
+    0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025,
+];
+const BLUE: [f64; 10] = [
+    1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+// Wavelength density used for sampling, proportional to 1 / cosh^2(a (lambda - center)) to follow
+// the eye's sensitivity (Radziszewski et al. 2009, as used by pbrt)
+const VISIBLE_CENTER: f64 = 538.0;
+const VISIBLE_SCALE: f64 = 0.0072;
+
+// Three wavelengths, in nanometers, that a camera sample carries through the scene, stratified
+// over the visible range from a random first ("hero") wavelength. Radiance along the path is then
+// a `Color` holding the spectrum at each of the three wavelengths.
+#[derive(Clone, Copy)]
+pub struct Wavelengths {
+    lambda: [f64; 3],
+    pdf: [f64; 3],
+    // Only the hero wavelength is still followed, after a wavelength-dependent refraction
+    secondary_terminated: bool,
+}
+
+impl Wavelengths {
+    // Wavelengths for a uniform random number `u` in [0, 1)
+    pub fn sample(u: f64) -> Wavelengths {
+        let tanh = |lambda: f64| (VISIBLE_SCALE * (lambda - VISIBLE_CENTER)).tanh();
+        let (t_min, t_max) = (tanh(LAMBDA_MIN), tanh(LAMBDA_MAX));
+
+        let lambda = [0.0, 1.0, 2.0].map(|i| {
+            let offset = (u + i / 3.0).fract();
+            let t = t_min + offset * (t_max - t_min);
+            (VISIBLE_CENTER + t.atanh() / VISIBLE_SCALE).clamp(LAMBDA_MIN, LAMBDA_MAX)
+        });
+        let pdf = lambda.map(|lambda| {
+            let cosh = (VISIBLE_SCALE * (lambda - VISIBLE_CENTER)).cosh();
+            VISIBLE_SCALE / (cosh * cosh * (t_max - t_min))
+        });
+
+        Wavelengths {
+            lambda,
+            pdf,
+            secondary_terminated: false,
+        }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    pub fn secondary_terminated(&self) -> bool {
+        self.secondary_terminated
+    }
+
+    // The same wavelengths with only the hero one left, for materials that send each
+    // wavelength in a different direction
+    pub fn terminate_secondary(&self) -> Wavelengths {
+        Wavelengths {
+            secondary_terminated: true,
+            ..*self
+        }
+    }
+
+    // Spectrum of an RGB reflectance or emission at the three wavelengths
+    pub fn sample_color(&self, color: &Color) -> Color {
+        self.evaluate(|lambda| rgb_to_spectrum(color, lambda))
+    }
+
+    // Spectrum `f` at the three wavelengths
+    pub fn evaluate(&self, f: impl Fn(f64) -> f64) -> Color {
+        let samples = self.lambda.map(f);
+        Color::with_values(samples[0], samples[1], samples[2])
+    }
+
+    // Linear RGB color of the spectrum `samples` taken at the three wavelengths, as an estimate
+    // of the whole spectrum. A constant spectrum of 1 gives white on average. Single estimates
+    // are mostly out of the sRGB gamut, with negative channels, which must be kept for their
+    // average to come out right: see `clip_to_gamut`.
+    pub fn to_rgb(&self, samples: &Color) -> Color {
+        // Monte Carlo estimate averaged over the wavelengths still followed
+        let followed = if self.secondary_terminated { 1 } else { 3 };
+
+        let mut xyz = Color::new();
+        for i in 0..followed {
+            xyz += samples[i] / self.pdf[i] * color_matching(self.lambda[i]);
+        }
+        xyz /= followed as f64;
+
+        xyz_to_rgb(&xyz) / *white_rgb()
+    }
+}
+
+// Averaged spectral estimate `rgb` with the negative channels of colors more saturated than sRGB
+// can show clipped to zero, before it reaches the image writers
+pub fn clip_to_gamut(rgb: &Color) -> Color {
+    Color::with_values(rgb.x().max(0.0), rgb.y().max(0.0), rgb.z().max(0.0))
+}
+
+// `color` as a spectrum at the wavelengths of `r`, when it carries any
+pub fn in_spectrum(r: &Ray, color: Color) -> Color {
+    match r.wavelengths() {
+        Some(wavelengths) => wavelengths.sample_color(&color),
+        None => color,
+    }
+}
+
+// Smits' conversion of an RGB color to a smooth spectrum, evaluated at `lambda`: the smallest
+// channel is made of white, the next of the cyan, magenta or yellow spectrum that combines both
+// remaining channels, and the rest of the primary spectrum of the largest channel.
+pub fn rgb_to_spectrum(color: &Color, lambda: f64) -> f64 {
+    let bin = (((lambda - LAMBDA_MIN) / (LAMBDA_MAX - LAMBDA_MIN) * 10.0) as usize).min(9);
+    let (r, g, b) = (color.x(), color.y(), color.z());
+
+    if r <= g && r <= b {
+        let white = r * WHITE[bin];
+        if g <= b {
+            white + (g - r) * CYAN[bin] + (b - g) * BLUE[bin]
+        } else {
+            white + (b - r) * CYAN[bin] + (g - b) * GREEN[bin]
+        }
+    } else if g <= r && g <= b {
+        let white = g * WHITE[bin];
+        if r <= b {
+            white + (r - g) * MAGENTA[bin] + (b - r) * BLUE[bin]
+        } else {
+            white + (b - g) * MAGENTA[bin] + (r - b) * RED[bin]
+        }
+    } else {
+        let white = b * WHITE[bin];
+        if r <= g {
+            white + (r - b) * YELLOW[bin] + (g - r) * GREEN[bin]
+        } else {
+            white + (g - b) * YELLOW[bin] + (r - g) * RED[bin]
+        }
+    }
+}
+
+// CIE 1931 standard observer color matching functions at `lambda`, as X, Y and Z, using the
+// piecewise Gaussian fit of Wyman, Sloan and Shirley ("Simple Analytic Approximations to the CIE
+// XYZ Color Matching Functions", 2013)
+pub fn color_matching(lambda: f64) -> Color {
+    let g = |mu: f64, sigma_below: f64, sigma_above: f64| {
+        let sigma = if lambda < mu {
+            sigma_below
+        } else {
+            sigma_above
+        };
+        let t = (lambda - mu) / sigma;
+        (-0.5 * t * t).exp()
+    };
+
+    Color::with_values(
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    )
+}
+
+// CIE XYZ to linear sRGB
+pub fn xyz_to_rgb(xyz: &Color) -> Color {
+    let (x, y, z) = (xyz.x(), xyz.y(), xyz.z());
+    Color::with_values(
+        3.240479 * x - 1.537150 * y - 0.498535 * z,
+        -0.969256 * x + 1.875991 * y + 0.041556 * z,
+        0.055648 * x - 0.204043 * y + 1.057311 * z,
+    )
+}
+
+// Wavelengths `spectrum_to_rgb` evaluates spectra at, evenly spaced over the visible range.
+// Interference of films up to a few micrometers thick stays within 1e-4 of a fine integration.
+const RGB_STEPS: usize = 32;
+
+// Linear RGB color of light with the spectrum `f`, for spectra that vary with the wavelength in
+// ways uplifted RGB colors can't, such as interference. A constant spectrum of 1 gives white.
+pub fn spectrum_to_rgb(f: impl Fn(f64) -> f64) -> Color {
+    let weights = rgb_weights();
+    let step = (LAMBDA_MAX - LAMBDA_MIN) / RGB_STEPS as f64;
+
+    let mut rgb = Color::new();
+    for (i, weight) in weights.iter().enumerate() {
+        rgb += f(LAMBDA_MIN + (i as f64 + 0.5) * step) * *weight;
+    }
+
+    rgb
+}
+
+// RGB contribution of each wavelength of `spectrum_to_rgb`, normalized to sum to white
+fn rgb_weights() -> &'static [Color; RGB_STEPS] {
+    static RGB_WEIGHTS: OnceLock<[Color; RGB_STEPS]> = OnceLock::new();
+
+    RGB_WEIGHTS.get_or_init(|| {
+        let step = (LAMBDA_MAX - LAMBDA_MIN) / RGB_STEPS as f64;
+        let mut weights = [Color::new(); RGB_STEPS];
+        let mut total = Color::new();
+        for (i, weight) in weights.iter_mut().enumerate() {
+            *weight = xyz_to_rgb(&color_matching(LAMBDA_MIN + (i as f64 + 0.5) * step));
+            total += *weight;
+        }
+
+        weights.map(|weight| weight / total)
+    })
+}
+
+// RGB of a constant spectrum of 1 over the sampled range, which `to_rgb` divides by so that
+// white surfaces under white light stay white
+fn white_rgb() -> &'static Color {
+    static WHITE_RGB: OnceLock<Color> = OnceLock::new();
+
+    WHITE_RGB.get_or_init(|| {
+        let steps = 1000;
+        let step = (LAMBDA_MAX - LAMBDA_MIN) / steps as f64;
+        let mut xyz = Color::new();
+        for i in 0..steps {
+            xyz += color_matching(LAMBDA_MIN + (i as f64 + 0.5) * step) * step;
+        }
+
+        xyz_to_rgb(&xyz)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Mean of `to_rgb` over evenly spread wavelength samples of the uplifted `color`
+    fn round_trip(color: &Color) -> Color {
+        let n = 3000;
+        let mut rgb = Color::new();
+        for k in 0..n {
+            let wavelengths = Wavelengths::sample((k as f64 + 0.5) / n as f64);
+            rgb += wavelengths.to_rgb(&wavelengths.sample_color(color));
+        }
+
+        rgb / n as f64
+    }
+
+    fn assert_color_near(a: &Color, b: &Color, tolerance: f64) {
+        for channel in 0..3 {
+            assert!(
+                (a[channel] - b[channel]).abs() < tolerance,
+                "{} != {}",
+                a,
+                b
+            );
+        }
+    }
+
+    #[test]
+    fn white_round_trips_to_white() {
+        let white = Color::with_values(1.0, 1.0, 1.0);
+        for lambda in [400.0, 500.0, 600.0, 700.0] {
+            assert!((rgb_to_spectrum(&white, lambda) - 1.0).abs() < 1e-3);
+        }
+
+        assert_color_near(&round_trip(&white), &white, 0.01);
+        assert_color_near(&spectrum_to_rgb(|_| 1.0), &white, 1e-9);
+    }
+
+    #[test]
+    fn saturated_primaries_round_trip() {
+        for primary in [
+            Color::with_values(1.0, 0.0, 0.0),
+            Color::with_values(0.0, 1.0, 0.0),
+            Color::with_values(0.0, 0.0, 1.0),
+        ] {
+            assert_color_near(&round_trip(&primary), &primary, 0.02);
+        }
+
+        // Mixtures keep their hue, and scale with the color
+        let orange = Color::with_values(0.8, 0.4, 0.1);
+        assert_color_near(&round_trip(&orange), &orange, 0.02);
+        assert_color_near(&round_trip(&(0.5 * orange)), &(0.5 * orange), 0.01);
+    }
+
+    #[test]
+    fn single_estimates_leave_the_gamut_until_averaged() {
+        // Light of one wavelength is more saturated than any sRGB color
+        let hero_only = Wavelengths::sample(0.3).terminate_secondary();
+        let rgb = hero_only.to_rgb(&Color::with_values(1.0, 1.0, 1.0));
+        assert!(rgb.x() < 0.0 || rgb.y() < 0.0 || rgb.z() < 0.0, "{}", rgb);
+
+        let clipped = clip_to_gamut(&rgb);
+        for channel in 0..3 {
+            assert_eq!(clipped[channel], rgb[channel].max(0.0));
+        }
+    }
+
+    #[test]
+    fn color_matching_fit_follows_the_cie_tables() {
+        // CIE 1931 2 degree observer at a few wavelengths
+        let table = [
+            (450.0, [0.3362, 0.0380, 1.7721]),
+            (555.0, [0.5121, 1.0000, 0.0057]),
+            (600.0, [1.0622, 0.6310, 0.0008]),
+        ];
+        for (lambda, xyz) in table {
+            let fit = color_matching(lambda);
+            assert_color_near(&fit, &Color::with_values(xyz[0], xyz[1], xyz[2]), 0.03);
+        }
+    }
+
+    #[test]
+    fn wavelengths_cover_the_visible_range_by_their_pdf() {
+        // Importance sampled, so the mean of 1 / pdf is the width of the range
+        let n = 3000;
+        let mut width = 0.0;
+        for k in 0..n {
+            let wavelengths = Wavelengths::sample((k as f64 + 0.5) / n as f64);
+            for i in 0..3 {
+                assert!((LAMBDA_MIN..=LAMBDA_MAX).contains(&wavelengths.lambda[i]));
+                width += 1.0 / wavelengths.pdf[i] / (3 * n) as f64;
+            }
+        }
+
+        assert!((width - (LAMBDA_MAX - LAMBDA_MIN)).abs() < 0.5, "{}", width);
+    }
+}
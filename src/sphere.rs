@@ -1,21 +1,72 @@
-use std::sync::Arc;
+use std::{f64::consts::PI, sync::Arc};
 
-use crate::{hittable::Hittable, interval::Interval, material::Material, vec3::Vec3};
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable},
+    interval::Interval,
+    material::Material,
+    onb::Onb,
+    ray::Ray,
+    sampler::Sampler,
+    vec3::{Point3, Vec3},
+};
 
 pub struct Sphere {
-    center: Vec3,
+    // Path of the center, from its position at time 0 to the one at time 1
+    center: Ray,
     radius: f64,
     mat: Arc<dyn Material>,
+    bbox: Aabb,
 }
 
 impl Sphere {
     pub fn new(center: Vec3, radius: f64, mat: Arc<dyn Material>) -> Self {
+        Sphere::moving(center, center, radius, mat)
+    }
+
+    // Sphere moving linearly from `center1` at time 0 to `center2` at time 1. It rests at those
+    // positions before and after, so the bounding box covers every ray time.
+    pub fn moving(center1: Vec3, center2: Vec3, radius: f64, mat: Arc<dyn Material>) -> Self {
+        let radius = f64::max(0.0, radius);
+        let rvec = Vec3::with_values(radius, radius, radius);
+        let box1 = Aabb::from_points(center1 - rvec, center1 + rvec);
+        let box2 = Aabb::from_points(center2 - rvec, center2 + rvec);
+
         Sphere {
-            center,
-            radius: f64::max(0.0, radius),
+            center: Ray::with_values(center1, center2 - center1),
+            radius,
             mat,
+            bbox: Aabb::surrounding(&box1, &box2),
         }
     }
+
+    fn center_at(&self, time: f64) -> Point3 {
+        self.center.at(time.clamp(0.0, 1.0))
+    }
+
+    // p: a given point on the sphere of radius one, centered at the origin.
+    // u: returned value [0,1] of angle around the Y axis from X=-1.
+    // v: returned value [0,1] of angle from Y=-1 to Y=+1.
+    fn get_sphere_uv(p: &Point3) -> (f64, f64) {
+        let theta = (-p.y()).acos();
+        let phi = (-p.z()).atan2(p.x()) + PI;
+
+        (phi / (2.0 * PI), theta / PI)
+    }
+
+    // Uniform direction inside the cone of directions subtended by a sphere of the given radius
+    // whose center lies at squared distance `distance_squared` along +z.
+    fn random_to_sphere(radius: f64, distance_squared: f64, sampler: &mut Sampler) -> Vec3 {
+        let r1 = sampler.random_f64();
+        let r2 = sampler.random_f64();
+        let z = 1.0 + r2 * ((1.0 - radius * radius / distance_squared).sqrt() - 1.0);
+
+        let phi = 2.0 * PI * r1;
+        let x = phi.cos() * (1.0 - z * z).sqrt();
+        let y = phi.sin() * (1.0 - z * z).sqrt();
+
+        Vec3::with_values(x, y, z)
+    }
 }
 
 impl Hittable for Sphere {
@@ -25,7 +76,8 @@ impl Hittable for Sphere {
         ray_t: Interval,
         rec: &mut crate::hittable::HitRecord,
     ) -> bool {
-        let oc: Vec3 = self.center - r.origin();
+        let current_center = self.center_at(r.time());
+        let oc: Vec3 = current_center - r.origin();
         let a = r.direction().length_squared();
         let h = r.direction().dot(&oc);
         let c = oc.length_squared() - self.radius * self.radius;
@@ -48,10 +100,130 @@ impl Hittable for Sphere {
 
         rec.t = root;
         rec.p = r.at(rec.t);
-        let outward_normal = (rec.p - self.center) / self.radius;
+        let outward_normal = (rec.p - current_center) / self.radius;
         rec.set_face_normal(r, &outward_normal);
+        (rec.u, rec.v) = Sphere::get_sphere_uv(&outward_normal);
+        (rec.dpdu, rec.dpdv) = sphere_derivatives(&outward_normal, self.radius);
         rec.mat = self.mat.clone();
 
         return true;
     }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    // Directions are sampled uniformly over the cone the sphere subtends, which is only defined
+    // when the origin lies outside of it. Light sampling has no ray time, so moving spheres are
+    // sampled at their position at time 0.
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let distance_squared = (self.center.origin() - *origin).length_squared();
+        if distance_squared <= self.radius * self.radius {
+            return 0.0;
+        }
+
+        let mut rec = HitRecord::new();
+        let ray = Ray::with_values(*origin, *direction);
+        if !self.hit(&ray, Interval::with_values(0.001, f64::INFINITY), &mut rec) {
+            return 0.0;
+        }
+
+        let cos_theta_max = (1.0 - self.radius * self.radius / distance_squared).sqrt();
+        let solid_angle = 2.0 * PI * (1.0 - cos_theta_max);
+
+        1.0 / solid_angle
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut Sampler) -> Vec3 {
+        let direction = self.center.origin() - *origin;
+        let distance_squared = direction.length_squared();
+        if distance_squared <= self.radius * self.radius {
+            return direction;
+        }
+
+        let uvw = Onb::new(&direction);
+        uvw.transform(&Sphere::random_to_sphere(
+            self.radius,
+            distance_squared,
+            sampler,
+        ))
+    }
+}
+
+// Derivatives along u and v of the mapping of `get_sphere_uv`, at the point `p` of the unit
+// sphere scaled by `radius`
+pub fn sphere_derivatives(p: &Point3, radius: f64) -> (Vec3, Vec3) {
+    let sin_theta = (p.x() * p.x() + p.z() * p.z()).sqrt();
+    if sin_theta < 1e-8 {
+        // The poles, where all u meet
+        return (Vec3::new(), Vec3::new());
+    }
+
+    let dpdu = 2.0 * PI * radius * Vec3::with_values(p.z(), 0.0, -p.x());
+    let dpdv = PI
+        * radius
+        * Vec3::with_values(
+            -p.x() * p.y() / sin_theta,
+            sin_theta,
+            -p.y() * p.z() / sin_theta,
+        );
+
+    (dpdu, dpdv)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{color::Color, material::Lambertian};
+
+    fn hit_at(sphere: &Sphere, origin: Point3, time: f64) -> HitRecord {
+        let mut rec = HitRecord::new();
+        let ray = Ray::with_time(origin, Vec3::with_values(0.0, 0.0, -1.0), time);
+        assert!(sphere.hit(&ray, Interval::with_values(0.001, f64::INFINITY), &mut rec));
+        rec
+    }
+
+    fn moving_sphere() -> Sphere {
+        let mat = Arc::new(Lambertian::new(Color::with_values(0.5, 0.5, 0.5)));
+        Sphere::moving(
+            Point3::with_values(0.0, 0.0, -5.0),
+            Point3::with_values(1.0, 0.5, -6.0),
+            1.0,
+            mat,
+        )
+    }
+
+    #[test]
+    fn moving_sphere_follows_its_center() {
+        let sphere = moving_sphere();
+        let offset = Vec3::with_values(1.0, 0.5, -1.0);
+        let origin = Point3::with_values(0.2, 0.3, 0.0);
+
+        // The same ray relative to the center hits the same spot on the sphere
+        let start = hit_at(&sphere, origin, 0.0);
+        let end = hit_at(&sphere, origin + Vec3::with_values(1.0, 0.5, 0.0), 1.0);
+        assert!(
+            (end.p - start.p - offset).length() < 1e-9,
+            "{}",
+            end.p - start.p
+        );
+        assert!((end.normal - start.normal).length() < 1e-9);
+
+        let halfway = hit_at(&sphere, origin + Vec3::with_values(0.5, 0.25, 0.0), 0.5);
+        assert!((halfway.p - start.p - 0.5 * offset).length() < 1e-9);
+
+        // It rests at its end positions outside of the shutter interval
+        let after = hit_at(&sphere, origin + Vec3::with_values(1.0, 0.5, 0.0), 3.0);
+        assert!((after.p - end.p).length() < 1e-9);
+    }
+
+    #[test]
+    fn bounding_box_covers_the_whole_path() {
+        let bbox = moving_sphere().bounding_box();
+
+        for (axis, min, max) in [(0, -1.0, 2.0), (1, -1.0, 1.5), (2, -7.0, -4.0)] {
+            let interval = bbox.axis_interval(axis);
+            assert_eq!((interval.min, interval.max), (min, max), "axis {}", axis);
+        }
+    }
 }
@@ -0,0 +1,278 @@
+use crate::{
+    color::Color,
+    hittable::{HitRecord, Hittable},
+    interval::Interval,
+    material::{dielectric_direction, Material},
+    ray::Ray,
+    sampler::Sampler,
+    vec3::Vec3,
+};
+
+// Scattering events followed inside the object before the light is taken as absorbed
+const MAX_STEPS: usize = 1024;
+
+// Translucent material such as skin, wax, marble or milk. Light refracting into the object takes
+// a random walk through it, scattering off particles after exponentially distributed distances,
+// until it refracts out again, possibly on the far side of thin parts. The walk traces the scene
+// to find where the surface is, so the object has to be closed.
+//
+// Without the scene, as the base of a layered material, it falls back to diffuse reflection of
+// its albedo.
+pub struct Subsurface {
+    // Chance of scattering rather than being absorbed at each event, per color channel
+    albedo: Color,
+    // Mean distance between events, per color channel
+    mean_free_path: Color,
+    // Refractive index of the surface
+    pub ior: f64,
+}
+
+impl Subsurface {
+    pub fn new(albedo: Color, mean_free_path: Color) -> Self {
+        Subsurface {
+            albedo,
+            mean_free_path,
+            ior: 1.4,
+        }
+    }
+
+    // Extinction coefficient per channel
+    fn sigma_t(&self) -> Color {
+        Color::with_values(
+            1.0 / self.mean_free_path.x().max(1e-6),
+            1.0 / self.mean_free_path.y().max(1e-6),
+            1.0 / self.mean_free_path.z().max(1e-6),
+        )
+    }
+}
+
+impl Material for Subsurface {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+        sampler: &mut Sampler,
+    ) -> bool {
+        let mut scatter_direction = rec.normal + Vec3::random_unit_vector(sampler);
+        if scatter_direction.near_zero() {
+            scatter_direction = rec.normal;
+        }
+
+        *scattered = Ray::with_time(rec.p, scatter_direction, ray_in.time());
+        *attenuation = self.albedo;
+
+        return true;
+    }
+
+    fn scatter_in_world(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        world: &dyn Hittable,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+        sampler: &mut Sampler,
+    ) -> bool {
+        // Reflect off the surface or refract into the object
+        let direction = dielectric_direction(ray_in, rec, self.ior, sampler);
+        let mut ray = Ray::with_time(rec.p, direction.unit_vector(), ray_in.time());
+        ray.set_wavelengths(ray_in.wavelengths());
+        if direction.dot(&rec.normal) > 0.0 {
+            *attenuation = Color::with_values(1.0, 1.0, 1.0);
+            *scattered = ray;
+            return true;
+        }
+
+        let sigma_t = self.sigma_t();
+        let mut weight = Color::with_values(1.0, 1.0, 1.0);
+
+        for _ in 0..MAX_STEPS {
+            let mut surface = HitRecord::new();
+            if !world.hit(
+                &ray,
+                Interval::with_values(0.001, f64::INFINITY),
+                &mut surface,
+            ) {
+                // The object isn't closed, the light leaves through the gap
+                *attenuation = weight;
+                *scattered = ray;
+                return true;
+            }
+
+            // Flight distance sampled with the extinction of a channel picked in proportion to
+            // its weight, and weighted against the density of picking any of them
+            let total = channel_sum(&weight);
+            if total <= 0.0 {
+                return false;
+            }
+            let chances = weight / total;
+            let u = sampler.random_f64();
+            let channel = if u < chances.x() {
+                0
+            } else if u < chances.x() + chances.y() {
+                1
+            } else {
+                2
+            };
+            let distance = -(1.0 - sampler.random_f64()).ln() / sigma_t[channel];
+            let transmittance = Color::with_values(
+                (-sigma_t.x() * distance.min(surface.t)).exp(),
+                (-sigma_t.y() * distance.min(surface.t)).exp(),
+                (-sigma_t.z() * distance.min(surface.t)).exp(),
+            );
+
+            if distance < surface.t {
+                // Scatter off a particle, equally in all directions
+                let pdf = channel_sum(&(chances * sigma_t * transmittance));
+                weight = weight * self.albedo * sigma_t * transmittance / pdf;
+
+                let mut next = Ray::with_time(
+                    ray.at(distance),
+                    Vec3::random_unit_vector(sampler),
+                    ray.time(),
+                );
+                next.set_wavelengths(ray.wavelengths());
+                ray = next;
+            } else {
+                // Reach the surface, where the light refracts out or reflects back inside
+                let pdf = channel_sum(&(chances * transmittance));
+                weight = weight * transmittance / pdf;
+
+                let direction = dielectric_direction(&ray, &surface, self.ior, sampler);
+                let mut next = Ray::with_time(surface.p, direction.unit_vector(), ray.time());
+                next.set_wavelengths(ray.wavelengths());
+                if direction.dot(&surface.normal) < 0.0 {
+                    *attenuation = weight;
+                    *scattered = next;
+                    return true;
+                }
+                ray = next;
+            }
+        }
+
+        return false;
+    }
+}
+
+fn channel_sum(color: &Color) -> f64 {
+    color.x() + color.y() + color.z()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{quad::make_box, sphere::Sphere, vec3::Point3};
+    use std::sync::Arc;
+
+    const WALKS: usize = 20_000;
+
+    // Walks started by light arriving at `object` along `ray_in`, each giving its weight and the
+    // ray it leaves along, or nothing when the light was absorbed
+    fn walks(
+        object: &dyn Hittable,
+        mat: &Subsurface,
+        ray_in: &Ray,
+        sampler: &mut Sampler,
+    ) -> Vec<Option<(Color, Ray)>> {
+        let mut rec = HitRecord::new();
+        assert!(object.hit(
+            ray_in,
+            Interval::with_values(0.001, f64::INFINITY),
+            &mut rec
+        ));
+
+        (0..WALKS)
+            .map(|_| {
+                let mut attenuation = Color::new();
+                let mut scattered = Ray::new();
+                mat.scatter_in_world(
+                    ray_in,
+                    &rec,
+                    object,
+                    &mut attenuation,
+                    &mut scattered,
+                    sampler,
+                )
+                .then_some((attenuation, scattered))
+            })
+            .collect()
+    }
+
+    // Without absorption a closed object sends out all the light that arrives
+    #[test]
+    fn white_furnace_keeps_all_light_in_a_closed_sphere() {
+        let white = Color::with_values(1.0, 1.0, 1.0);
+        let mat = Arc::new(Subsurface::new(white, Color::with_values(0.2, 0.5, 1.0)));
+        let sphere = Sphere::new(Point3::with_values(0.0, 0.0, 0.0), 1.0, mat.clone());
+        let mut sampler = Sampler::new(11);
+
+        for height in [0.0, 0.6, 0.95] {
+            let ray_in = Ray::with_values(
+                Point3::with_values(0.0, height, 5.0),
+                Vec3::with_values(0.0, 0.0, -1.0),
+            );
+            let mut total = Color::new();
+            for (attenuation, scattered) in walks(&sphere, &mat, &ray_in, &mut sampler)
+                .into_iter()
+                .flatten()
+            {
+                total += attenuation;
+                // Light only ever leaves outwards
+                assert!(scattered.direction().dot(&scattered.origin()) > 0.0);
+            }
+
+            let mean = total / WALKS as f64;
+            for channel in 0..3 {
+                assert!(
+                    (mean[channel] - 1.0).abs() < 0.03,
+                    "light arriving at height {} leaves with {}",
+                    height,
+                    mean
+                );
+            }
+        }
+    }
+
+    // Share of the light arriving straight down on the top of a slab that leaves through its
+    // bottom
+    fn bleed_through(thickness: f64, sampler: &mut Sampler) -> f64 {
+        let mat = Arc::new(Subsurface::new(
+            Color::with_values(0.9, 0.9, 0.9),
+            Color::with_values(1.0, 1.0, 1.0),
+        ));
+        let slab = make_box(
+            Point3::with_values(-50.0, -thickness, -50.0),
+            Point3::with_values(50.0, 0.0, 50.0),
+            mat.clone(),
+        );
+        let ray_in = Ray::with_values(
+            Point3::with_values(0.0, 5.0, 0.0),
+            Vec3::with_values(0.0, -1.0, 0.0),
+        );
+
+        let through: f64 = walks(&slab, &mat, &ray_in, sampler)
+            .into_iter()
+            .flatten()
+            .filter(|(_, scattered)| scattered.direction().y() < 0.0)
+            .map(|(attenuation, _)| channel_sum(&attenuation) / 3.0)
+            .sum();
+
+        through / WALKS as f64
+    }
+
+    #[test]
+    fn light_bleeds_through_thin_slabs() {
+        let mut sampler = Sampler::new(12);
+        let thin = bleed_through(0.05, &mut sampler);
+        let thick = bleed_through(5.0, &mut sampler);
+
+        assert!(thin > 0.5, "{} of the light gets through a thin slab", thin);
+        assert!(
+            thick < 0.1,
+            "{} of the light gets through a thick slab",
+            thick
+        );
+    }
+}
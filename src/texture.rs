@@ -0,0 +1,111 @@
+use std::{path::Path, sync::Arc};
+
+use crate::{
+    color::Color,
+    image::{Image, ImageError},
+    interval::Interval,
+    vec3::Point3,
+};
+
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color;
+}
+
+pub struct SolidColor {
+    albedo: Color,
+}
+
+impl SolidColor {
+    pub fn new(albedo: Color) -> Self {
+        SolidColor { albedo }
+    }
+
+    pub fn with_rgb(red: f64, green: f64, blue: f64) -> Self {
+        SolidColor::new(Color::with_values(red, green, blue))
+    }
+
+    // Constant scalar, for material parameters given as textures
+    pub fn gray(value: f64) -> Self {
+        SolidColor::with_rgb(value, value, value)
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+        self.albedo
+    }
+}
+
+// Solid 3D checker pattern of cubes with side `scale`, alternating between two textures
+pub struct CheckerTexture {
+    inv_scale: f64,
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+}
+
+impl CheckerTexture {
+    pub fn new(scale: f64, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> Self {
+        CheckerTexture {
+            inv_scale: 1.0 / scale,
+            even,
+            odd,
+        }
+    }
+
+    pub fn with_colors(scale: f64, c1: Color, c2: Color) -> Self {
+        CheckerTexture::new(
+            scale,
+            Arc::new(SolidColor::new(c1)),
+            Arc::new(SolidColor::new(c2)),
+        )
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let x_integer = (self.inv_scale * p.x()).floor() as i64;
+        let y_integer = (self.inv_scale * p.y()).floor() as i64;
+        let z_integer = (self.inv_scale * p.z()).floor() as i64;
+
+        let is_even = (x_integer + y_integer + z_integer) % 2 == 0;
+
+        if is_even {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+// Image looked up by surface coordinates, with (0, 0) at the bottom left of the image
+pub struct ImageTexture {
+    image: Image,
+}
+
+impl ImageTexture {
+    pub fn new(image: Image) -> Self {
+        ImageTexture { image }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ImageError> {
+        Ok(ImageTexture::new(Image::load(path)?))
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: &Point3) -> Color {
+        // With no image data, return solid cyan as a debugging aid
+        if self.image.height() == 0 || self.image.width() == 0 {
+            return Color::with_values(0.0, 1.0, 1.0);
+        }
+
+        // Wrap the coordinates so tiled uvs repeat the image, then flip v to image coordinates
+        let u = Interval::with_values(0.0, 1.0).clamp(u - u.floor());
+        let v = 1.0 - Interval::with_values(0.0, 1.0).clamp(v - v.floor());
+
+        let i = ((u * self.image.width() as f64) as usize).min(self.image.width() - 1);
+        let j = ((v * self.image.height() as f64) as usize).min(self.image.height() - 1);
+
+        self.image.pixel(i, j)
+    }
+}
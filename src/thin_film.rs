@@ -0,0 +1,261 @@
+use std::{
+    f64::consts::PI,
+    ops::{Add, Div, Mul, Sub},
+    sync::Arc,
+};
+
+use crate::{
+    color::Color,
+    hittable::HitRecord,
+    ray::Ray,
+    spectrum::spectrum_to_rgb,
+    texture::{SolidColor, Texture},
+};
+
+// Transparent coating a fraction of a wavelength thick, such as the anti-reflective layer of a
+// lens or the wall of a soap bubble. Light reflected off its top and bottom interferes, which
+// colors the reflection depending on the thickness and the viewing angle.
+pub struct ThinFilm {
+    // Refractive index relative to the medium outside the coated object
+    ior: f64,
+    // Thickness in micrometers, the mean of the color channels
+    thickness: Arc<dyn Texture>,
+}
+
+impl ThinFilm {
+    pub fn new(ior: f64, thickness: f64) -> Self {
+        ThinFilm::with_texture(ior, Arc::new(SolidColor::gray(thickness)))
+    }
+
+    pub fn with_texture(ior: f64, thickness: Arc<dyn Texture>) -> Self {
+        ThinFilm { ior, thickness }
+    }
+
+    // Reflectance of the film over a dielectric, for light arriving at an angle with cosine
+    // `cos_theta_i` from the side with refractive index `incident_ior` towards the side with
+    // `substrate_ior` (both relative to the outside medium). It is given at the wavelengths
+    // `ray_in` carries, or in RGB when it carries none.
+    pub fn dielectric_reflectance(
+        &self,
+        ray_in: &Ray,
+        cos_theta_i: f64,
+        incident_ior: f64,
+        substrate_ior: f64,
+        rec: &HitRecord,
+    ) -> Color {
+        let substrate = Complex::new(substrate_ior, 0.0);
+        self.reflectance(ray_in, cos_theta_i, incident_ior, |_| substrate, rec)
+    }
+
+    // Reflectance of the film over a conductor with complex refractive index `eta` + i `k` given
+    // at red, green and blue wavelengths, for light arriving from outside
+    pub fn conductor_reflectance(
+        &self,
+        ray_in: &Ray,
+        cos_theta_i: f64,
+        eta: &Color,
+        k: &Color,
+        rec: &HitRecord,
+    ) -> Color {
+        let substrate =
+            |lambda: f64| Complex::new(rgb_interpolate(eta, lambda), rgb_interpolate(k, lambda));
+        self.reflectance(ray_in, cos_theta_i, 1.0, substrate, rec)
+    }
+
+    // Airy reflectance of the film, averaged over both polarizations. Spectral renders take it at
+    // the ray's wavelengths, where the fringes stay sharp, and others convert its spectrum to RGB.
+    fn reflectance(
+        &self,
+        ray_in: &Ray,
+        cos_theta_i: f64,
+        incident_ior: f64,
+        substrate: impl Fn(f64) -> Complex,
+        rec: &HitRecord,
+    ) -> Color {
+        let value = self.thickness.value(rec.u, rec.v, &rec.p);
+        let thickness = 1000.0 * ((value.x() + value.y() + value.z()) / 3.0).max(0.0);
+
+        let cos_theta_i = cos_theta_i.clamp(0.0, 1.0);
+        let n1 = Complex::new(incident_ior, 0.0);
+        let n2 = Complex::new(self.ior, 0.0);
+        // Squared sine of the angle in each layer times its squared index, which Snell's law
+        // keeps the same in all of them
+        let n_sin2 = incident_ior * incident_ior * (1.0 - cos_theta_i * cos_theta_i);
+
+        // Index times the cosine of the angle in each layer, complex past total internal
+        // reflection and in conductors
+        let q1 = Complex::new(incident_ior * cos_theta_i, 0.0);
+        let q2 = (n2 * n2 - Complex::new(n_sin2, 0.0)).sqrt();
+
+        let airy_reflectance = |lambda: f64| {
+            let n3 = substrate(lambda);
+            let q3 = (n3 * n3 - Complex::new(n_sin2, 0.0)).sqrt();
+
+            // Phase difference between the light reflected at the top and bottom of the film
+            let phase = (4.0 * PI * thickness / lambda) * q2;
+            let round_trip = (Complex::new(0.0, 1.0) * phase).exp();
+
+            let airy = |r12: Complex, r23: Complex| {
+                let r =
+                    (r12 + r23 * round_trip) / (Complex::new(1.0, 0.0) + r12 * r23 * round_trip);
+                r.norm_squared()
+            };
+            let s = airy(fresnel_s(q1, q2), fresnel_s(q2, q3));
+            let p = airy(fresnel_p(n1, q1, n2, q2), fresnel_p(n2, q2, n3, q3));
+
+            0.5 * (s + p)
+        };
+
+        if let Some(wavelengths) = ray_in.wavelengths() {
+            return wavelengths.evaluate(airy_reflectance);
+        }
+
+        let rgb = spectrum_to_rgb(airy_reflectance);
+        Color::with_values(
+            rgb.x().clamp(0.0, 1.0),
+            rgb.y().clamp(0.0, 1.0),
+            rgb.z().clamp(0.0, 1.0),
+        )
+    }
+}
+
+// Amplitude reflection coefficients of an interface between layers with indices `n` and index
+// times cosine `q`, for light polarized perpendicular and parallel to the plane of incidence
+fn fresnel_s(q_i: Complex, q_t: Complex) -> Complex {
+    (q_i - q_t) / (q_i + q_t)
+}
+
+fn fresnel_p(n_i: Complex, q_i: Complex, n_t: Complex, q_t: Complex) -> Complex {
+    let a = n_t * n_t * q_i;
+    let b = n_i * n_i * q_t;
+    (a - b) / (a + b)
+}
+
+// Value of a quantity given at red, green and blue wavelengths, interpolated linearly between
+// them and constant beyond
+fn rgb_interpolate(rgb: &Color, lambda: f64) -> f64 {
+    const BLUE: f64 = 450.0;
+    const GREEN: f64 = 550.0;
+    const RED: f64 = 650.0;
+
+    if lambda <= BLUE {
+        rgb.z()
+    } else if lambda <= GREEN {
+        rgb.z() + (rgb.y() - rgb.z()) * (lambda - BLUE) / (GREEN - BLUE)
+    } else if lambda <= RED {
+        rgb.y() + (rgb.x() - rgb.y()) * (lambda - GREEN) / (RED - GREEN)
+    } else {
+        rgb.x()
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    fn new(re: f64, im: f64) -> Complex {
+        Complex { re, im }
+    }
+
+    fn norm_squared(&self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+
+    fn exp(&self) -> Complex {
+        let magnitude = self.re.exp();
+        Complex::new(magnitude * self.im.cos(), magnitude * self.im.sin())
+    }
+
+    // Principal square root, with a non-negative real part
+    fn sqrt(&self) -> Complex {
+        let r = self.norm_squared().sqrt();
+        let re = (0.5 * (r + self.re)).max(0.0).sqrt();
+        let im = (0.5 * (r - self.re)).max(0.0).sqrt();
+        Complex::new(re, if self.im < 0.0 { -im } else { im })
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+
+    fn add(self, other: Complex) -> Complex {
+        Complex::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+
+    fn sub(self, other: Complex) -> Complex {
+        Complex::new(self.re - other.re, self.im - other.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, other: Complex) -> Complex {
+        Complex::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+}
+
+impl Mul<Complex> for f64 {
+    type Output = Complex;
+
+    fn mul(self, other: Complex) -> Complex {
+        Complex::new(self * other.re, self * other.im)
+    }
+}
+
+impl Div for Complex {
+    type Output = Complex;
+
+    fn div(self, other: Complex) -> Complex {
+        let denominator = other.norm_squared();
+        Complex::new(
+            (self.re * other.re + self.im * other.im) / denominator,
+            (self.im * other.re - self.re * other.im) / denominator,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{spectrum::Wavelengths, vec3::Vec3};
+
+    #[test]
+    fn spectral_rays_see_the_fringes_at_their_wavelengths() {
+        // Quarter-wave anti-reflective coating on glass, which cancels the reflection of the
+        // hero wavelength at normal incidence
+        let wavelengths = Wavelengths::sample(0.3);
+        let substrate_ior: f64 = 1.5;
+        let film_ior = substrate_ior.sqrt();
+        let thickness = wavelengths.hero() / (4.0 * film_ior) / 1000.0;
+        let film = ThinFilm::new(film_ior, thickness);
+
+        let mut ray = Ray::with_values(Vec3::new(), Vec3::with_values(0.0, 0.0, -1.0));
+        ray.set_wavelengths(Some(wavelengths));
+        let rec = HitRecord::new();
+
+        let spectral = film.dielectric_reflectance(&ray, 1.0, 1.0, substrate_ior, &rec);
+        assert!(spectral.x() < 1e-12, "hero reflectance {}", spectral.x());
+        assert!(spectral.y() > 1e-3 && spectral.z() > 1e-3);
+
+        // Averaged over the visible range in RGB, the other wavelengths still reflect
+        let rgb = film.dielectric_reflectance(
+            &Ray::with_values(Vec3::new(), Vec3::with_values(0.0, 0.0, -1.0)),
+            1.0,
+            1.0,
+            substrate_ior,
+            &rec,
+        );
+        assert!(rgb.x() > 1e-3 || rgb.y() > 1e-3 || rgb.z() > 1e-3);
+    }
+}
@@ -0,0 +1,381 @@
+use std::{f64::consts::PI, sync::Arc};
+
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable},
+    interval::Interval,
+    material::Material,
+    onb::Onb,
+    ray::Ray,
+    vec3::{Point3, Vec3},
+};
+
+// Ring around `center` whose tube of radius `minor_radius` follows a circle of radius
+// `major_radius` facing along `axis`. Intersections happen in a local frame where the axis is +z.
+pub struct Torus {
+    center: Point3,
+    major_radius: f64,
+    minor_radius: f64,
+    frame: Onb,
+    mat: Arc<dyn Material>,
+    bbox: Aabb,
+}
+
+impl Torus {
+    pub fn new(
+        center: Point3,
+        axis: Vec3,
+        major_radius: f64,
+        minor_radius: f64,
+        mat: Arc<dyn Material>,
+    ) -> Torus {
+        assert!(!axis.near_zero(), "Torus axis must not be zero");
+
+        let major_radius = f64::max(0.0, major_radius);
+        let minor_radius = f64::max(0.0, minor_radius);
+        let frame = Onb::new(&axis);
+        let n = frame.w();
+
+        // The core circle's extent along each axis, grown by the tube
+        let extent = Vec3::with_values(
+            major_radius * (1.0 - n.x() * n.x()).max(0.0).sqrt() + minor_radius,
+            major_radius * (1.0 - n.y() * n.y()).max(0.0).sqrt() + minor_radius,
+            major_radius * (1.0 - n.z() * n.z()).max(0.0).sqrt() + minor_radius,
+        );
+
+        Torus {
+            center,
+            major_radius,
+            minor_radius,
+            frame,
+            mat,
+            bbox: Aabb::from_points(center - extent, center + extent),
+        }
+    }
+}
+
+impl Hittable for Torus {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        // Work with a unit direction starting where the ray enters the bounding sphere, so the
+        // quartic's coefficients stay well scaled no matter how far away the ray starts
+        let direction_length = r.direction().length();
+        let d = self.frame.inverse_transform(&r.direction()) / direction_length;
+        let o = self.frame.inverse_transform(&(r.origin() - self.center));
+
+        // Slightly larger than the torus, which touches its tightest bounding sphere
+        let bound = 1.01 * (self.major_radius + self.minor_radius);
+        let b = o.dot(&d);
+        let discriminant = b * b - (o.length_squared() - bound * bound);
+        if discriminant < 0.0 {
+            return false;
+        }
+
+        let sqrtd = discriminant.sqrt();
+        let s_min = (ray_t.min * direction_length).max(-b - sqrtd);
+        let s_max = (ray_t.max * direction_length).min(-b + sqrtd);
+        if s_min >= s_max {
+            return false;
+        }
+
+        let start = o + s_min * d;
+
+        // (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + y^2) along p = start + s d
+        let r2 = self.major_radius * self.major_radius;
+        let e = start.length_squared() + r2 - self.minor_radius * self.minor_radius;
+        let f = start.dot(&d);
+        let dxy = d.x() * d.x() + d.y() * d.y();
+        let oxy = start.x() * d.x() + start.y() * d.y();
+        let sxy = start.x() * start.x() + start.y() * start.y();
+        let coefficients = [
+            e * e - 4.0 * r2 * sxy,
+            4.0 * f * e - 8.0 * r2 * oxy,
+            4.0 * f * f + 2.0 * e - 4.0 * r2 * dxy,
+            4.0 * f,
+            1.0,
+        ];
+
+        let root = match smallest_root(&coefficients, 0.0, s_max - s_min) {
+            Some(root) => root,
+            None => return false,
+        };
+
+        let s = s_min + root;
+        if s <= ray_t.min * direction_length {
+            return false;
+        }
+
+        let p = o + s * d;
+        let rho = (p.x() * p.x() + p.y() * p.y()).sqrt();
+        let core = if rho > 0.0 {
+            Vec3::with_values(p.x(), p.y(), 0.0) * (self.major_radius / rho)
+        } else {
+            Vec3::new()
+        };
+        let outward_normal = (p - core).unit_vector();
+
+        // u: angle around the axis, v: angle around the tube starting on the outer side
+        let phi = p.y().atan2(p.x());
+        let theta = p.z().atan2(rho - self.major_radius);
+        rec.u = if phi < 0.0 { phi + 2.0 * PI } else { phi } / (2.0 * PI);
+        rec.v = if theta < 0.0 { theta + 2.0 * PI } else { theta } / (2.0 * PI);
+        rec.dpdu = self
+            .frame
+            .transform(&(2.0 * PI * Vec3::with_values(-p.y(), p.x(), 0.0)));
+        rec.dpdv = self.frame.transform(
+            &(2.0
+                * PI
+                * Vec3::with_values(
+                    -p.z() * phi.cos(),
+                    -p.z() * phi.sin(),
+                    rho - self.major_radius,
+                )),
+        );
+
+        rec.t = s / direction_length;
+        rec.p = r.at(rec.t);
+        rec.set_face_normal(r, &self.frame.transform(&outward_normal));
+        rec.mat = self.mat.clone();
+
+        return true;
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+fn evaluate(coefficients: &[f64], x: f64) -> f64 {
+    coefficients.iter().rev().fold(0.0, |sum, c| sum * x + c)
+}
+
+// Smallest root in (lo, hi] of the polynomial with the given coefficients, lowest degree first.
+// The roots of the derivative split the range into pieces where the polynomial is monotonic, so
+// every sign change is bracketed and found by bisection; this stays robust for the nearly double
+// roots of grazing rays where closed-form quartic solutions lose all precision.
+fn smallest_root(coefficients: &[f64], lo: f64, hi: f64) -> Option<f64> {
+    let mut bounds = [0.0; MAX_DEGREE + 1];
+    let splits = monotonic_splits(coefficients, lo, hi, &mut bounds[1..]);
+    bounds[0] = lo;
+    bounds[splits + 1] = hi;
+
+    bounds[..splits + 2]
+        .windows(2)
+        .find_map(|pair| bracketed_root(coefficients, pair[0], pair[1]))
+}
+
+// Highest polynomial degree the root finder handles, which keeps its buffers on the stack
+const MAX_DEGREE: usize = 4;
+
+// Writes the roots of the derivative inside (lo, hi) into `splits` in increasing order and
+// returns how many there are
+fn monotonic_splits(coefficients: &[f64], lo: f64, hi: f64, splits: &mut [f64]) -> usize {
+    debug_assert!(coefficients.len() <= MAX_DEGREE + 1);
+    let degree = coefficients.len().saturating_sub(1);
+    if degree < 2 {
+        return 0;
+    }
+
+    let mut derivative = [0.0; MAX_DEGREE];
+    for power in 1..=degree {
+        derivative[power - 1] = power as f64 * coefficients[power];
+    }
+    let derivative = &derivative[..degree];
+
+    let mut bounds = [0.0; MAX_DEGREE + 1];
+    let inner = monotonic_splits(derivative, lo, hi, &mut bounds[1..]);
+    bounds[0] = lo;
+    bounds[inner + 1] = hi;
+
+    let mut count = 0;
+    for pair in bounds[..inner + 2].windows(2) {
+        if let Some(root) = bracketed_root(derivative, pair[0], pair[1]) {
+            if lo < root && root < hi {
+                splits[count] = root;
+                count += 1;
+            }
+        }
+    }
+
+    count
+}
+
+// Root in (lo, hi] of a polynomial that is monotonic there, if it changes sign
+fn bracketed_root(coefficients: &[f64], mut lo: f64, mut hi: f64) -> Option<f64> {
+    let mut f_lo = evaluate(coefficients, lo);
+    let f_hi = evaluate(coefficients, hi);
+
+    if f_hi == 0.0 {
+        return Some(hi);
+    }
+    if f_lo == 0.0 || f_lo.signum() == f_hi.signum() {
+        return None;
+    }
+
+    for _ in 0..64 {
+        let mid = 0.5 * (lo + hi);
+        if mid <= lo || mid >= hi {
+            break;
+        }
+
+        let f_mid = evaluate(coefficients, mid);
+        if f_mid.signum() == f_lo.signum() {
+            lo = mid;
+            f_lo = f_mid;
+        } else {
+            hi = mid;
+        }
+    }
+
+    Some(hi)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        color::Color,
+        hittable::testing::{assert_near, hit},
+        material::Lambertian,
+    };
+
+    // Ring of radius 2 around the z axis with a tube of radius 0.5
+    fn torus() -> Torus {
+        Torus::new(
+            Point3::with_values(0.0, 0.0, 0.0),
+            Vec3::with_values(0.0, 0.0, 1.0),
+            2.0,
+            0.5,
+            Arc::new(Lambertian::new(Color::with_values(0.5, 0.5, 0.5))),
+        )
+    }
+
+    #[test]
+    fn ray_along_the_axis_hits_the_top_of_the_tube() {
+        let rec = hit(
+            &torus(),
+            Point3::with_values(2.0, 0.0, 5.0),
+            Vec3::with_values(0.0, 0.0, -1.0),
+        )
+        .unwrap();
+
+        assert!((rec.t - 4.5).abs() < 1e-9);
+        assert_near(rec.normal, Vec3::with_values(0.0, 0.0, 1.0));
+        assert!(rec.front_face);
+        assert!((rec.u - 0.5).abs() < 1e-9);
+        assert!((rec.v - 0.25).abs() < 1e-9);
+
+        // Through the hole
+        let hole = hit(
+            &torus(),
+            Point3::with_values(0.0, 0.0, 5.0),
+            Vec3::with_values(0.0, 0.0, -1.0),
+        );
+        assert!(hole.is_none());
+    }
+
+    #[test]
+    fn rays_through_the_ring_pick_the_nearest_of_four_roots() {
+        // The line crosses the tube at x = -2.5, -1.5, 1.5 and 2.5
+        let outside = hit(
+            &torus(),
+            Point3::with_values(-5.0, 0.0, 0.0),
+            Vec3::with_values(2.0, 0.0, 0.0),
+        )
+        .unwrap();
+        assert!((outside.t - 1.25).abs() < 1e-9);
+        assert_near(outside.normal, Vec3::with_values(-1.0, 0.0, 0.0));
+        assert!(outside.front_face);
+
+        // From the center of the hole, the inner side of the tube comes first
+        let center = hit(
+            &torus(),
+            Point3::with_values(0.0, 0.0, 0.0),
+            Vec3::with_values(1.0, 0.0, 0.0),
+        )
+        .unwrap();
+        assert!((center.t - 1.5).abs() < 1e-9);
+        assert_near(center.normal, Vec3::with_values(-1.0, 0.0, 0.0));
+        assert!(center.front_face);
+        assert!((center.v - 0.5).abs() < 1e-9);
+
+        // From inside the tube, the way out of it
+        let inside = hit(
+            &torus(),
+            Point3::with_values(-2.0, 0.0, 0.0),
+            Vec3::with_values(1.0, 0.0, 0.0),
+        )
+        .unwrap();
+        assert!((inside.t - 0.5).abs() < 1e-9);
+        assert_near(inside.normal, Vec3::with_values(-1.0, 0.0, 0.0));
+        assert!(!inside.front_face);
+    }
+
+    #[test]
+    fn rays_skimming_the_hole_separate_nearly_double_roots() {
+        // Leaves the tube just before the hole and comes back in just after it, with the middle
+        // two roots only 0.11 apart
+        let x: f64 = 1.499;
+        let gap = (1.5 * 1.5 - x * x).sqrt();
+        let leaving = hit(
+            &torus(),
+            Point3::with_values(x, -1.0, 0.0),
+            Vec3::with_values(0.0, 1.0, 0.0),
+        )
+        .unwrap();
+        assert!((leaving.t - (1.0 - gap)).abs() < 1e-9);
+        assert!(!leaving.front_face);
+
+        // Just outside the hole the ray stays in the tube across the axis
+        let x: f64 = 1.501;
+        let staying = hit(
+            &torus(),
+            Point3::with_values(x, -1.0, 0.0),
+            Vec3::with_values(0.0, 1.0, 0.0),
+        )
+        .unwrap();
+        assert!((staying.t - (1.0 + (2.5 * 2.5 - x * x).sqrt())).abs() < 1e-9);
+        assert!(!staying.front_face);
+    }
+
+    #[test]
+    fn grazing_rays_hit_just_below_the_top() {
+        let z = 0.499;
+        let inside = hit(
+            &torus(),
+            Point3::with_values(2.0, 5.0, z),
+            Vec3::with_values(0.0, -1.0, 0.0),
+        )
+        .unwrap();
+        let rho = 2.0 + (0.25 - z * z).sqrt();
+        let y = (rho * rho - 4.0).sqrt();
+        assert!((inside.t - (5.0 - y)).abs() < 1e-9);
+        assert!(inside.front_face);
+        assert!(inside.normal.z() > 0.99);
+
+        let outside = hit(
+            &torus(),
+            Point3::with_values(2.0, 5.0, 0.501),
+            Vec3::with_values(0.0, -1.0, 0.0),
+        );
+        assert!(outside.is_none());
+    }
+
+    #[test]
+    fn bounding_box_of_a_tilted_ring() {
+        // Axis halfway between x and y, so the ring spans the whole major radius only along z
+        let s = 0.5_f64.sqrt();
+        let torus = Torus::new(
+            Point3::with_values(1.0, 0.0, 0.0),
+            Vec3::with_values(1.0, 1.0, 0.0),
+            2.0,
+            0.5,
+            Arc::new(Lambertian::new(Color::with_values(0.5, 0.5, 0.5))),
+        );
+        let bbox = torus.bounding_box();
+
+        let xy = 2.0 * s + 0.5;
+        assert!((bbox.x.min - (1.0 - xy)).abs() < 1e-9 && (bbox.x.max - (1.0 + xy)).abs() < 1e-9);
+        assert!((bbox.y.min + xy).abs() < 1e-9 && (bbox.y.max - xy).abs() < 1e-9);
+        assert!((bbox.z.min + 2.5).abs() < 1e-9 && (bbox.z.max - 2.5).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,220 @@
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable},
+    interval::Interval,
+    mat4::Mat4,
+    ray::Ray,
+    sampler::Sampler,
+    vec3::{Point3, Vec3},
+};
+
+// Places an object in the world through an affine transform. Rays are moved into the object's
+// space, and the hit point and normal are moved back out.
+pub struct Transform {
+    object: Box<dyn Hittable>,
+    to_world: Mat4,
+    to_object: Mat4,
+    // Normals transform with the inverse transpose
+    normal_to_world: Mat4,
+    bbox: Aabb,
+}
+
+impl Transform {
+    // Panics if the matrix can't be inverted, e.g. when it scales an axis by zero
+    pub fn new(object: Box<dyn Hittable>, matrix: Mat4) -> Transform {
+        let to_object = matrix
+            .inverse()
+            .expect("transform matrix must be invertible");
+        let bbox = Transform::transform_box(&object.bounding_box(), &matrix);
+
+        Transform {
+            object,
+            to_world: matrix,
+            to_object,
+            normal_to_world: to_object.transpose(),
+            bbox,
+        }
+    }
+
+    pub fn translate(object: Box<dyn Hittable>, offset: Vec3) -> Transform {
+        Transform::new(object, Mat4::translation(&offset))
+    }
+
+    // Rotates about an axis through the origin
+    pub fn rotate(object: Box<dyn Hittable>, axis: Vec3, degrees: f64) -> Transform {
+        Transform::new(object, Mat4::rotation(&axis, degrees))
+    }
+
+    pub fn scale(object: Box<dyn Hittable>, factors: Vec3) -> Transform {
+        Transform::new(object, Mat4::scaling(&factors))
+    }
+
+    // Box around the eight transformed corners of `bbox`
+    fn transform_box(bbox: &Aabb, matrix: &Mat4) -> Aabb {
+        if bbox.is_empty() {
+            return Aabb::EMPTY;
+        }
+
+        let mut result = Aabb::EMPTY;
+        for i in 0..8 {
+            let corner = Point3::with_values(
+                if i & 1 == 0 { bbox.x.min } else { bbox.x.max },
+                if i & 2 == 0 { bbox.y.min } else { bbox.y.max },
+                if i & 4 == 0 { bbox.z.min } else { bbox.z.max },
+            );
+            let p = matrix.transform_point(&corner);
+            result = Aabb::surrounding(&result, &Aabb::from_points(p, p));
+        }
+
+        result
+    }
+}
+
+impl Hittable for Transform {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        // The direction is not normalized, so distances along the ray stay the same in both spaces
+        let object_r = Ray::with_time(
+            self.to_object.transform_point(&r.origin()),
+            self.to_object.transform_vector(&r.direction()),
+            r.time(),
+        );
+
+        if !self.object.hit(&object_r, ray_t, rec) {
+            return false;
+        }
+
+        rec.p = self.to_world.transform_point(&rec.p);
+        rec.normal = self
+            .normal_to_world
+            .transform_vector(&rec.normal)
+            .unit_vector();
+        rec.geometric_normal = self
+            .normal_to_world
+            .transform_vector(&rec.geometric_normal)
+            .unit_vector();
+        rec.dpdu = self.to_world.transform_vector(&rec.dpdu);
+        rec.dpdv = self.to_world.transform_vector(&rec.dpdv);
+
+        return true;
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    // The object's density is per solid angle of its own space. Mapping a unit direction w to
+    // A w / |A w|, with A the linear part of `to_object`, scales solid angles by |det A| / |A w|^3.
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let object_origin = self.to_object.transform_point(origin);
+        let object_direction = self.to_object.transform_vector(&direction.unit_vector());
+
+        let pdf = self.object.pdf_value(&object_origin, &object_direction);
+        if pdf <= 0.0 {
+            return 0.0;
+        }
+
+        pdf * self.to_object.determinant3().abs() / object_direction.length().powi(3)
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut Sampler) -> Vec3 {
+        let object_origin = self.to_object.transform_point(origin);
+        let direction = self.object.random(&object_origin, sampler);
+
+        self.to_world.transform_vector(&direction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        color::Color,
+        hittable::testing::{assert_near, hit},
+        material::{Lambertian, Material},
+        quad::make_box,
+        sphere::Sphere,
+    };
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Color::with_values(0.5, 0.5, 0.5)))
+    }
+
+    #[test]
+    fn normals_stay_perpendicular_under_non_uniform_scale() {
+        // The unit sphere stretched into the ellipsoid x^2 / 4 + y^2 + z^2 / 9 = 1
+        let sphere = Box::new(Sphere::new(Point3::new(), 1.0, material()));
+        let ellipsoid = Transform::scale(sphere, Vec3::with_values(2.0, 1.0, 3.0));
+
+        let origin = Point3::with_values(4.0, 3.0, 5.0);
+        let rec = hit(&ellipsoid, origin, Point3::new() - origin).unwrap();
+        let p = rec.p;
+        let on_surface = p.x() * p.x() / 4.0 + p.y() * p.y() + p.z() * p.z() / 9.0;
+        assert!((on_surface - 1.0).abs() < 1e-9, "{}", p);
+
+        // Gradient of the implicit surface
+        let expected = Vec3::with_values(p.x() / 4.0, p.y(), p.z() / 9.0).unit_vector();
+        assert_near(rec.normal, expected);
+        assert_near(rec.geometric_normal, expected);
+        assert!(rec.front_face);
+        // Moving the object space normal p / |p| like a direction would point it along p instead
+        let naive = p.unit_vector();
+        assert!((naive - expected).length() > 0.1);
+    }
+
+    #[test]
+    fn hits_are_moved_back_into_the_world() {
+        let sphere = Box::new(Sphere::new(Point3::new(), 1.0, material()));
+        let matrix = Mat4::translation(&Vec3::with_values(0.0, 0.0, -5.0))
+            * Mat4::rotation(&Vec3::with_values(0.0, 1.0, 0.0), 90.0)
+            * Mat4::scaling(&Vec3::with_values(1.0, 1.0, 2.0));
+        let moved = Transform::new(sphere, matrix);
+
+        // The long axis now points along x
+        let rec = hit(&moved, Point3::new(), Vec3::with_values(0.0, 0.0, -1.0)).unwrap();
+        assert!((rec.t - 4.0).abs() < 1e-9, "t = {}", rec.t);
+        assert_near(rec.normal, Vec3::with_values(0.0, 0.0, 1.0));
+        let rec = hit(
+            &moved,
+            Point3::with_values(-5.0, 0.0, -5.0),
+            Vec3::with_values(1.0, 0.0, 0.0),
+        )
+        .unwrap();
+        assert!((rec.t - 3.0).abs() < 1e-9, "t = {}", rec.t);
+    }
+
+    #[test]
+    fn bounding_box_covers_the_rotated_corners() {
+        let cube = Box::new(make_box(
+            Point3::new(),
+            Point3::with_values(1.0, 1.0, 1.0),
+            material(),
+        ));
+        let rotated = Transform::rotate(cube, Vec3::with_values(0.0, 1.0, 0.0), 45.0);
+        let bbox = rotated.bounding_box();
+
+        // Counter-clockwise about y, the corner at x = 1 swings towards -z
+        let half_diagonal = 0.5_f64.sqrt();
+        let expected = [
+            (bbox.x, 0.0, 2.0 * half_diagonal),
+            (bbox.y, 0.0, 1.0),
+            (bbox.z, -half_diagonal, half_diagonal),
+        ];
+        for (interval, min, max) in expected {
+            // Quads pad their boxes slightly
+            assert!(
+                (interval.min - min).abs() < 1e-3,
+                "{} != {}",
+                interval.min,
+                min
+            );
+            assert!(
+                (interval.max - max).abs() < 1e-3,
+                "{} != {}",
+                interval.max,
+                max
+            );
+        }
+    }
+}
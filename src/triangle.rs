@@ -0,0 +1,139 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable},
+    interval::Interval,
+    material::Material,
+    ray::Ray,
+    vec3::{Point3, Vec3},
+};
+
+pub struct TriangleHit {
+    pub t: f64,
+    // Barycentric weights of the three vertices
+    pub b0: f64,
+    pub b1: f64,
+    pub b2: f64,
+}
+
+// Watertight ray/triangle intersection (Woop, Benthin and Wald 2013). The triangle is moved into
+// a ray-aligned space where the ray runs along +z from the origin, so rays through shared edges
+// and vertices of neighbouring triangles always hit exactly one of them.
+pub fn intersect_triangle(
+    r: &Ray,
+    ray_t: Interval,
+    p0: &Point3,
+    p1: &Point3,
+    p2: &Point3,
+) -> Option<TriangleHit> {
+    let dir = r.direction();
+
+    let mut kz = 0;
+    if dir[1].abs() > dir[kz].abs() {
+        kz = 1;
+    }
+    if dir[2].abs() > dir[kz].abs() {
+        kz = 2;
+    }
+    if dir[kz] == 0.0 {
+        return None;
+    }
+
+    let mut kx = (kz + 1) % 3;
+    let mut ky = (kx + 1) % 3;
+    if dir[kz] < 0.0 {
+        std::mem::swap(&mut kx, &mut ky);
+    }
+
+    let sx = dir[kx] / dir[kz];
+    let sy = dir[ky] / dir[kz];
+    let sz = 1.0 / dir[kz];
+
+    let a = *p0 - r.origin();
+    let b = *p1 - r.origin();
+    let c = *p2 - r.origin();
+
+    let ax = a[kx] - sx * a[kz];
+    let ay = a[ky] - sy * a[kz];
+    let bx = b[kx] - sx * b[kz];
+    let by = b[ky] - sy * b[kz];
+    let cx = c[kx] - sx * c[kz];
+    let cy = c[ky] - sy * c[kz];
+
+    let u = cx * by - cy * bx;
+    let v = ax * cy - ay * cx;
+    let w = bx * ay - by * ax;
+
+    if (u < 0.0 || v < 0.0 || w < 0.0) && (u > 0.0 || v > 0.0 || w > 0.0) {
+        return None;
+    }
+
+    let det = u + v + w;
+    if det == 0.0 {
+        return None;
+    }
+
+    let t_scaled = u * sz * a[kz] + v * sz * b[kz] + w * sz * c[kz];
+    let t = t_scaled / det;
+
+    if !ray_t.surrounds(t) {
+        return None;
+    }
+
+    Some(TriangleHit {
+        t,
+        b0: u / det,
+        b1: v / det,
+        b2: w / det,
+    })
+}
+
+pub struct Triangle {
+    p0: Point3,
+    p1: Point3,
+    p2: Point3,
+    normal: Vec3,
+    mat: Arc<dyn Material>,
+    bbox: Aabb,
+}
+
+impl Triangle {
+    pub fn new(p0: Point3, p1: Point3, p2: Point3, mat: Arc<dyn Material>) -> Self {
+        let normal = (p1 - p0).cross(&(p2 - p0)).unit_vector();
+        let bbox = Aabb::surrounding(&Aabb::from_points(p0, p1), &Aabb::from_points(p0, p2));
+
+        Triangle {
+            p0,
+            p1,
+            p2,
+            normal,
+            mat,
+            bbox,
+        }
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let hit = match intersect_triangle(r, ray_t, &self.p0, &self.p1, &self.p2) {
+            Some(hit) => hit,
+            None => return false,
+        };
+
+        rec.t = hit.t;
+        rec.p = r.at(hit.t);
+        rec.u = hit.b1;
+        rec.v = hit.b2;
+        rec.dpdu = self.p1 - self.p0;
+        rec.dpdv = self.p2 - self.p0;
+        rec.set_face_normal(r, &self.normal);
+        rec.mat = self.mat.clone();
+
+        return true;
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
@@ -3,7 +3,7 @@ use std::{
     ops::{Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Sub},
 };
 
-use crate::utils::{random_between, random_f64};
+use crate::sampler::Sampler;
 
 #[derive(Clone, Copy)]
 pub struct Vec3 {
@@ -21,20 +21,24 @@ impl Vec3 {
         Vec3 { e: [e0, e1, e2] }
     }
 
-    pub fn random() -> Vec3 {
-        Vec3::with_values(random_f64(), random_f64(), random_f64())
+    pub fn random(sampler: &mut Sampler) -> Vec3 {
+        Vec3::with_values(
+            sampler.random_f64(),
+            sampler.random_f64(),
+            sampler.random_f64(),
+        )
     }
 
-    pub fn random_min_max(min: f64, max: f64) -> Vec3 {
+    pub fn random_min_max(min: f64, max: f64, sampler: &mut Sampler) -> Vec3 {
         Vec3::with_values(
-            random_between(min, max),
-            random_between(min, max),
-            random_between(min, max),
+            sampler.random_between(min, max),
+            sampler.random_between(min, max),
+            sampler.random_between(min, max),
         )
     }
 
-    pub fn random_on_hemisphere(normal: &Vec3) -> Vec3 {
-        let in_unit_sphere = Vec3::random_unit_vector();
+    pub fn random_on_hemisphere(normal: &Vec3, sampler: &mut Sampler) -> Vec3 {
+        let in_unit_sphere = Vec3::random_unit_vector(sampler);
 
         if in_unit_sphere.dot(normal) > 0.0 {
             return in_unit_sphere;
@@ -92,13 +96,13 @@ impl Vec3 {
         return self[0].abs() < s && self[1].abs() < s && self[2].abs() < s;
     }
 
-    pub fn random_unit_vector() -> Vec3 {
+    pub fn random_unit_vector(sampler: &mut Sampler) -> Vec3 {
         loop {
-            let v = Vec3::random_min_max(-1.0, 1.0);
+            let v = Vec3::random_min_max(-1.0, 1.0, sampler);
             let lensq = v.length_squared();
 
             if 1e-160 < lensq && lensq <= 1.0 {
-                return v / lensq;
+                return v / lensq.sqrt();
             }
         }
     }
@@ -107,9 +111,13 @@ impl Vec3 {
         return vec - 2.0 * vec.dot(normal) * *normal;
     }
 
-    pub fn random_in_unit_disk() -> Vec3 {
+    pub fn random_in_unit_disk(sampler: &mut Sampler) -> Vec3 {
         loop {
-            let p = Vec3::with_values(random_between(-1.0, 1.0), random_between(-1.0, 1.0), 0.0);
+            let p = Vec3::with_values(
+                sampler.random_between(-1.0, 1.0),
+                sampler.random_between(-1.0, 1.0),
+                0.0,
+            );
 
             if p.length_squared() < 1.0 {
                 return p;
@@ -202,6 +210,14 @@ impl Mul<f64> for Vec3 {
     }
 }
 
+impl Div for Vec3 {
+    type Output = Vec3;
+
+    fn div(self, rhs: Self) -> Self::Output {
+        Vec3::with_values(self[0] / rhs[0], self[1] / rhs[1], self[2] / rhs[2])
+    }
+}
+
 impl Div<f64> for Vec3 {
     type Output = Vec3;
 